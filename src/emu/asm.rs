@@ -0,0 +1,848 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::{ error, fmt, fs };
+use std::path::Path;
+use super::emulator;
+use super::instruction::Instruction;
+
+// Two pass assembler for the mnemonics the disassembler prints (Cowgod's syntax plus the
+// SUPER-CHIP and XO-CHIP additions). A line holds any of:
+//
+//     label:                     address of the next byte
+//     NAME = expr                constant, may refer to labels defined later
+//     LD V0, NAME + 1            instruction
+//     DB 1, 0x2, "text"          bytes; DW emits big endian words
+//     ORG 0x300                  continue at a later address, padding with zeros
+//     INCLUDE "file.asm"         assemble another file in place
+//     6A 02                      raw hex, as the original .ch8.src files are written
+//     0200: 6A 02                raw hex at a checked address
+//
+// Comments start with `;`, `#` or `//`. Numbers are decimal, 0x hex or 0b binary, and
+// expressions take + - * / % & | ^ << >> ~ and parentheses.
+
+// includes nested deeper than this are assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 16;
+// constants referring to constants deeper than this are assumed to be circular
+const MAX_EVAL_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String
+}
+
+impl AsmError {
+    pub fn new(file: &str, line: usize, message: String) -> AsmError {
+        AsmError {
+            file: file.to_string(),
+            line,
+            message
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+// where the bytes of a source line ended up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: usize,
+    pub addr: u16
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    // address of the first byte, where Emulator::load_rom puts it
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    pub lines: Vec<SourceLine>
+}
+
+impl Program {
+    // one `0xADDR name` line per label, in address order
+    pub fn symbol_map(&self) -> String {
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|&(name, addr)| (*addr, name.clone()));
+
+        labels.into_iter()
+            .map(|(name, addr)| format!("0x{:04X} {}\n", addr, name))
+            .collect()
+    }
+
+    // address of the first byte generated by `file` at `line`, if it generated any
+    pub fn address_of(&self, file: &str, line: usize) -> Option<u16> {
+        self.lines.iter()
+            .find(|l| l.file == file && l.line == line)
+            .map(|l| l.addr)
+    }
+
+    // the source line that generated the byte at `addr`
+    pub fn line_at(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.iter().find(|l| l.addr == addr)
+    }
+
+    // one `0xADDR file:line` line per source line that generated bytes, in source order
+    pub fn line_map(&self) -> String {
+        self.lines.iter()
+            .map(|l| format!("0x{:04X} {}:{}\n", l.addr, l.file, l.line))
+            .collect()
+    }
+
+    // Labels and source lines read back from symbol_map and line_map output, without the
+    // bytes. Anything else in `map` is skipped.
+    pub fn from_symbol_map(map: &str) -> Program {
+        let mut labels = BTreeMap::new();
+        let mut lines = Vec::new();
+
+        for entry in map.lines() {
+            let (addr, name) = match entry.trim().split_once(' ') {
+                Some((addr, name)) => (addr.trim_start_matches("0x"), name.trim()),
+                None => continue
+            };
+            let addr = match u16::from_str_radix(addr, 16) {
+                Ok(addr) => addr,
+                Err(_) => continue
+            };
+
+            match name.rsplit_once(':').map(|(file, line)| (file, line.parse::<usize>())) {
+                Some((file, Ok(line))) => lines.push(SourceLine { file: file.to_string(), line, addr }),
+                _ => {
+                    labels.insert(name.to_string(), addr);
+                }
+            }
+        }
+
+        Program {
+            origin: emulator::PRG_OFFSET as u16,
+            bytes: Vec::new(),
+            labels,
+            lines
+        }
+    }
+}
+
+// Assemble source that doesn't include other files
+pub fn assemble(name: &str, source: &str) -> Result<Program, AsmError> {
+    assemble_with(name, source, |_, path| Err(format!("can't include {} here", path)))
+}
+
+// Assemble a file from disk, resolving includes relative to the file including them
+pub fn assemble_file(path: &str) -> Result<Program, AsmError> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| AsmError::new(path, 0, format!("couldn't open {}: {}", path, e)))?;
+
+    assemble_with(path, &source, |from, include| {
+        let resolved = Path::new(from).with_file_name(include);
+        let name = resolved.to_string_lossy().into_owned();
+        fs::read_to_string(&resolved)
+            .map(|source| (name.clone(), source))
+            .map_err(|e| format!("couldn't open {}: {}", name, e))
+    })
+}
+
+// `load` is called with the including file and the path it asked for, and returns the
+// name to report the included file by and its source.
+pub fn assemble_with<F>(name: &str, source: &str, mut load: F) -> Result<Program, AsmError>
+    where F: FnMut(&str, &str) -> Result<(String, String), String> {
+    let mut asm = Assembler::new();
+    asm.parse(name, source, &mut load, 0)?;
+    asm.emit()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Num(i64),
+    Symbol(String),
+    Unary(char, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    // LONG expr, the F000 NNNN address
+    Long(Expr),
+    // Vx - Vy
+    Range(u8, u8),
+    Value(Expr)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Datum {
+    Value(Expr),
+    Str(Vec<u8>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Item {
+    Instruction(String, Vec<Operand>),
+    // element width in bytes and the elements
+    Data(usize, Vec<Datum>),
+    Raw(Vec<u8>)
+}
+
+enum Symbol {
+    Label(u16),
+    Const(Expr, String, usize)
+}
+
+struct Statement {
+    file: String,
+    line: usize,
+    addr: usize,
+    item: Item
+}
+
+struct Assembler {
+    addr: usize,
+    symbols: HashMap<String, Symbol>,
+    // constant names in the order they were defined
+    constants: Vec<String>,
+    statements: Vec<Statement>
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            addr: emulator::PRG_OFFSET,
+            symbols: HashMap::new(),
+            constants: Vec::new(),
+            statements: Vec::new()
+        }
+    }
+
+    // first pass: split lines into statements, assigning addresses and collecting symbols
+    fn parse<F>(&mut self, file: &str, source: &str, load: &mut F, depth: usize) -> Result<(), AsmError>
+        where F: FnMut(&str, &str) -> Result<(String, String), String> {
+        for (index, raw) in source.lines().enumerate() {
+            let line = index + 1;
+            let err = |message: String| AsmError::new(file, line, message);
+            let mut text = strip_comment(raw).trim();
+
+            // labels, or the address column of a raw listing
+            if let Some((head, rest)) = split_label(text) {
+                if head.starts_with(|c: char| c.is_ascii_digit()) {
+                    let addr = usize::from_str_radix(head, 16)
+                        .map_err(|_| err(format!("bad address {}", head)))?;
+                    if addr != self.addr {
+                        return Err(err(format!("address 0x{:03X} doesn't match 0x{:03X}", addr, self.addr)));
+                    }
+                } else {
+                    self.define(head, Symbol::Label(self.addr as u16)).map_err(err)?;
+                }
+                text = rest.trim();
+            }
+
+            if text.is_empty() {
+                continue;
+            }
+
+            let (word, rest) = split_word(text);
+            let keyword = word.to_ascii_uppercase();
+
+            if rest.starts_with('=') && is_ident(word) {
+                let expr = parse_expr(rest[1 ..].trim()).map_err(err)?;
+                self.define(word, Symbol::Const(expr, file.to_string(), line)).map_err(err)?;
+                continue;
+            }
+
+            let item = match keyword.as_str() {
+                "INCLUDE" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(err("includes nested too deeply".to_string()));
+                    }
+
+                    let path = parse_string(rest).map_err(err)?;
+                    let path = String::from_utf8_lossy(&path).into_owned();
+                    let (name, included) = load(file, &path).map_err(err)?;
+                    self.parse(&name, &included, load, depth + 1)?;
+                    continue;
+                }
+                "ORG" => {
+                    let expr = parse_expr(rest).map_err(err)?;
+                    let addr = self.eval(&expr, 0).map_err(err)?;
+                    if addr < self.addr as i64 || addr > 0xFFFF {
+                        return Err(err(format!("ORG 0x{:X} is before 0x{:03X} or past the end of RAM", addr, self.addr)));
+                    }
+                    self.addr = addr as usize;
+                    continue;
+                }
+                // but `DB 01` is the raw hex DXYN with X = B, as the .ch8.src files write it
+                "DB" if parse_raw(text).is_none() => Item::Data(1, parse_data(rest).map_err(err)?),
+                "DW" => Item::Data(2, parse_data(rest).map_err(err)?),
+                _ if is_mnemonic(&keyword) => {
+                    let operands = split_operands(rest).iter()
+                        .map(|o| parse_operand(o))
+                        .collect::<Result<_, _>>()
+                        .map_err(err)?;
+                    Item::Instruction(keyword, operands)
+                }
+                _ => match parse_raw(text) {
+                    Some(bytes) => Item::Raw(bytes),
+                    None => return Err(err(format!("unknown instruction {}", word)))
+                }
+            };
+
+            let addr = self.addr;
+            self.addr += item_size(&item);
+            if self.addr > 0x10000 {
+                return Err(err("program runs past the end of RAM".to_string()));
+            }
+
+            self.statements.push(Statement {
+                file: file.to_string(),
+                line,
+                addr,
+                item
+            });
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), String> {
+        if !is_ident(name) || is_reserved(name) {
+            return Err(format!("{} can't be used as a name", name));
+        }
+
+        if self.symbols.contains_key(name) {
+            return Err(format!("{} is already defined", name));
+        }
+
+        if let Symbol::Const(..) = symbol {
+            self.constants.push(name.to_string());
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr, depth: usize) -> Result<i64, String> {
+        if depth > MAX_EVAL_DEPTH {
+            return Err("constant refers to itself".to_string());
+        }
+
+        match expr {
+            Expr::Num(n) => Ok(*n),
+            Expr::Symbol(name) => match self.symbols.get(name) {
+                Some(Symbol::Label(addr)) => Ok(*addr as i64),
+                Some(Symbol::Const(expr, _, _)) => self.eval(expr, depth + 1),
+                None => Err(format!("{} is not defined", name))
+            },
+            Expr::Unary(op, e) => {
+                let v = self.eval(e, depth)?;
+                Ok(if *op == '-' { v.wrapping_neg() } else { !v })
+            }
+            Expr::Binary(op, l, r) => {
+                let l = self.eval(l, depth)?;
+                let r = self.eval(r, depth)?;
+                match *op {
+                    "+" => Ok(l.wrapping_add(r)),
+                    "-" => Ok(l.wrapping_sub(r)),
+                    "*" => Ok(l.wrapping_mul(r)),
+                    "/" | "%" if r == 0 => Err("division by zero".to_string()),
+                    "/" => Ok(l.wrapping_div(r)),
+                    "%" => Ok(l.wrapping_rem(r)),
+                    "&" => Ok(l & r),
+                    "|" => Ok(l | r),
+                    "^" => Ok(l ^ r),
+                    "<<" => Ok(l.wrapping_shl(r as u32)),
+                    _ => Ok(l.wrapping_shr(r as u32))
+                }
+            }
+        }
+    }
+
+    // second pass: evaluate operands now that every symbol is known, and lay out the bytes
+    fn emit(self) -> Result<Program, AsmError> {
+        let origin = emulator::PRG_OFFSET;
+        let mut bytes = Vec::new();
+        let mut lines = Vec::new();
+
+        for s in &self.statements {
+            let err = |message: String| AsmError::new(&s.file, s.line, message);
+            let code = match &s.item {
+                Item::Raw(raw) => raw.clone(),
+                Item::Data(width, data) => {
+                    let mut out = Vec::new();
+                    for datum in data {
+                        match datum {
+                            Datum::Str(s) => out.extend_from_slice(s),
+                            Datum::Value(e) if *width == 1 => out.push(byte(self.eval(e, 0).map_err(err)?).map_err(err)?),
+                            Datum::Value(e) => {
+                                let w = ranged(self.eval(e, 0).map_err(err)?, -0x8000, 0xFFFF).map_err(err)?;
+                                out.extend_from_slice(&(w as u16).to_be_bytes());
+                            }
+                        }
+                    }
+                    out
+                }
+                Item::Instruction(mnemonic, operands) => {
+                    let values = operands.iter()
+                        .map(|o| self.resolve(o))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(err)?;
+                    let (instruction, long) = encode(mnemonic, &values).map_err(err)?;
+
+                    let mut out = instruction.encode().to_be_bytes().to_vec();
+                    if let Some(long) = long {
+                        out.extend_from_slice(&long.to_be_bytes());
+                    }
+                    out
+                }
+            };
+
+            bytes.resize(s.addr - origin, 0);
+            bytes.extend_from_slice(&code);
+            lines.push(SourceLine {
+                file: s.file.clone(),
+                line: s.line,
+                addr: s.addr as u16
+            });
+        }
+
+        let labels = self.symbols.iter()
+            .filter_map(|(name, symbol)| match symbol {
+                Symbol::Label(addr) => Some((name.clone(), *addr)),
+                Symbol::Const(..) => None
+            })
+            .collect();
+
+        // constants nobody used still have to evaluate
+        for name in &self.constants {
+            if let Some(Symbol::Const(expr, file, line)) = self.symbols.get(name) {
+                self.eval(expr, 0).map_err(|m| AsmError::new(file, *line, m))?;
+            }
+        }
+
+        Ok(Program {
+            origin: origin as u16,
+            bytes,
+            labels,
+            lines
+        })
+    }
+
+    fn resolve(&self, operand: &Operand) -> Result<Value, String> {
+        Ok(match operand {
+            Operand::Value(e) => Value::Num(self.eval(e, 0)?),
+            Operand::Long(e) => Value::Long(self.eval(e, 0)?),
+            Operand::V(x) => Value::V(*x),
+            Operand::Range(x, y) => Value::Range(*x, *y),
+            other => Value::Keyword(other.clone())
+        })
+    }
+}
+
+// an operand after evaluation
+enum Value {
+    V(u8),
+    Range(u8, u8),
+    Num(i64),
+    Long(i64),
+    // I, [I], DT, ST, K, F, HF, B, R
+    Keyword(Operand)
+}
+
+const MNEMONICS: [&str; 30] = [
+    "SYS", "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH",
+    "JP", "CALL", "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND",
+    "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE"
+];
+
+fn is_mnemonic(word: &str) -> bool {
+    MNEMONICS.contains(&word) || word == "AUDIO" || word == "PITCH"
+}
+
+// names that would be read as something else in an operand
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    register(&upper).is_some() || ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"].contains(&upper.as_str())
+}
+
+fn is_ident(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn register(word: &str) -> Option<u8> {
+    let upper = word.to_ascii_uppercase();
+    let digit = upper.strip_prefix('V')?;
+    if digit.len() == 1 {
+        u8::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn item_size(item: &Item) -> usize {
+    match item {
+        Item::Raw(bytes) => bytes.len(),
+        Item::Data(width, data) => data.iter()
+            .map(|d| match d {
+                Datum::Str(s) => s.len(),
+                Datum::Value(_) => *width
+            })
+            .sum(),
+        Item::Instruction(_, operands) => {
+            if operands.iter().any(|o| matches!(o, Operand::Long(_))) { 4 } else { 2 }
+        }
+    }
+}
+
+fn ranged(v: i64, min: i64, max: i64) -> Result<i64, String> {
+    if v < min || v > max {
+        Err(format!("value {} is out of range", v))
+    } else {
+        Ok(v)
+    }
+}
+
+// bytes may be written signed, so ADD V0, -1 works
+fn byte(v: i64) -> Result<u8, String> {
+    Ok(ranged(v, -0x80, 0xFF)? as u8)
+}
+
+fn nibble(v: i64) -> Result<u8, String> {
+    Ok(ranged(v, 0, 0xF)? as u8)
+}
+
+fn addr(v: i64) -> Result<u16, String> {
+    Ok(ranged(v, 0, 0xFFF)? as u16)
+}
+
+// the instruction for a mnemonic and its operands, plus the F000 address word if any
+fn encode(mnemonic: &str, ops: &[Value]) -> Result<(Instruction, Option<u16>), String> {
+    use self::Instruction::*;
+    use self::Value::{ Num, V };
+
+    let kw = |o: &Value, k: Operand| matches!(o, Value::Keyword(op) if *op == k);
+    let instruction = match (mnemonic, ops) {
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Lores,
+        ("HIGH", []) => Hires,
+        ("AUDIO", []) => Audio,
+        ("SCD", [Num(n)]) => ScrollDown(nibble(*n)?),
+        ("SCU", [Num(n)]) => ScrollUp(nibble(*n)?),
+        ("PLANE", [Num(n)]) => Plane(nibble(*n)?),
+        ("SYS", [Num(a)]) => Sys(addr(*a)?),
+        ("JP", [Num(a)]) => Jump(addr(*a)?),
+        ("JP", [V(0), Num(a)]) => JumpOffset(addr(*a)?),
+        ("CALL", [Num(a)]) => Call(addr(*a)?),
+        ("SE", [V(x), Num(n)]) => SkipEqImm(*x, byte(*n)?),
+        ("SE", [V(x), V(y)]) => SkipEq(*x, *y),
+        ("SNE", [V(x), Num(n)]) => SkipNeImm(*x, byte(*n)?),
+        ("SNE", [V(x), V(y)]) => SkipNe(*x, *y),
+        ("SAVE", [Value::Range(x, y)]) => SaveRange(*x, *y),
+        ("LOAD", [Value::Range(x, y)]) => LoadRange(*x, *y),
+        ("LD", [V(x), Num(n)]) => LoadImm(*x, byte(*n)?),
+        ("LD", [V(x), V(y)]) => Move(*x, *y),
+        ("LD", [V(x), o]) if kw(o, Operand::Dt) => GetDelay(*x),
+        ("LD", [V(x), o]) if kw(o, Operand::K) => WaitKey(*x),
+        ("LD", [V(x), o]) if kw(o, Operand::IndirectI) => Load(*x),
+        ("LD", [V(x), o]) if kw(o, Operand::R) => LoadFlags(*x),
+        ("LD", [o, Num(a)]) if kw(o, Operand::I) => LoadI(addr(*a)?),
+        ("LD", [o, Value::Long(a)]) if kw(o, Operand::I) => {
+            return Ok((LoadLongI, Some(ranged(*a, 0, 0xFFFF)? as u16)));
+        }
+        ("LD", [o, V(x)]) if kw(o, Operand::Dt) => SetDelay(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::St) => SetSound(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::F) => Font(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::Hf) => BigFont(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::B) => Bcd(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::IndirectI) => Store(*x),
+        ("LD", [o, V(x)]) if kw(o, Operand::R) => SaveFlags(*x),
+        ("ADD", [V(x), Num(n)]) => AddImm(*x, byte(*n)?),
+        ("ADD", [V(x), V(y)]) => Add(*x, *y),
+        ("ADD", [o, V(x)]) if kw(o, Operand::I) => AddI(*x),
+        ("OR", [V(x), V(y)]) => Or(*x, *y),
+        ("AND", [V(x), V(y)]) => And(*x, *y),
+        ("XOR", [V(x), V(y)]) => Xor(*x, *y),
+        ("SUB", [V(x), V(y)]) => Sub(*x, *y),
+        ("SUBN", [V(x), V(y)]) => SubReverse(*x, *y),
+        ("SHR", [V(x)]) => ShiftRight(*x, *x),
+        ("SHR", [V(x), V(y)]) => ShiftRight(*x, *y),
+        ("SHL", [V(x)]) => ShiftLeft(*x, *x),
+        ("SHL", [V(x), V(y)]) => ShiftLeft(*x, *y),
+        ("RND", [V(x), Num(n)]) => Random(*x, byte(*n)?),
+        ("DRW", [V(x), V(y), Num(n)]) => Draw(*x, *y, nibble(*n)?),
+        ("SKP", [V(x)]) => SkipKey(*x),
+        ("SKNP", [V(x)]) => SkipNotKey(*x),
+        ("PITCH", [V(x)]) => Pitch(*x),
+        _ => return Err(format!("invalid operands for {}", mnemonic))
+    };
+
+    Ok((instruction, None))
+}
+
+// cut the line at the first comment marker outside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | '#' if !quoted => return &line[.. i],
+            '/' if !quoted && line[i ..].starts_with("//") => return &line[.. i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+// `head: rest` where head is a single word
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let colon = text.find(':')?;
+    let head = &text[.. colon];
+    if !head.is_empty() && head.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+        Some((head, &text[colon + 1 ..]))
+    } else {
+        None
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(|c: char| c.is_whitespace() || c == '=') {
+        Some(i) => (&text[.. i], text[i ..].trim_start()),
+        None => (text, "")
+    }
+}
+
+// split on commas outside of strings and parentheses
+fn split_operands(text: &str) -> Vec<String> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut nesting = 0;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => nesting += 1,
+            ')' | ']' if !quoted => nesting -= 1,
+            ',' if !quoted && nesting == 0 => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    operands.push(current.trim().to_string());
+
+    operands
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_ascii_uppercase();
+    if let Some(x) = register(&upper) {
+        return Ok(Operand::V(x));
+    }
+
+    let keyword = match upper.as_str() {
+        "I" => Some(Operand::I),
+        "[I]" => Some(Operand::IndirectI),
+        "DT" => Some(Operand::Dt),
+        "ST" => Some(Operand::St),
+        "K" => Some(Operand::K),
+        "F" => Some(Operand::F),
+        "HF" => Some(Operand::Hf),
+        "B" => Some(Operand::B),
+        "R" => Some(Operand::R),
+        _ => None
+    };
+    if let Some(keyword) = keyword {
+        return Ok(keyword);
+    }
+
+    if let Some((x, y)) = upper.split_once('-') {
+        if let (Some(x), Some(y)) = (register(x.trim()), register(y.trim())) {
+            return Ok(Operand::Range(x, y));
+        }
+    }
+
+    let (word, rest) = split_word(text);
+    if word.eq_ignore_ascii_case("LONG") {
+        return Ok(Operand::Long(parse_expr(rest)?));
+    }
+
+    Ok(Operand::Value(parse_expr(text)?))
+}
+
+fn parse_data(text: &str) -> Result<Vec<Datum>, String> {
+    let operands = split_operands(text);
+    if operands.is_empty() {
+        return Err("no data".to_string());
+    }
+
+    operands.iter()
+        .map(|o| if o.starts_with('"') {
+            parse_string(o).map(Datum::Str)
+        } else {
+            parse_expr(o).map(Datum::Value)
+        })
+        .collect()
+}
+
+fn parse_string(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        Ok(text.as_bytes()[1 .. text.len() - 1].to_vec())
+    } else {
+        Err(format!("expected a quoted string, found {}", text))
+    }
+}
+
+// the original format: nothing but pairs of hex digits
+fn parse_raw(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0 .. digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i .. i + 2], 16).ok())
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    const OPS: [&str; 12] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "!"];
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            let word: String = chars[start .. i].iter().collect();
+            tokens.push(if c.is_ascii_digit() { Token::Num(parse_number(&word)?) } else { Token::Ident(word) });
+        } else {
+            let rest: String = chars[i ..].iter().collect();
+            let op = OPS.iter().find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("unexpected {}", c))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Result<i64, String> {
+    let lower = word.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+
+    parsed.map_err(|_| format!("bad number {}", word))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let tokens = tokenize(text)?;
+    if tokens.is_empty() {
+        return Err("missing value".to_string());
+    }
+
+    let mut pos = 0;
+    let expr = parse_binary(&tokens, &mut pos, 0)?;
+    if pos != tokens.len() {
+        return Err(format!("unexpected input in {}", text.trim()));
+    }
+
+    Ok(expr)
+}
+
+// binary operators from loosest to tightest binding
+const PRECEDENCE: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+fn parse_binary(tokens: &[Token], pos: &mut usize, level: usize) -> Result<Expr, String> {
+    if level == PRECEDENCE.len() {
+        return parse_unary(tokens, pos);
+    }
+
+    let mut lhs = parse_binary(tokens, pos, level + 1)?;
+    while let Some(Token::Op(op)) = tokens.get(*pos) {
+        if !PRECEDENCE[level].contains(op) {
+            break;
+        }
+        *pos += 1;
+        let rhs = parse_binary(tokens, pos, level + 1)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let token = tokens.get(*pos).cloned().ok_or("missing value")?;
+    *pos += 1;
+
+    match token {
+        Token::Num(n) => Ok(Expr::Num(n)),
+        Token::Ident(name) => Ok(Expr::Symbol(name)),
+        Token::Op("-") => Ok(Expr::Unary('-', Box::new(parse_unary(tokens, pos)?))),
+        Token::Op("~") | Token::Op("!") => Ok(Expr::Unary('~', Box::new(parse_unary(tokens, pos)?))),
+        Token::Open => {
+            let expr = parse_binary(tokens, pos, 0)?;
+            match tokens.get(*pos) {
+                Some(Token::Close) => {
+                    *pos += 1;
+                    Ok(expr)
+                }
+                _ => Err("missing )".to_string())
+            }
+        }
+        _ => Err("missing value".to_string())
+    }
+}
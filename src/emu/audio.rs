@@ -0,0 +1,173 @@
+use super::timer;
+use super::state::{ StateError, StateReader, StateWriter };
+
+// XO-CHIP audio state: a 128 sample 1-bit pattern and the pitch it plays back at
+pub const PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+
+// the buzzer plays a square wave until a rom loads a pattern with F002
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+pub struct Audio {
+    // sound timer, the buzzer sounds while it is non-zero
+    timer: timer::Timer,
+    pattern: [u8; PATTERN_SIZE],
+    use_pattern: bool,
+    pitch: u8,
+    frequency: f32,
+    volume: f32,
+    // position within the current waveform period, in [0, 1)
+    phase: f64,
+    // fractional samples carried over between frames
+    carry: f64
+}
+
+impl Audio {
+    pub fn new() -> Audio {
+        let timer = timer::Timer::new();
+        let pattern = [0; PATTERN_SIZE];
+        let use_pattern = false;
+        let pitch = DEFAULT_PITCH;
+        let frequency = DEFAULT_FREQUENCY;
+        let volume = DEFAULT_VOLUME;
+        let phase = 0.0;
+        let carry = 0.0;
+
+        Audio {
+            timer,
+            pattern,
+            use_pattern,
+            pitch,
+            frequency,
+            volume,
+            phase,
+            carry
+        }
+    }
+
+    pub fn timer(&self) -> u8 {
+        self.timer.get()
+    }
+
+    pub fn set_timer(&mut self, val: u8) {
+        self.timer.set(val);
+    }
+
+    // called by the emulator once per 60Hz frame, alongside the delay timer
+    pub fn decrement(&mut self) {
+        self.timer.decrement();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.timer.get() > 0
+    }
+
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+
+    // from here on the pattern is played instead of the square wave
+    pub fn set_pattern(&mut self, pattern: &[u8]) {
+        self.pattern.copy_from_slice(pattern);
+        self.use_pattern = true;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    // playback rate of the pattern in samples per second, 4000 * 2^((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    // Render one frame (1/frame_rate s) of mono PCM in [-volume, volume], silence while the
+    // timer is 0. Rates that don't divide evenly carry the remainder into the next frame.
+    pub fn render(&mut self, sample_rate: u32, frame_rate: u32) -> Vec<f32> {
+        let exact = sample_rate as f64 / frame_rate.max(1) as f64 + self.carry;
+        let count = exact as usize;
+        self.carry = exact - count as f64;
+
+        if !self.is_playing() || sample_rate == 0 {
+            self.phase = 0.0;
+            return vec![0.0; count];
+        }
+
+        // a pattern period is 128 bits long, the square wave has one period per cycle
+        let step = if self.use_pattern {
+            self.playback_rate() / (PATTERN_SIZE * 8) as f64
+        } else {
+            self.frequency as f64
+        } / sample_rate as f64;
+
+        (0 .. count)
+            .map(|_| {
+                let high = if self.use_pattern {
+                    let bit = (self.phase * (PATTERN_SIZE * 8) as f64) as usize;
+                    self.pattern[bit / 8] >> (7 - bit % 8) & 1 != 0
+                } else {
+                    self.phase < 0.5
+                };
+
+                self.phase = (self.phase + step).fract();
+                if high { self.volume } else { -self.volume }
+            })
+            .collect()
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.timer.save_state(w);
+        w.bytes(&self.pattern);
+        w.bool(self.use_pattern);
+        w.u8(self.pitch);
+        w.f32(self.frequency);
+        w.f32(self.volume);
+        w.f64(self.phase);
+        w.f64(self.carry);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.timer.load_state(r)?;
+
+        let pattern = r.bytes()?;
+        if pattern.len() != PATTERN_SIZE {
+            return Err(StateError::Invalid);
+        }
+
+        self.pattern.copy_from_slice(pattern);
+        self.use_pattern = r.bool()?;
+        self.pitch = r.u8()?;
+        self.frequency = r.f32()?;
+        self.set_volume(r.f32()?);
+        self.phase = r.f64()?.fract().abs();
+        self.carry = r.f64()?.fract().abs();
+        Ok(())
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio::new()
+    }
+}
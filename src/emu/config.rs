@@ -0,0 +1,39 @@
+use wasm_bindgen::prelude::*;
+use super::emulator::{ CLOCK_RATE, RENDER_RATE };
+
+// Speed settings, which can change while a rom runs. Roms vary a lot in how fast they
+// expect the interpreter to be.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmulatorConfig {
+    // instructions each frame under Timing::Fixed, Timing::Vip works out its own
+    pub instructions_per_frame: u32,
+    // how many times a second of frames (RENDER_RATE of them) the delay and sound timers
+    // count down, spread evenly over the frames
+    pub timer_frequency: u32
+}
+
+#[wasm_bindgen]
+impl EmulatorConfig {
+    // CLOCK_RATE instructions a second at RENDER_RATE frames a second
+    pub fn new() -> EmulatorConfig {
+        EmulatorConfig {
+            instructions_per_frame: CLOCK_RATE / RENDER_RATE,
+            timer_frequency: RENDER_RATE
+        }
+    }
+
+    // `instructions_per_second` at the usual 60Hz, rounded to whole instructions per frame
+    pub fn with_clock_rate(instructions_per_second: u32) -> EmulatorConfig {
+        EmulatorConfig {
+            instructions_per_frame: (instructions_per_second + RENDER_RATE / 2) / RENDER_RATE,
+            timer_frequency: RENDER_RATE
+        }
+    }
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> EmulatorConfig {
+        EmulatorConfig::new()
+    }
+}
@@ -1,225 +1,421 @@
-use std::collections::LinkedList;
-use rand::Rng;
-use super::{ display, emulator, keyboard, timer };
-
-extern crate web_sys;
-
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
-    }
-}
+use super::{ audio, display, emulator, keyboard, memory, rng, timer, timing };
+use super::quirks::Quirks;
+use super::error::ExecError;
+use super::instruction::Instruction;
+use super::state::{ StateError, StateReader, StateWriter };
 
 pub struct Cpu {
     pc: usize,
     i: u16,
     v: Vec<u8>,
-    stack: LinkedList<usize>
+    // fixed number of return address slots, sp points at the next free one
+    stack: Vec<u16>,
+    sp: usize,
+    quirks: Quirks,
+    // SUPER-CHIP RPL user flags, saved and restored by FX75/FX85
+    rpl: Vec<u8>,
+    // set by 00FD, the cpu no longer executes anything
+    halted: bool,
+    // set by DXYN under the display_wait quirk, cleared at the next frame
+    waiting_vblank: bool
 }
 
 impl Cpu {
     pub fn new() -> Cpu {
+        Cpu::with_stack_depth(emulator::STACK_DEPTH)
+    }
+
+    pub fn with_stack_depth(depth: usize) -> Cpu {
         let pc = emulator::PRG_OFFSET;
         let i = 0;
         let v = vec![0; emulator::REG_SIZE];
-        let stack = LinkedList::new();
+        let stack = vec![0; depth];
+        let sp = 0;
+        let quirks = Quirks::default();
+        let rpl = vec![0; emulator::REG_SIZE];
+        let halted = false;
+        let waiting_vblank = false;
 
         Cpu {
             pc,
             i,
             v,
-            stack
+            stack,
+            sp,
+            quirks,
+            rpl,
+            halted,
+            waiting_vblank
         }
     }
 
+    // address of the next instruction to execute
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    // V0 - VF
+    pub fn v(&self) -> &[u8] {
+        &self.v
+    }
+
+    pub fn v_mut(&mut self) -> &mut [u8] {
+        &mut self.v
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn waiting_vblank(&self) -> bool {
+        self.waiting_vblank
+    }
+
+    // called by the emulator at the start of every 60Hz frame
+    pub fn vblank(&mut self) {
+        self.waiting_vblank = false;
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    // return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[.. self.sp]
+    }
+
     // TODO: Only re-render when display changes
     // TODO: Ram and display should probably be borrowed by Cpu struct, not just this function
-    pub fn tick(&mut self, ram: &mut Vec<u8>, keyboard: &keyboard::Keyboard, display: &mut display::DisplayFrame, timer: &mut timer::Timer) {
-        // Decompose opcode into 4 nibbles
-        let opcode: u16 = (ram[self.pc] as u16) << 8 | (ram[self.pc + 1] as u16);
-        // let opcode: usize = ((ram[self.pc] as u16) << 8 | (ram[self.pc + 1] as u16)) as usize;
-        let a = opcode >> 12;
-        let b = opcode >> 8 & 0xf;
-        let c = opcode >> 4 & 0xf;
-        let d = opcode & 0xf;
+    // Returns the VIP machine cycles the instruction took, see timing.rs
+    pub fn tick(&mut self, ram: &mut memory::Memory, keyboard: &keyboard::Keyboard, display: &mut display::DisplayFrame, timer: &mut timer::Timer, audio: &mut audio::Audio, rng: &mut rng::Rng) -> Result<u32, ExecError> {
+        if self.halted || self.waiting_vblank {
+            return Ok(0);
+        }
+
+        let pc = self.pc;
+        let result = self.execute(ram, keyboard, display, timer, audio, rng);
+
+        // leave PC on the faulting instruction so the host can inspect it
+        if result.is_err() {
+            self.pc = pc;
+        }
 
-        //println!("Executing: 0x{:x?}", opcode);
+        result
+    }
+
+    fn execute(&mut self, ram: &mut memory::Memory, keyboard: &keyboard::Keyboard, display: &mut display::DisplayFrame, timer: &mut timer::Timer, audio: &mut audio::Audio, rng: &mut rng::Rng) -> Result<u32, ExecError> {
+        let pc = self.pc as u16;
+        if self.pc + 1 >= ram.len() {
+            return Err(ExecError::pc_out_of_range(pc));
+        }
+
+        let bytes = ram.as_slice();
+        let opcode: u16 = (bytes[self.pc] as u16) << 8 | (bytes[self.pc + 1] as u16);
+        let fault = |addr: usize| ExecError::out_of_bounds(pc, opcode, addr);
+        let instruction = Instruction::decode(opcode)
+            .ok_or_else(|| ExecError::unknown_opcode(pc, opcode))?;
+
+        // before the registers it depends on change
+        let cycles = timing::vip_cycles(&instruction, &self.v);
+
+        // jumps and skips below are relative to the next instruction
+        self.pc += 2;
 
-        match (a, b, c, d) {
+        match instruction {
 
             // disp_clear
-            (0x0, 0x0, 0xE, 0x0) => {
+            Instruction::Cls => {
                 display.clear();
             }
 
+            // scroll down N (SUPER-CHIP)
+            Instruction::ScrollDown(n) => {
+                display.scroll_down(n as u32);
+            }
+
+            // scroll right 4 (SUPER-CHIP)
+            Instruction::ScrollRight => {
+                display.scroll_right(4);
+            }
+
+            // scroll left 4 (SUPER-CHIP)
+            Instruction::ScrollLeft => {
+                display.scroll_left(4);
+            }
+
+            // exit (SUPER-CHIP)
+            Instruction::Exit => {
+                self.halted = true;
+                self.pc = pc as usize;
+            }
+
+            // scroll up N (XO-CHIP)
+            Instruction::ScrollUp(n) => {
+                display.scroll_up(n as u32);
+            }
+
+            // lores (SUPER-CHIP)
+            Instruction::Lores => {
+                display.set_hires(false);
+            }
+
+            // hires (SUPER-CHIP)
+            Instruction::Hires => {
+                display.set_hires(true);
+            }
+
             // return
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.pc = self.stack.pop_front().expect("Stack underflow on return");
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    return Err(ExecError::stack_underflow(pc, opcode));
+                }
+
+                self.sp -= 1;
+                self.pc = self.stack[self.sp] as usize;
             }
 
-            // call RCA (not implemented)
-            (0x0, _, _, _) => {
-                panic!("Unsupported opcode (RCA 1802): 0x{:x?}", opcode);
+            // call RCA (not supported)
+            Instruction::Sys(_) => {
+                return Err(ExecError::unknown_opcode(pc, opcode));
             }
 
             // goto addr
-            (0x1, n1, n2, n3) => {
-                let n = (n1 << 8 | n2 << 4 | n3) as usize;
-                self.pc = n - 2;
+            Instruction::Jump(n) => {
+                self.pc = n as usize;
             }
 
             // call addr
-            (0x2, n1, n2, n3) => {
-                let n = (n1 << 8 | n2 << 4 | n3) as usize;
-                self.stack.push_front(self.pc);
-                self.pc = n - 2;
+            Instruction::Call(n) => {
+                if self.sp == self.stack.len() {
+                    return Err(ExecError::stack_overflow(pc, opcode));
+                }
+
+                self.stack[self.sp] = self.pc as u16;
+                self.sp += 1;
+                self.pc = n as usize;
             }
 
             // Vx == N
-            (0x3, x, n1, n2) => {
-                let n = (n1 << 4 | n2) as u8;
+            Instruction::SkipEqImm(x, n) => {
                 if self.v[x as usize] == n {
-                    self.pc += 2;
+                    self.skip(ram);
                 }
             }
 
             // Vx != N
-            (0x4, x, n1, n2) => {
-                let n = (n1 << 4 | n2) as u8;
+            Instruction::SkipNeImm(x, n) => {
                 if self.v[x as usize] != n {
-                    self.pc += 2;
+                    self.skip(ram);
                 }
             }
 
             // Vx == Vy
-            (0x5, x, y, 0x0) => {
+            Instruction::SkipEq(x, y) => {
                 if self.v[x as usize] == self.v[y as usize] {
-                    self.pc += 2;
+                    self.skip(ram);
+                }
+            }
+
+            // Save Vx..Vy to [I] (XO-CHIP)
+            Instruction::SaveRange(x, y) => {
+                let regs = Cpu::reg_range(x, y);
+                let addrs = self.i_addrs(ram, regs.len()).map_err(fault)?;
+                let bytes = ram.as_mut_slice();
+                for (r, a) in regs.into_iter().zip(addrs) {
+                    bytes[a] = self.v[r];
+                }
+            }
+
+            // Load Vx..Vy from [I] (XO-CHIP)
+            Instruction::LoadRange(x, y) => {
+                let regs = Cpu::reg_range(x, y);
+                let addrs = self.i_addrs(ram, regs.len()).map_err(fault)?;
+                let bytes = ram.as_slice();
+                for (r, a) in regs.into_iter().zip(addrs) {
+                    self.v[r] = bytes[a];
                 }
             }
 
             // Vx = N
-            (0x6, x, n1, n2) => {
-                let n = (n1 << 4 | n2) as u8;
+            Instruction::LoadImm(x, n) => {
                 self.v[x as usize] = n;
             }
 
             // Vx += N
-            (0x7, x, n1, n2) => {
-                let n: u16 = n1 << 4 | n2;
-                let res = self.v[x as usize] as u16 + n;
-                self.v[x as usize] = (res % 0x100) as u8;
+            Instruction::AddImm(x, n) => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(n);
             }
 
             // Vx = Vy
-            (0x8, x, y, 0x0) => {
+            Instruction::Move(x, y) => {
                 self.v[x as usize] = self.v[y as usize];
             }
 
             // Vx |= Vy
-            (0x8, x, y, 0x1) => {
+            Instruction::Or(x, y) => {
                 self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xf] = 0;
+                }
             }
 
             // Vx &= Vy
-            (0x8, x, y, 0x2) => {
+            Instruction::And(x, y) => {
                 self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xf] = 0;
+                }
             }
 
             // Vx ^= Vy
-            (0x8, x, y, 0x3) => {
+            Instruction::Xor(x, y) => {
                 self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xf] = 0;
+                }
             }
 
             // Vx += Vy
-            (0x8, x, y, 0x4) => {
+            Instruction::Add(x, y) => {
                 let res = self.v[x as usize] as u16 + self.v[y as usize] as u16;
                 self.v[x as usize] = res as u8;
                 self.v[0xf] = if res > 0xff { 1 } else { 0 }; // cary flag
             }
 
             // Vx -= Vy
-            (0x8, x, y, 0x5) => {
+            Instruction::Sub(x, y) => {
                 let res = self.v[x as usize] as i16 - self.v[y as usize] as i16;
                 self.v[x as usize] = res as u8;
                 self.v[0xf] = if res >= 0 { 1 } else { 0 }; // inverse borrow flag
             }
 
             // Vx >>= 1
-            (0x8, x, _, 0x6) => {
-                self.v[0xf] = self.v[x as usize] & 1; // store LSB of Vx in v[0xf]
-                self.v[x as usize] >>= 1;
+            Instruction::ShiftRight(x, y) => {
+                let src = if self.quirks.shift_vy { self.v[y as usize] } else { self.v[x as usize] };
+                self.v[x as usize] = src >> 1;
+                self.v[0xf] = src & 1; // store LSB in v[0xf]
             }
 
             // Vx = Vy - Vx
-            (0x8, x, y, 0x7) => {
+            Instruction::SubReverse(x, y) => {
                 let res = self.v[y as usize] as i16 - self.v[x as usize] as i16;
                 self.v[x as usize] = res as u8;
                 self.v[0xf] = if res >= 0 { 1 } else { 0 }; // inverse borrow flag
             }
 
             // Vx <<= 1
-            (0x8, x, _, 0xE) => {
-                self.v[0xf] = self.v[x as usize] >> 7 & 1; // store MSB of Vx in v[0xf]
-                self.v[x as usize] <<= 1;
+            Instruction::ShiftLeft(x, y) => {
+                let src = if self.quirks.shift_vy { self.v[y as usize] } else { self.v[x as usize] };
+                self.v[x as usize] = src << 1;
+                self.v[0xf] = src >> 7 & 1; // store MSB in v[0xf]
             }
 
             // Vx != Vy
-            (0x9, x, y, 0x0) => {
+            Instruction::SkipNe(x, y) => {
                 if self.v[x as usize] != self.v[y as usize] {
-                    self.pc += 2;
+                    self.skip(ram);
                 }
             }
 
             // I = N
-            (0xA, n1, n2, n3) => {
-                let n = (n1 << 8 | n2 << 4 | n3) as u16;
+            Instruction::LoadI(n) => {
                 self.i = n;
             }
 
-            // Jmp V0 + N
-            (0xB, n1, n2, n3) => {
-                let n = (n1 << 8 | n2 << 4 | n3) as u16;
-                self.pc += (self.v[0] as u16 + n - 2) as usize; // -2 to offset the increment below
+            // Jmp V0 + N (or Vx + N)
+            Instruction::JumpOffset(n) => {
+                let offset = if self.quirks.jump_vx { self.v[(n >> 8) as usize] } else { self.v[0] };
+                self.pc = (offset as u16 + n) as usize;
             }
 
             // Vx = Rand() & N
-            (0xC, x, n1, n2) => {
-                let n = (n1 << 4 | n2) as u8;
-                self.v[x as usize] = n & rand::thread_rng().gen_range(0, 0xFF);
-            }
-
-            // Drw Vx, Vy, N
-            (0xD, x, y, n) => {
-                let sprite_start = self.i as usize;
-                let sprite_end = sprite_start + n as usize;
-                let sprite = &ram[sprite_start .. sprite_end];
-                
-                let pixel_flip = display.draw(self.v[x as usize], self.v[y as usize], &sprite);
+            Instruction::Random(x, n) => {
+                self.v[x as usize] = n & rng.next_byte(ram.as_slice());
+            }
+
+            // Drw Vx, Vy, N (N = 0 draws a 16x16 SUPER-CHIP sprite)
+            Instruction::Draw(x, y, n) => {
+                let wide = n == 0;
+                let len = if wide { 32 } else { n as usize } * display.plane_count();
+                let sprite: Vec<u8> = self.i_addrs(ram, len).map_err(fault)?
+                    .into_iter()
+                    .map(|a| ram.as_slice()[a])
+                    .collect();
+
+                let pixel_flip = display.draw(self.v[x as usize], self.v[y as usize], &sprite, wide, self.quirks.clip_sprites);
                 self.v[0xF] =  if pixel_flip { 1 } else { 0 };
+                self.waiting_vblank = self.quirks.display_wait;
             }
 
             // Key == Vx
-            (0xE, x, 0x9, 0xE) => {
+            Instruction::SkipKey(x) => {
                 if keyboard.is_pressed(self.v[x as usize] as usize) {
-                    self.pc += 2;
+                    self.skip(ram);
                 }
             }
 
             // Key != Vx
-            (0xE, x, 0xA, 0x1) => {
+            Instruction::SkipNotKey(x) => {
                 if !keyboard.is_pressed(self.v[x as usize] as usize) {
-                    self.pc += 2;
+                    self.skip(ram);
+                }
+            }
+
+            // I = NNNN (XO-CHIP, 4 byte instruction)
+            Instruction::LoadLongI => {
+                if self.pc + 1 >= ram.len() {
+                    return Err(ExecError::pc_out_of_range(pc));
                 }
+
+                let bytes = ram.as_slice();
+                self.i = (bytes[self.pc] as u16) << 8 | (bytes[self.pc + 1] as u16);
+                self.pc += 2;
+            }
+
+            // select drawing planes N (XO-CHIP)
+            Instruction::Plane(n) => {
+                display.select_planes(n);
+            }
+
+            // load audio pattern from [I] (XO-CHIP)
+            Instruction::Audio => {
+                let addrs = self.i_addrs(ram, audio::PATTERN_SIZE).map_err(fault)?;
+                let pattern: Vec<u8> = addrs.into_iter().map(|a| ram.as_slice()[a]).collect();
+                audio.set_pattern(&pattern);
             }
 
             // Vx = Timer
-            (0xF, x, 0x0, 0x7) => {
+            Instruction::GetDelay(x) => {
                 self.v[x as usize] = timer.get();
             }
 
             // Vx = Key
-            (0xF, x, 0x0, 0xA) => {
+            Instruction::WaitKey(x) => {
                 match keyboard.current_key() {
                     Some(k) => self.v[x as usize] = k as u8,
                     None => self.pc -= 2 // block on this instruction until key is pressed
@@ -227,52 +423,168 @@ impl Cpu {
             }
 
             // Timer = Vx
-            (0xF, x, 0x1, 0x5) => {
-                timer.set(self.v[x as usize] as u8);
+            Instruction::SetDelay(x) => {
+                timer.set(self.v[x as usize]);
             }
 
             // Sound = Vx
-            (0xF, x, 0x1, 0x8) => {
-                //log!("Sound not supported: 0x{:x?}", opcode)
+            Instruction::SetSound(x) => {
+                audio.set_timer(self.v[x as usize]);
             }
 
             // I += Vx
-            (0xF, x, 0x1, 0xE) => {
-                self.i += self.v[x as usize] as u16;
+            Instruction::AddI(x) => {
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
             }
 
             // I = sprite[Vx]
-            (0xF, x, 0x2, 0x9) => {
-                self.i = (emulator::FONT_OFFSET + (emulator::FONT_WIDTH * self.v[x as usize] as usize)) as u16;
+            Instruction::Font(x) => {
+                self.i = (emulator::FONT_OFFSET + (emulator::FONT_WIDTH * (self.v[x as usize] & 0xf) as usize)) as u16;
+            }
+
+            // I = big_sprite[Vx] (SUPER-CHIP)
+            Instruction::BigFont(x) => {
+                self.i = (emulator::BIG_FONT_OFFSET + (emulator::BIG_FONT_WIDTH * (self.v[x as usize] & 0xf) as usize)) as u16;
+            }
+
+            // Pitch = Vx (XO-CHIP)
+            Instruction::Pitch(x) => {
+                audio.set_pitch(self.v[x as usize]);
             }
 
             // I = BCD(Vx)
-            (0xF, x, 0x3, 0x3) => {
+            Instruction::Bcd(x) => {
                 let vx = self.v[x as usize];
-                ram[self.i as usize] = vx / 100;
-                ram[self.i as usize + 1] = (vx % 100) / 10;
-                ram[self.i as usize + 2] = vx % 10;
+                let addrs = self.i_addrs(ram, 3).map_err(fault)?;
+                let bytes = ram.as_mut_slice();
+                bytes[addrs[0]] = vx / 100;
+                bytes[addrs[1]] = (vx % 100) / 10;
+                bytes[addrs[2]] = vx % 10;
             }
 
             // Load [I], Vx (reg_dump)
-            (0xF, x, 0x5, 0x5) => {
-                for k in 0..x + 1 {
-                    ram[self.i as usize + k as usize] = self.v[k as usize];
+            Instruction::Store(x) => {
+                let addrs = self.i_addrs(ram, x as usize + 1).map_err(fault)?;
+                let bytes = ram.as_mut_slice();
+                for (k, a) in addrs.into_iter().enumerate() {
+                    bytes[a] = self.v[k];
+                }
+
+                if self.quirks.load_store_inc_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                } else if self.quirks.load_store_inc_i_by_x {
+                    self.i = self.i.wrapping_add(x as u16);
                 }
             }
 
             // Load Vx, [I] (reg_load)
-            (0xF, x, 0x6, 0x5) => {
-                for k in 0..x + 1 {
-                    self.v[k as usize] = ram[self.i as usize + k as usize];
+            Instruction::Load(x) => {
+                let addrs = self.i_addrs(ram, x as usize + 1).map_err(fault)?;
+                let bytes = ram.as_slice();
+                for (k, a) in addrs.into_iter().enumerate() {
+                    self.v[k] = bytes[a];
+                }
+
+                if self.quirks.load_store_inc_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                } else if self.quirks.load_store_inc_i_by_x {
+                    self.i = self.i.wrapping_add(x as u16);
                 }
             }
 
-            _ => {
-                panic!("Unsupported opcode: 0x{:x?}", opcode)
+            // Save V0..Vx to RPL flags (SUPER-CHIP)
+            Instruction::SaveFlags(x) => {
+                let n = x as usize + 1;
+                self.rpl[0 .. n].copy_from_slice(&self.v[0 .. n]);
+            }
+
+            // Load V0..Vx from RPL flags (SUPER-CHIP)
+            Instruction::LoadFlags(x) => {
+                let n = x as usize + 1;
+                self.v[0 .. n].copy_from_slice(&self.rpl[0 .. n]);
             }
         };
 
-        self.pc += 2;
+        let skipped = self.pc > pc as usize + 2 && matches!(instruction,
+            Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..) | Instruction::SkipEq(..)
+                | Instruction::SkipNe(..) | Instruction::SkipKey(_) | Instruction::SkipNotKey(_));
+        Ok(if skipped { cycles + timing::VIP_SKIP } else { cycles })
+    }
+
+    // skip the next instruction, which is 4 bytes long if it's XO-CHIP's F000 NNNN
+    fn skip(&mut self, ram: &memory::Memory) {
+        let bytes = ram.as_slice();
+        let long = bytes.get(self.pc) == Some(&0xF0) && bytes.get(self.pc + 1) == Some(&0x00);
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    // registers x through y in order, counting down if y < x
+    fn reg_range(x: u8, y: u8) -> Vec<usize> {
+        let (x, y) = (x as usize, y as usize);
+        if x <= y {
+            (x ..= y).collect()
+        } else {
+            (y ..= x).rev().collect()
+        }
+    }
+
+    // Resolve `len` consecutive addresses starting at I under the memory policy.
+    // Every opcode that goes through I uses this, so a fault happens before anything is written.
+    fn i_addrs(&self, ram: &memory::Memory, len: usize) -> Result<Vec<usize>, usize> {
+        let start = self.i as usize;
+        (start .. start + len)
+            .map(|addr| ram.resolve(addr).ok_or(addr))
+            .collect()
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u32(self.pc as u32);
+        w.u16(self.i);
+        w.bytes(&self.v);
+        w.u16(self.stack.len() as u16);
+        w.u16(self.sp as u16);
+        for &addr in &self.stack {
+            w.u16(addr);
+        }
+        self.quirks.save_state(w);
+        w.bytes(&self.rpl);
+        w.bool(self.halted);
+        w.bool(self.waiting_vblank);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pc = r.u32()? as usize;
+        self.i = r.u16()?;
+
+        let v = r.bytes()?;
+        if v.len() != emulator::REG_SIZE {
+            return Err(StateError::Invalid);
+        }
+        self.v = v.to_vec();
+
+        let depth = r.u16()? as usize;
+        self.sp = r.u16()? as usize;
+        if self.sp > depth {
+            return Err(StateError::Invalid);
+        }
+        self.stack = (0 .. depth).map(|_| r.u16()).collect::<Result<_, _>>()?;
+
+        self.quirks.load_state(r)?;
+
+        let rpl = r.bytes()?;
+        if rpl.len() != emulator::REG_SIZE {
+            return Err(StateError::Invalid);
+        }
+        self.rpl = rpl.to_vec();
+
+        self.halted = r.bool()?;
+        self.waiting_vblank = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
     }
 }
@@ -0,0 +1,515 @@
+// Debug Adapter Protocol server, so editors can launch and debug roms. Requests and events
+// are JSON with a Content-Length header, on stdin and stdout for the native binary.
+//
+// `launch` takes `program`, a .ch8 rom (with its labels and source lines read from the .sym
+// file `skylark asm` writes next to it), an Octo .8o file or assembler source, and optional
+// `stopOnEntry`. There is a single thread. Instruction steps stand in for line steps since
+// each source line assembles to at most one instruction, except in Octo.
+
+mod json;
+
+use std::collections::BTreeMap;
+use std::io::{ self, BufRead, Write };
+use std::path::Path;
+use std::sync::mpsc;
+use std::{ fs, thread, time };
+use super::{ asm, octo };
+use super::asm::Program;
+use super::debugger::BreakReason;
+use super::emulator::{ Emulator, RENDER_RATE };
+use super::instruction::Instruction;
+use self::json::Json;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+const STACK_REF: u64 = 2;
+
+// Serve one client until it disconnects. `input` is read on its own thread so a running
+// program can be paused.
+pub fn serve<R, W>(input: R, output: W) -> io::Result<()>
+    where R: BufRead + Send + 'static, W: Write {
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new(output);
+    loop {
+        let message = if session.running {
+            match messages.try_recv() {
+                Ok(message) => Some(message),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => return Ok(())
+            }
+        } else {
+            match messages.recv() {
+                Ok(message) => Some(message),
+                Err(_) => return Ok(())
+            }
+        };
+
+        if let Some(message) = message {
+            session.handle(&message)?;
+            if session.done {
+                return Ok(());
+            }
+        }
+
+        if session.running {
+            session.run_frame()?;
+        }
+    }
+}
+
+// The next message's JSON, None at the end of the input. Messages that aren't valid JSON
+// come through as Null and are ignored.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(Json::parse(&String::from_utf8_lossy(&body)).unwrap_or(Json::Null)))
+}
+
+struct Session<W: Write> {
+    output: W,
+    seq: u64,
+    emulator: Option<Emulator>,
+    // labels and source lines of the program being debugged, source paths made absolute
+    program: Program,
+    // breakpoint addresses by absolute source path, as set by setBreakpoints
+    breakpoints: BTreeMap<String, Vec<u16>>,
+    // a breakpoint of our own for stepping over or out of a call
+    step_target: Option<u16>,
+    stop_on_entry: bool,
+    configured: bool,
+    running: bool,
+    done: bool,
+    // events waiting for the response to the request that caused them
+    events: Vec<(&'static str, Json)>
+}
+
+impl<W: Write> Session<W> {
+    fn new(output: W) -> Session<W> {
+        Session {
+            output,
+            seq: 0,
+            emulator: None,
+            program: Program::from_symbol_map(""),
+            breakpoints: BTreeMap::new(),
+            step_target: None,
+            stop_on_entry: false,
+            configured: false,
+            running: false,
+            done: false,
+            events: Vec::new()
+        }
+    }
+
+    fn handle(&mut self, request: &Json) -> io::Result<()> {
+        if request.get("type").as_str() != Some("request") {
+            return Ok(());
+        }
+
+        let command = request.get("command").as_str().unwrap_or("");
+        let args = request.get("arguments");
+        match self.respond(command, args) {
+            Ok(body) => self.send_response(request, true, body, None)?,
+            Err(message) => self.send_response(request, false, Json::Null, Some(message))?
+        }
+        self.send_events()
+    }
+
+    fn respond(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into())
+            ])),
+            // breakpoints come after initialized, which waits for the program's source lines
+            "launch" => {
+                self.launch(args)?;
+                self.events.push(("initialized", Json::Null));
+                self.start();
+                Ok(Json::Null)
+            }
+            "configurationDone" => {
+                self.configured = true;
+                self.start();
+                Ok(Json::Null)
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "threads" => Ok(Json::object(vec![
+                ("threads", vec![Json::object(vec![("id", THREAD_ID.into()), ("name", "CHIP-8".into())])].into())
+            ])),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(Json::object(vec![
+                ("scopes", vec![
+                    scope("Registers", REGISTERS_REF),
+                    scope("Stack", STACK_REF)
+                ].into())
+            ])),
+            "variables" => Ok(self.variables(args.get("variablesReference").as_u64().unwrap_or(0))),
+            "continue" => {
+                self.emulator()?;
+                self.running = true;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.step(command)?;
+                Ok(Json::Null)
+            }
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    self.step_target = None;
+                    self.apply_breakpoints();
+                    self.stopped("pause", None);
+                }
+                Ok(Json::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Json::Null)
+            }
+            _ => Err(format!("{} is not supported", command))
+        }
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<(), String> {
+        let path = args.get("program").as_str().ok_or("launch needs a program")?;
+        let (bytes, mut program) = load(path)?;
+
+        let base = Path::new(path).parent().unwrap_or(Path::new("."));
+        for line in &mut program.lines {
+            line.file = absolute(&line.file, base);
+        }
+
+        let mut emulator = Emulator::new();
+        emulator.load_rom(bytes);
+        self.emulator = Some(emulator);
+        self.program = program;
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.apply_breakpoints();
+        Ok(())
+    }
+
+    // once launched and configured, stop at the first instruction or start running
+    fn start(&mut self) {
+        if self.emulator.is_none() || !self.configured || self.running {
+            return;
+        }
+
+        if self.stop_on_entry {
+            self.stop_on_entry = false;
+            self.stopped("entry", None);
+        } else {
+            self.running = true;
+        }
+    }
+
+    fn emulator(&mut self) -> Result<&mut Emulator, String> {
+        self.emulator.as_mut().ok_or_else(|| "no program has been launched".to_string())
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Json {
+        let source = args.get("source").get("path").as_str().unwrap_or("");
+        let file = absolute(source, Path::new("."));
+
+        let mut addrs = Vec::new();
+        let results: Vec<Json> = args.get("breakpoints").as_array().iter()
+            .filter_map(|b| b.get("line").as_u64())
+            .map(|line| {
+                // breakpoints on blank lines and comments move down to the next instruction
+                let code = self.program.lines.iter()
+                    .filter(|l| l.file == file && l.line as u64 >= line)
+                    .min_by_key(|l| l.line);
+                match code {
+                    Some(code) => {
+                        addrs.push(code.addr);
+                        Json::object(vec![("verified", true.into()), ("line", code.line.into())])
+                    }
+                    None => Json::object(vec![
+                        ("verified", false.into()),
+                        ("line", line.into()),
+                        ("message", "no code at or after this line".into())
+                    ])
+                }
+            })
+            .collect();
+
+        self.breakpoints.insert(file, addrs);
+        self.apply_breakpoints();
+        Json::object(vec![("breakpoints", results.into())])
+    }
+
+    // put the emulator's breakpoints back to the editor's plus the step target
+    fn apply_breakpoints(&mut self) {
+        let target = self.step_target;
+        let addrs: Vec<u16> = self.breakpoints.values().flatten().copied().chain(target).collect();
+        if let Some(emulator) = self.emulator.as_mut() {
+            emulator.clear_breakpoints();
+            for addr in addrs {
+                emulator.add_breakpoint(addr as usize);
+            }
+        }
+    }
+
+    // Step one instruction. Stepping over a call or out of a subroutine runs to the
+    // return address instead.
+    fn step(&mut self, command: &str) -> Result<(), String> {
+        let emulator = self.emulator()?;
+        let pc = emulator.pc();
+        let call = emulator.peek(pc, 2);
+        let is_call = call.len() == 2
+            && matches!(Instruction::decode((call[0] as u16) << 8 | call[1] as u16), Some(Instruction::Call(_)));
+
+        let target = match command {
+            "next" if is_call => Some(pc as u16 + 2),
+            "stepOut" => emulator.stack().last().copied(),
+            _ => None
+        };
+
+        match target {
+            Some(target) => {
+                self.step_target = Some(target);
+                self.apply_breakpoints();
+                self.running = true;
+                Ok(())
+            }
+            None => {
+                match emulator.step() {
+                    Ok(BreakReason::Halted) => self.exited(),
+                    Ok(_) => self.stopped("step", None),
+                    Err(e) => self.stopped("exception", Some(e.to_string()))
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // run a frame at RENDER_RATE, reporting why the program stopped if it did
+    fn run_frame(&mut self) -> io::Result<()> {
+        let started = time::Instant::now();
+        let result = match self.emulator.as_mut() {
+            Some(emulator) => emulator.run_until_break(1),
+            None => return Ok(())
+        };
+
+        if let Ok(BreakReason::FrameLimit) = result {
+            let frame = time::Duration::from_secs(1) / RENDER_RATE;
+            if let Some(rest) = frame.checked_sub(started.elapsed()) {
+                thread::sleep(rest);
+            }
+            return Ok(());
+        }
+
+        self.running = false;
+        let target = self.step_target.take();
+        self.apply_breakpoints();
+
+        match result {
+            Ok(BreakReason::Halted) => self.exited(),
+            Ok(_) => {
+                let pc = self.emulator.as_ref().map_or(0, |e| e.pc() as u16);
+                self.stopped(if target == Some(pc) { "step" } else { "breakpoint" }, None);
+            }
+            Err(e) => self.stopped("exception", Some(e.to_string()))
+        }
+        self.send_events()
+    }
+
+    fn stack_trace(&self) -> Json {
+        let emulator = match self.emulator.as_ref() {
+            Some(emulator) => emulator,
+            None => return Json::object(vec![("stackFrames", Json::Array(Vec::new())), ("totalFrames", 0u64.into())])
+        };
+
+        // the current instruction, then each call site, innermost first
+        let mut addrs = vec![emulator.pc() as u16];
+        addrs.extend(emulator.stack().iter().rev().map(|ret| ret.wrapping_sub(2)));
+
+        let frames: Vec<Json> = addrs.iter().enumerate()
+            .map(|(id, &addr)| {
+                let mut frame = vec![
+                    ("id", Json::from(id)),
+                    ("name", self.describe(addr).into()),
+                    ("instructionPointerReference", format!("0x{:03X}", addr).into())
+                ];
+                match self.program.line_at(addr) {
+                    Some(line) => {
+                        let name = Path::new(&line.file).file_name().map_or(line.file.clone(), |n| n.to_string_lossy().into_owned());
+                        frame.push(("source", Json::object(vec![("name", name.into()), ("path", line.file.as_str().into())])));
+                        frame.push(("line", line.line.into()));
+                        frame.push(("column", 1u64.into()));
+                    }
+                    None => {
+                        frame.push(("line", 0u64.into()));
+                        frame.push(("column", 0u64.into()));
+                    }
+                }
+                Json::object(frame)
+            })
+            .collect();
+
+        let total = frames.len();
+        Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())])
+    }
+
+    // the closest label at or before `addr`, or the bare address
+    fn describe(&self, addr: u16) -> String {
+        let label = self.program.labels.iter()
+            .filter(|&(_, &at)| at <= addr)
+            .max_by_key(|&(_, &at)| at);
+
+        match label {
+            Some((name, &at)) if at == addr => name.clone(),
+            Some((name, &at)) => format!("{}+{}", name, addr - at),
+            None => format!("0x{:03X}", addr)
+        }
+    }
+
+    fn variables(&self, reference: u64) -> Json {
+        let e = match self.emulator.as_ref() {
+            Some(e) => e,
+            None => return Json::object(vec![("variables", Json::Array(Vec::new()))])
+        };
+
+        let variables: Vec<(String, String)> = match reference {
+            REGISTERS_REF => {
+                let mut vars: Vec<(String, String)> = e.registers().iter().enumerate()
+                    .map(|(x, v)| (format!("V{:X}", x), format!("0x{:02X}", v)))
+                    .collect();
+                vars.push(("I".to_string(), format!("0x{:03X}", e.index())));
+                vars.push(("PC".to_string(), format!("0x{:03X}", e.pc())));
+                vars.push(("SP".to_string(), e.stack_pointer().to_string()));
+                vars.push(("DT".to_string(), e.delay_timer().to_string()));
+                vars.push(("ST".to_string(), e.sound_timer().to_string()));
+                vars
+            }
+            STACK_REF => e.stack().iter().enumerate()
+                .map(|(i, ret)| (format!("[{}]", i), format!("0x{:03X}", ret)))
+                .collect(),
+            _ => Vec::new()
+        };
+
+        let variables: Vec<Json> = variables.into_iter()
+            .map(|(name, value)| Json::object(vec![
+                ("name", name.into()),
+                ("value", value.into()),
+                ("variablesReference", 0u64.into())
+            ]))
+            .collect();
+        Json::object(vec![("variables", variables.into())])
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        let mut body = vec![
+            ("reason", Json::from(reason)),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into())
+        ];
+        if let Some(text) = text {
+            body.push(("text", text.into()));
+        }
+        self.events.push(("stopped", Json::object(body)));
+    }
+
+    fn exited(&mut self) {
+        self.events.push(("exited", Json::object(vec![("exitCode", 0u64.into())])));
+        self.events.push(("terminated", Json::Null));
+    }
+
+    fn send_response(&mut self, request: &Json, success: bool, body: Json, message: Option<String>) -> io::Result<()> {
+        let mut response = vec![
+            ("type", Json::from("response")),
+            ("request_seq", request.get("seq").clone()),
+            ("success", success.into()),
+            ("command", request.get("command").clone())
+        ];
+        if let Some(message) = message {
+            response.push(("message", message.into()));
+        }
+        if body != Json::Null {
+            response.push(("body", body));
+        }
+        self.send(response)
+    }
+
+    fn send_events(&mut self) -> io::Result<()> {
+        for (event, body) in std::mem::take(&mut self.events) {
+            let mut message = vec![("type", Json::from("event")), ("event", event.into())];
+            if body != Json::Null {
+                message.push(("body", body));
+            }
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        let mut message = vec![("seq", Json::from(self.seq))];
+        message.extend(fields);
+
+        let text = Json::object(message).to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+        self.output.flush()
+    }
+}
+
+fn scope(name: &str, reference: u64) -> Json {
+    Json::object(vec![
+        ("name", name.into()),
+        ("variablesReference", reference.into()),
+        ("expensive", false.into())
+    ])
+}
+
+// the rom and its symbols: .8o is compiled, .ch8 comes with its .sym if there is one,
+// anything else is assembled
+fn load(path: &str) -> Result<(Vec<u8>, Program), String> {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "8o" => {
+            let source = fs::read_to_string(path).map_err(|e| format!("couldn't open {}: {}", path, e))?;
+            octo::compile(path, &source).map(|p| (p.bytes.clone(), p)).map_err(|e| e.to_string())
+        }
+        "ch8" => {
+            let bytes = fs::read(path).map_err(|e| format!("couldn't open {}: {}", path, e))?;
+            let symbols = fs::read_to_string(Path::new(path).with_extension("sym")).unwrap_or_default();
+            Ok((bytes, Program::from_symbol_map(&symbols)))
+        }
+        _ => asm::assemble_file(path).map(|p| (p.bytes.clone(), p)).map_err(|e| e.to_string())
+    }
+}
+
+// `path` made absolute, trying it as given and then relative to `base`
+fn absolute(path: &str, base: &Path) -> String {
+    fs::canonicalize(path)
+        .or_else(|_| fs::canonicalize(base.join(path)))
+        .map_or_else(|_| path.to_string(), |p| p.to_string_lossy().into_owned())
+}
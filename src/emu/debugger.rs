@@ -0,0 +1,267 @@
+use wasm_bindgen::prelude::*;
+use super::{ audio, cpu, display, memory };
+use super::instruction::Instruction;
+
+// Why run_until_break or step returned
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    // step ran its instruction
+    Step,
+    // run_until_break ran all its frames without hitting anything
+    FrameLimit,
+    Breakpoint,
+    // a register condition became true
+    Register,
+    ReadWatch,
+    WriteWatch,
+    Opcode,
+    // the rom executed 00FD, nothing more will run
+    Halted
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite
+}
+
+// Vx <compare> value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub reg: u8,
+    pub compare: Compare,
+    pub value: u8
+}
+
+impl Condition {
+    pub fn new(reg: u8, compare: Compare, value: u8) -> Condition {
+        Condition {
+            reg,
+            compare,
+            value
+        }
+    }
+
+    pub fn holds(&self, v: &[u8]) -> bool {
+        let vx = match v.get(self.reg as usize) {
+            Some(&vx) => vx,
+            None => return false
+        };
+
+        match self.compare {
+            Compare::Eq => vx == self.value,
+            Compare::Ne => vx != self.value,
+            Compare::Lt => vx < self.value,
+            Compare::Le => vx <= self.value,
+            Compare::Gt => vx > self.value,
+            Compare::Ge => vx >= self.value
+        }
+    }
+}
+
+struct Breakpoint {
+    addr: usize,
+    condition: Option<Condition>
+}
+
+struct Watchpoint {
+    start: usize,
+    len: usize,
+    kind: WatchKind
+}
+
+// Breakpoints, watchpoints and opcode breaks, checked before each instruction while the
+// emulator runs under run_until_break. tick_frame never looks at them.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    // register conditions and whether each held at the last check, they fire on the edge
+    conditions: Vec<(Condition, bool)>,
+    watchpoints: Vec<Watchpoint>,
+    // (pattern, mask) pairs matched against the opcode at the PC
+    opcodes: Vec<(u16, u16)>,
+    // ram address that set off the last watchpoint
+    watch_hit: Option<usize>,
+    // PC of the last break, the instruction there runs unchecked when execution resumes
+    stopped_at: Option<usize>
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize, condition: Option<Condition>) {
+        self.remove_breakpoint(addr);
+        self.breakpoints.push(Breakpoint { addr, condition });
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.addr != addr);
+        self.breakpoints.len() != before
+    }
+
+    pub fn add_register_break(&mut self, condition: Condition) {
+        self.conditions.push((condition, false));
+    }
+
+    pub fn add_watchpoint(&mut self, start: usize, len: usize, kind: WatchKind) {
+        self.remove_watchpoint(start);
+        self.watchpoints.push(Watchpoint { start, len: len.max(1), kind });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.start != start);
+        self.watchpoints.len() != before
+    }
+
+    // `pattern` is four characters, hex digits match themselves and anything else
+    // matches any nibble, so "DXYN" breaks on every draw and "FX0A" on key waits
+    pub fn add_opcode_break(&mut self, pattern: &str) -> bool {
+        match Debugger::parse_pattern(pattern) {
+            Some(pair) => {
+                self.opcodes.push(pair);
+                true
+            }
+            None => false
+        }
+    }
+
+    // Removes every break, a program stopped at one still resumes past it
+    pub fn clear(&mut self) {
+        *self = Debugger {
+            stopped_at: self.stopped_at,
+            ..Debugger::default()
+        };
+    }
+
+    pub fn watch_hit(&self) -> Option<usize> {
+        self.watch_hit
+    }
+
+    pub fn set_stopped_at(&mut self, pc: Option<usize>) {
+        self.stopped_at = pc;
+    }
+
+    // whether execution is resuming from a break at `pc`, forgetting the break either way
+    pub fn resuming_at(&mut self, pc: usize) -> bool {
+        self.stopped_at.take() == Some(pc)
+    }
+
+    // Remember which register conditions hold now, so that only a change from here fires them
+    pub fn sync(&mut self, cpu: &cpu::Cpu) {
+        for (condition, held) in &mut self.conditions {
+            *held = condition.holds(cpu.v());
+        }
+    }
+
+    // Whether the instruction at the PC should stop execution before it runs
+    pub fn check(&mut self, cpu: &cpu::Cpu, ram: &memory::Memory, display: &display::DisplayFrame) -> Option<BreakReason> {
+        let pc = cpu.pc();
+
+        let mut became_true = false;
+        for (condition, held) in &mut self.conditions {
+            let holds = condition.holds(cpu.v());
+            became_true |= holds && !*held;
+            *held = holds;
+        }
+
+        if self.breakpoints.iter().any(|b| b.addr == pc && b.condition.is_none_or(|c| c.holds(cpu.v()))) {
+            return Some(BreakReason::Breakpoint);
+        }
+
+        let bytes = ram.as_slice();
+        let opcode = match (bytes.get(pc), bytes.get(pc + 1)) {
+            (Some(&hi), Some(&lo)) => (hi as u16) << 8 | lo as u16,
+            _ => return if became_true { Some(BreakReason::Register) } else { None }
+        };
+
+        if self.opcodes.iter().any(|&(pattern, mask)| opcode & mask == pattern) {
+            return Some(BreakReason::Opcode);
+        }
+
+        if !self.watchpoints.is_empty() {
+            if let Some(reason) = self.check_watchpoints(opcode, cpu, ram, display) {
+                return Some(reason);
+            }
+        }
+
+        if became_true { Some(BreakReason::Register) } else { None }
+    }
+
+    fn check_watchpoints(&mut self, opcode: u16, cpu: &cpu::Cpu, ram: &memory::Memory, display: &display::DisplayFrame) -> Option<BreakReason> {
+        let (write, addrs) = Debugger::accesses(opcode, cpu, ram, display)?;
+
+        for w in &self.watchpoints {
+            let wanted = match w.kind {
+                WatchKind::Read => !write,
+                WatchKind::Write => write,
+                WatchKind::ReadWrite => true
+            };
+            if !wanted {
+                continue;
+            }
+
+            if let Some(&addr) = addrs.iter().find(|&&a| a >= w.start && a < w.start + w.len) {
+                self.watch_hit = Some(addr);
+                return Some(if write { BreakReason::WriteWatch } else { BreakReason::ReadWatch });
+            }
+        }
+
+        None
+    }
+
+    // Whether the instruction writes, and the ram addresses it touches through I.
+    // Addresses that fault under the memory policy are left out, the cpu reports those.
+    fn accesses(opcode: u16, cpu: &cpu::Cpu, ram: &memory::Memory, display: &display::DisplayFrame) -> Option<(bool, Vec<usize>)> {
+        let (write, len) = match Instruction::decode(opcode)? {
+            Instruction::Draw(_, _, n) => (false, if n == 0 { 32 } else { n as usize } * display.plane_count()),
+            Instruction::Load(x) => (false, x as usize + 1),
+            Instruction::LoadRange(x, y) => (false, (x as isize - y as isize).unsigned_abs() + 1),
+            Instruction::Audio => (false, audio::PATTERN_SIZE),
+            Instruction::Store(x) => (true, x as usize + 1),
+            Instruction::SaveRange(x, y) => (true, (x as isize - y as isize).unsigned_abs() + 1),
+            Instruction::Bcd(_) => (true, 3),
+            _ => return None
+        };
+
+        let start = cpu.i() as usize;
+        Some((write, (start .. start + len).filter_map(|a| ram.resolve(a)).collect()))
+    }
+
+    fn parse_pattern(pattern: &str) -> Option<(u16, u16)> {
+        if pattern.chars().count() != 4 {
+            return None;
+        }
+
+        let mut value = 0;
+        let mut mask = 0;
+        for c in pattern.chars() {
+            value <<= 4;
+            mask <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                value |= digit as u16;
+                mask |= 0xf;
+            }
+        }
+
+        // all wildcards would stop on every instruction, step() does that
+        if mask == 0 { None } else { Some((value, mask)) }
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{ BTreeMap, BTreeSet };
+use super::instruction::Instruction;
+
+// data lines hold at most this many bytes
+const DATA_PER_LINE: usize = 8;
+// listing column the address comments start at
+const COMMENT_COLUMN: usize = 28;
+
+// One line of a disassembly: an instruction, or a run of bytes that isn't reached as code
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+    // None for data
+    pub instruction: Option<Instruction>,
+    pub label: Option<String>,
+    pub text: String
+}
+
+impl Line {
+    pub fn is_code(&self) -> bool {
+        self.instruction.is_some()
+    }
+}
+
+// Disassemble `len` bytes of `ram` from `start`. Code is told apart from data by following
+// execution from the `entries` (jumps, calls, skips and fallthrough); bytes never reached
+// that way, or that don't decode, come out as DB lines. Targets of jumps, calls and I loads
+// that start a line get labels, which the operands then refer to.
+pub fn disassemble(ram: &[u8], start: usize, len: usize, entries: &[usize]) -> Vec<Line> {
+    let end = start.saturating_add(len).min(ram.len());
+    let code = trace(ram, start, end, entries);
+
+    // lay out lines: instructions where traced code starts, data everywhere else
+    let mut spans: Vec<(usize, usize, Option<Instruction>)> = Vec::new();
+    let mut addr = start;
+    while addr < end {
+        if let Some(&instruction) = code.get(&addr) {
+            spans.push((addr, instruction.size(), Some(instruction)));
+            addr += instruction.size();
+            continue;
+        }
+
+        let mut size = 1;
+        while addr + size < end && size < DATA_PER_LINE && !code.contains_key(&(addr + size)) {
+            size += 1;
+        }
+        spans.push((addr, size, None));
+        addr += size;
+    }
+
+    // data spans are split up again wherever a label lands inside one
+    let targets = targets(ram, &code);
+    let mut split = Vec::new();
+    for (addr, size, instruction) in spans {
+        if instruction.is_some() {
+            split.push((addr, size, instruction));
+            continue;
+        }
+
+        let mut from = addr;
+        for at in addr + 1 .. addr + size {
+            if targets.contains_key(&at) {
+                split.push((from, at - from, None));
+                from = at;
+            }
+        }
+        split.push((from, addr + size - from, None));
+    }
+
+    let labels: BTreeMap<usize, String> = split.iter()
+        .filter_map(|&(addr, _, _)| targets.get(&addr).map(|kind| (addr, kind.label(addr))))
+        .collect();
+    let operand = |a: u16| match labels.get(&(a as usize)) {
+        Some(label) => label.clone(),
+        None => format!("0x{:03X}", a)
+    };
+
+    split.into_iter()
+        .map(|(addr, size, instruction)| {
+            let bytes = ram[addr .. addr + size].to_vec();
+            let text = match instruction {
+                Some(instruction) => instruction.format(word(ram, addr + 2), operand),
+                None => {
+                    let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    format!("DB {}", values.join(", "))
+                }
+            };
+
+            Line {
+                addr,
+                bytes,
+                instruction,
+                label: labels.get(&addr).cloned(),
+                text
+            }
+        })
+        .collect()
+}
+
+// Assembler-style listing: labels on their own line, each line commented with its
+// address and bytes, so the output can be fed back to the assembler.
+pub fn listing(lines: &[Line]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        if let Some(label) = &line.label {
+            out.push_str(label);
+            out.push_str(":\n");
+        }
+
+        let hex: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let code = format!("    {}", line.text);
+        out.push_str(&format!("{:<width$} ; 0x{:03X}  {}\n", code, line.addr, hex, width = COMMENT_COLUMN));
+    }
+
+    out
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Call,
+    Jump,
+    Data
+}
+
+impl Target {
+    fn label(self, addr: usize) -> String {
+        match self {
+            Target::Call => format!("sub_{:03X}", addr),
+            Target::Jump => format!("L{:03X}", addr),
+            Target::Data => format!("data_{:03X}", addr)
+        }
+    }
+}
+
+fn word(ram: &[u8], addr: usize) -> u16 {
+    let hi = ram.get(addr).cloned().unwrap_or(0) as u16;
+    let lo = ram.get(addr + 1).cloned().unwrap_or(0) as u16;
+    hi << 8 | lo
+}
+
+// instruction at `addr`, if it lies entirely inside start..end and is something the cpu runs
+fn fetch(ram: &[u8], addr: usize, start: usize, end: usize) -> Option<Instruction> {
+    if addr < start || addr + 2 > end {
+        return None;
+    }
+
+    match Instruction::decode(word(ram, addr))? {
+        // 0NNN is far more likely to be padding than a machine code call
+        Instruction::Sys(_) => None,
+        instruction if addr + instruction.size() > end => None,
+        instruction => Some(instruction)
+    }
+}
+
+// Follow every path from the entry points, returning the instructions reached by address.
+// Instructions that would overlap one already found are left out.
+fn trace(ram: &[u8], start: usize, end: usize, entries: &[usize]) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut covered = BTreeSet::new();
+    let mut pending: Vec<usize> = entries.to_vec();
+
+    while let Some(addr) = pending.pop() {
+        if code.contains_key(&addr) || covered.contains(&addr) {
+            continue;
+        }
+
+        let instruction = match fetch(ram, addr, start, end) {
+            Some(instruction) => instruction,
+            None => continue
+        };
+        let size = instruction.size();
+        if (addr .. addr + size).any(|a| covered.contains(&a)) {
+            continue;
+        }
+
+        code.insert(addr, instruction);
+        covered.extend(addr .. addr + size);
+
+        let next = addr + size;
+        if instruction.falls_through() {
+            pending.push(next);
+        }
+
+        if instruction.is_skip() {
+            let skipped = fetch(ram, next, start, end).map_or(2, |i| i.size());
+            pending.push(next + skipped);
+        }
+
+        match instruction {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) | Instruction::JumpOffset(nnn) => {
+                pending.push(nnn as usize);
+            }
+            _ => {}
+        }
+    }
+
+    code
+}
+
+// addresses the code refers to, and how
+fn targets(ram: &[u8], code: &BTreeMap<usize, Instruction>) -> BTreeMap<usize, Target> {
+    let mut targets = BTreeMap::new();
+    for (&addr, instruction) in code {
+        let (target, kind) = match instruction {
+            Instruction::Call(nnn) => (*nnn, Target::Call),
+            Instruction::Jump(nnn) | Instruction::JumpOffset(nnn) => (*nnn, Target::Jump),
+            Instruction::LoadI(nnn) => (*nnn, Target::Data),
+            Instruction::LoadLongI => (word(ram, addr + 2), Target::Data),
+            _ => continue
+        };
+
+        // a subroutine that is also jumped to is still named as a subroutine
+        let entry = targets.entry(target as usize).or_insert(kind);
+        *entry = (*entry).min(kind);
+    }
+
+    targets
+}
@@ -1,71 +1,235 @@
 use std::fmt;
-use super::emulator;
+use super::{ emulator, state };
+use super::state::{ StateError, StateReader, StateWriter };
 
+// XO-CHIP has two bitplanes. Bit n of each pixel belongs to plane n, which makes
+// the pixel value an index into a 4 color palette (0 is the background).
+pub const PLANES: usize = 2;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct DisplayFrame {
-    pixels: Vec<bool>
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    // bitmask of the planes that drawing, clearing and scrolling apply to
+    selected: u8
 }
 
 impl DisplayFrame {
     pub fn new() -> DisplayFrame {
-        let pixels = vec![false; (emulator::WIDTH * emulator::HEIGHT) as usize];
+        let width = emulator::WIDTH;
+        let height = emulator::HEIGHT;
+        let pixels = vec![0; (width * height) as usize];
+        let selected = 1;
 
         DisplayFrame {
-            pixels
+            width,
+            height,
+            pixels,
+            selected
         }
     }
 
     fn get_index(&self, x: u32, y: u32) -> usize {
-        ((x % emulator::WIDTH) + (y % emulator::HEIGHT) * emulator::WIDTH) as usize
+        ((x % self.width) + (y % self.height) * self.width) as usize
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == emulator::HIRES_WIDTH
     }
 
-    pub fn pixels(&self) -> *const bool {
+    // switch between 64x32 and 128x64, which clears the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (emulator::HIRES_WIDTH, emulator::HIRES_HEIGHT)
+        } else {
+            (emulator::WIDTH, emulator::HEIGHT)
+        };
+
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; (width * height) as usize];
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.pixels[self.get_index(x, y)]
+    }
+
+    // palette index of every pixel, row by row
+    pub fn pixels(&self) -> *const u8 {
         self.pixels.as_ptr()
     }
 
+    pub fn selected_planes(&self) -> u8 {
+        self.selected
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & ((1 << PLANES) - 1);
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.selected.count_ones() as usize
+    }
+
+    // clears the selected planes only
     pub fn clear(&mut self) {
-        for idx in 0..self.pixels.len() {
-            self.pixels[idx] = false;
+        for pixel in &mut self.pixels {
+            *pixel &= !self.selected;
         }
     }
 
-    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+    pub fn scroll_down(&mut self, n: u32) {
+        let shift = n.min(self.height) as i32;
+        self.scroll(0, shift);
+    }
+
+    pub fn scroll_up(&mut self, n: u32) {
+        let shift = n.min(self.height) as i32;
+        self.scroll(0, -shift);
+    }
+
+    pub fn scroll_right(&mut self, n: u32) {
+        let shift = n.min(self.width) as i32;
+        self.scroll(shift, 0);
+    }
+
+    pub fn scroll_left(&mut self, n: u32) {
+        let shift = n.min(self.width) as i32;
+        self.scroll(-shift, 0);
+    }
+
+    // move the selected planes by (dx, dy), filling the exposed area with blank pixels
+    fn scroll(&mut self, dx: i32, dy: i32) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let source = self.pixels.clone();
+
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    source[(sx + sy * width) as usize] & self.selected
+                } else {
+                    0
+                };
+
+                let index = (x + y * width) as usize;
+                self.pixels[index] = self.pixels[index] & !self.selected | moved;
+            }
+        }
+    }
+
+    // Rows are one byte wide, or two bytes (big-endian) for `wide` 16x16 SUPER-CHIP sprites.
+    // With several planes selected, `sprite` holds one sprite per plane back to back.
+    // The origin always wraps onto the screen; with `clip` set, the parts of the
+    // sprite that run off the right or bottom edge are dropped instead of wrapping.
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8], wide: bool, clip: bool) -> bool {
         let mut change = false;
+        let planes = self.plane_count();
+        if planes == 0 {
+            return false;
+        }
+
+        let per_plane = (sprite.len() / planes).max(1);
+        let mask = self.selected;
+        let selected = (0 .. PLANES as u8).filter(|p| mask >> p & 1 != 0);
+        for (plane, data) in selected.zip(sprite.chunks(per_plane)) {
+            change |= self.draw_plane(x, y, data, wide, clip, 1 << plane);
+        }
+
+        change
+    }
+
+    fn draw_plane(&mut self, x: u8, y: u8, sprite: &[u8], wide: bool, clip: bool, plane: u8) -> bool {
+        let mut change = false;
+        let x = x as u32 % self.width;
+        let y = y as u32 % self.height;
+        let row_bytes = if wide { 2 } else { 1 };
         // println!("Drawing ({}, {}) h{}", x, y, sprite.len());
-        let test: Vec<usize> = sprite.iter().map(|s| { *s as usize }).collect();
-        for dy in 0..sprite.len() {
-            change |= self.draw_sprite(x, y + dy as u8, sprite[dy as usize]);
+        for (dy, row) in sprite.chunks(row_bytes).enumerate() {
+            if clip && y + dy as u32 >= self.height {
+                break;
+            }
+
+            let bits = row.iter().fold(0u16, |acc, &b| acc << 8 | b as u16);
+            change |= self.draw_sprite(x, y + dy as u32, bits, 8 * row_bytes as u32, clip, plane);
         }
 
         change
     }
 
     // TODO: This can be improved by simply XORing the sprite with existing byte from ram
-    fn draw_sprite(&mut self, x: u8, y: u8, sprite: u8) -> bool {
+    fn draw_sprite(&mut self, x: u32, y: u32, sprite: u16, len: u32, clip: bool, plane: u8) -> bool {
         let mut change = false;
-        for i in 0 .. 8 {
-            let index = self.get_index(x as u32 + i as u32, y as u32);
-            let new_pixel = match sprite >> (7 - i) & 1 {
-                0 => false,
-                _ => true
-            } ^ self.pixels[index];
-
-            change |= self.pixels[index] && !new_pixel;
-            self.pixels[index] = new_pixel;
+        for i in 0 .. len {
+            if clip && x + i >= self.width {
+                break;
+            }
+
+            if sprite >> (len - 1 - i) & 1 == 0 {
+                continue;
+            }
+
+            let index = self.get_index(x + i, y);
+            change |= self.pixels[index] & plane != 0;
+            self.pixels[index] ^= plane;
         }
 
         change
     }
+
+    // CRC-32 of the resolution and pixels, for comparing screens without storing them
+    pub fn hash(&self) -> u32 {
+        let mut bytes = Vec::with_capacity(8 + self.pixels.len());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.pixels);
+        state::crc32(&bytes)
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.is_hires());
+        w.u8(self.selected);
+        w.bytes(&self.pixels);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.set_hires(r.bool()?);
+        self.select_planes(r.u8()?);
+
+        let pixels = r.bytes()?;
+        if pixels.len() != self.pixels.len() || pixels.iter().any(|&p| p >> PLANES != 0) {
+            return Err(StateError::Invalid);
+        }
+
+        self.pixels.copy_from_slice(pixels);
+        Ok(())
+    }
+}
+
+impl Default for DisplayFrame {
+    fn default() -> DisplayFrame {
+        DisplayFrame::new()
+    }
 }
 
 impl fmt::Display for DisplayFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for row in self.pixels.as_slice().chunks(emulator::WIDTH as usize) {
+        for row in self.pixels.as_slice().chunks(self.width as usize) {
             for &pixel in row {
-                let symbol = if pixel { '◼' } else { '◻' };
+                let symbol = if pixel != 0 { '◼' } else { '◻' };
                 write!(f, "{}", symbol)?;
             }
 
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
         Ok(())
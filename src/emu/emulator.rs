@@ -1,226 +1,881 @@
 extern crate wasm_bindgen;
 
 use wasm_bindgen::prelude::*;
-use super::{ display, cpu, keyboard, timer };
+use super::{ audio, config, debugger, disasm, display, cpu, keyboard, memory, movie, quirks, rewind, rng, timer, timing, trace };
+use super::error::ExecError;
+use super::state::{ StateError, StateReader, StateWriter };
+use crate::utils;
 
-extern crate web_sys;
-
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
-    }
-}
 
 // TODO: Maybe usize? Also, these probably shouln't be public
 pub const WIDTH: u32 = 64;
 pub const HEIGHT: u32 = 32;
+pub const HIRES_WIDTH: u32 = 128;
+pub const HIRES_HEIGHT: u32 = 64;
 pub const PRG_OFFSET: usize = 0x200;
 pub const RAM_SIZE: usize = 0x1000;
+pub const XO_RAM_SIZE: usize = 0x10000;
 pub const REG_SIZE: usize = 0x10;
 pub const FONT_OFFSET: usize = 0x0;
 pub const FONT_WIDTH: usize = 5;
+pub const BIG_FONT_OFFSET: usize = FONT_OFFSET + 16 * FONT_WIDTH;
+pub const BIG_FONT_WIDTH: usize = 10;
+
+// the VIP interpreter reserves room for 12 return addresses, most later ones 16
+pub const STACK_DEPTH: usize = 16;
 
 // no offiical clock rate, but this works pretty well
+// the defaults for EmulatorConfig, which can change both at runtime
 pub const CLOCK_RATE: u32 = 600;
 
 // render should be done at 60Hz
-// it's up to the runtime env to ensure this rate, but emulation will be correct regardless of render rate
+// it's up to the runtime env to ensure this rate (or use run_for), but emulation will be correct regardless of render rate
 pub const RENDER_RATE: u32 = 60;
 
+// run_for drops time past this instead of catching up on it, after the host was suspended say
+const MAX_RUN_FOR_MS: f64 = 1000.0;
+
+// built-in hex font, 0-F, FONT_WIDTH bytes per glyph
+const FONT_SET: [u8; 16 * FONT_WIDTH] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
+    0xf0, 0x10, 0xf0, 0x10, 0xf0, // 3
+    0x90, 0x90, 0xf0, 0x10, 0x10, // 4
+    0xf0, 0x80, 0xf0, 0x10, 0xf0, // 5
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, // 6
+    0xf0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, // 8
+    0xf0, 0x90, 0xf0, 0x10, 0xf0, // 9
+    0xf0, 0x90, 0xf0, 0x90, 0x90, // A
+    0xe0, 0x90, 0xe0, 0x90, 0xe0, // B
+    0xf0, 0x80, 0x80, 0x80, 0xf0, // C
+    0xe0, 0x90, 0x90, 0x90, 0xe0, // D
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
+    0xf0, 0x80, 0xf0, 0x80, 0x80  // F
+];
+
+// SUPER-CHIP 8x10 font, BIG_FONT_WIDTH bytes per glyph (A-F as in Octo)
+const BIG_FONT_SET: [u8; 16 * BIG_FONT_WIDTH] = [
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff, // 1
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // 2
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 3
+    0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 5
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 6
+    0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18, // 7
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 8
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 9
+    0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // A
+    0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, // B
+    0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c, // C
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // D
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // E
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0  // F
+];
+
 #[wasm_bindgen]
 pub struct Emulator {
-    ram: Vec<u8>,
+    ram: memory::Memory,
     cpu: cpu::Cpu,
     display: display::DisplayFrame,
     keyboard: keyboard::Keyboard,
-    timer: timer::Timer
+    timer: timer::Timer,
+    audio: audio::Audio,
+    rng: rng::Rng,
+    rewind: Option<rewind::Rewind>,
+    // last rom loaded, kept so the machine can be reset
+    rom: Vec<u8>,
+    // frames run since the rom was loaded
+    frame: u32,
+    // whether the current frame has started, and what it has used of its budget so far
+    // (between frames, what the last one overran it by), instructions under Timing::Fixed
+    // and machine cycles under Timing::Vip
+    in_frame: bool,
+    cycle: u32,
+    timing: timing::Timing,
+    config: config::EmulatorConfig,
+    // frame budget run_for has been given time for but not run yet, negative when the
+    // last instruction overran it
+    owed: f64,
+    // timer ticks owed, in 1/RENDER_RATE of a tick: each frame adds timer_frequency and
+    // the timers count down once for every whole tick
+    timer_phase: u32,
+    debugger: debugger::Debugger,
+    trace: trace::Trace,
+    recording: Option<movie::Movie>,
+    // movie being replayed and the index of its next key event
+    playback: Option<(movie::Movie, usize)>
 }
 
 #[wasm_bindgen]
 impl Emulator {
     pub fn new() -> Emulator {
-        let ram = vec![0; RAM_SIZE];
+        utils::set_panic_hook();
+
+        let ram = memory::Memory::new(RAM_SIZE, memory::MemoryPolicy::Trap);
         let cpu = cpu::Cpu::new();
         let display = display::DisplayFrame::new();
         let keyboard = keyboard::Keyboard::new();
         let timer = timer::Timer::new();
+        let audio = audio::Audio::new();
+        let rng = rng::Rng::default();
+        let rewind = None;
+        let rom = Vec::new();
+        let frame = 0;
+        let in_frame = false;
+        let cycle = 0;
+        let timing = timing::Timing::Fixed;
+        let config = config::EmulatorConfig::new();
+        let owed = 0.0;
+        let timer_phase = 0;
+        let debugger = debugger::Debugger::new();
+        let trace = trace::Trace::new();
+        let recording = None;
+        let playback = None;
 
         Emulator {
             ram,
             cpu,
             display,
             keyboard,
-            timer
+            timer,
+            audio,
+            rng,
+            rewind,
+            rom,
+            frame,
+            in_frame,
+            cycle,
+            timing,
+            config,
+            owed,
+            timer_phase,
+            debugger,
+            trace,
+            recording,
+            playback
         }
     }
 
+    // CXNN draws from an xorshift generator seeded with `seed`, so runs with the same
+    // seed and input are identical
+    pub fn with_seed(seed: u32) -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.set_rng(rng::RngKind::Xorshift, seed);
+        emulator
+    }
+
+    pub fn with_quirks(quirks: &quirks::Quirks) -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.set_quirks(quirks);
+        emulator
+    }
+
     pub fn load_rom(&mut self, rom: Vec<u8>) {
         self.load_fonts();
 
-        for (i, rom_byte) in rom.iter().enumerate() {
-            self.ram[PRG_OFFSET + i] = *rom_byte;
+        // anything that doesn't fit in ram is dropped
+        for (dst, rom_byte) in self.ram.as_mut_slice()[PRG_OFFSET ..].iter_mut().zip(rom.iter()) {
+            *dst = *rom_byte;
         }
+
+        self.rom = rom;
+        self.frame = 0;
+        self.in_frame = false;
+        self.cycle = 0;
+        self.owed = 0.0;
+        self.timer_phase = 0;
+    }
+
+    // Power cycle and reload the last rom. Settings (quirks, timing, config, memory policy
+    // and size, stack depth, rng algorithm and seed, audio frequency and volume) are kept.
+    pub fn reset(&mut self) {
+        let quirks = self.cpu.quirks();
+        let (frequency, volume) = (self.audio.frequency(), self.audio.volume());
+
+        self.ram = memory::Memory::new(self.ram.len(), self.ram.policy());
+        self.cpu = cpu::Cpu::with_stack_depth(self.cpu.stack_depth());
+        self.cpu.set_quirks(quirks);
+        self.display = display::DisplayFrame::new();
+        self.keyboard = keyboard::Keyboard::new();
+        self.timer = timer::Timer::new();
+        self.audio = audio::Audio::new();
+        self.audio.set_frequency(frequency);
+        self.audio.set_volume(volume);
+        self.rng.reseed(self.rng.kind(), self.rng.seed());
+
+        let rom = std::mem::take(&mut self.rom);
+        self.load_rom(rom);
+    }
+
+    // number of tick_frame calls since the rom was loaded
+    pub fn frame_count(&self) -> u32 {
+        self.frame
     }
 
     pub fn display_out(&self) -> String {
         self.display.to_string()
     }
 
-    pub fn pixels(&self) -> *const bool {
+    // one palette index (0-3) per pixel, see display::PLANES
+    pub fn pixels(&self) -> *const u8 {
         self.display.pixels()
     }
 
-    // tick for 1 frame (60Hz)
-    pub fn tick_frame(&mut self) {
-        let ticks_per_frame = CLOCK_RATE / RENDER_RATE;
+    // tick for 1 frame (60Hz), or what is left of it after step or run_until_break
+    // stops at the first faulting instruction, leaving the PC pointing at it
+    pub fn tick_frame(&mut self) -> Result<(), ExecError> {
+        self.debugger.set_stopped_at(None);
+        loop {
+            self.tick()?;
+            if !self.in_frame {
+                return Ok(());
+            }
+        }
+    }
+
+    // Run as much as `elapsed_ms` of real time is worth at RENDER_RATE frames a second, returning
+    // the number of frames finished. What's left of a frame carries into the next call, so
+    // hosts get the same speed whatever rate they call this at.
+    pub fn run_for(&mut self, elapsed_ms: f64) -> Result<u32, ExecError> {
+        let frames = elapsed_ms.clamp(0.0, MAX_RUN_FOR_MS) * RENDER_RATE as f64 / 1000.0;
+        self.owed += frames * self.frame_budget() as f64;
+        self.debugger.set_stopped_at(None);
+
+        // a little slack so float rounding doesn't run an extra instruction
+        let start = self.frame;
+        while self.owed > 1e-6 {
+            // a frame cut short by a vblank wait or halt uses up the rest of its budget idling
+            let before = self.cycle;
+            if let Err(e) = self.tick() {
+                self.owed = 0.0;
+                return Err(e);
+            }
+            let after = if self.in_frame { self.cycle } else { self.frame_budget() + self.cycle };
+            self.owed -= after.saturating_sub(before) as f64;
+        }
+
+        Ok(self.frame.wrapping_sub(start))
+    }
+
+    // Run one instruction. A cpu waiting for vblank moves on to the next frame first,
+    // so something always executes unless the rom has halted.
+    pub fn step(&mut self) -> Result<debugger::BreakReason, ExecError> {
+        if self.cpu.halted() {
+            return Ok(debugger::BreakReason::Halted);
+        }
+
+        if !self.in_frame {
+            self.begin_frame();
+        }
+        self.debugger.set_stopped_at(None);
+        self.tick()?;
+        Ok(debugger::BreakReason::Step)
+    }
+
+    // Run for up to `frames` frames, stopping before any instruction that hits a breakpoint,
+    // register condition, watchpoint or opcode break. Only the instruction a break stopped
+    // at runs unchecked, so calling this again resumes from it, while a break on the PC
+    // execution starts from (after a reset, step or set_pc) fires before anything runs.
+    pub fn run_until_break(&mut self, frames: u32) -> Result<debugger::BreakReason, ExecError> {
+        let start = self.frame;
+        let mut resuming = self.debugger.resuming_at(self.cpu.pc());
+        self.debugger.sync(&self.cpu);
+
+        while self.frame.wrapping_sub(start) < frames {
+            if self.cpu.halted() {
+                return Ok(debugger::BreakReason::Halted);
+            }
+
+            // start the frame first so the check sees the cpu as it will run
+            if !self.in_frame {
+                self.begin_frame();
+            }
+
+            if !resuming {
+                if let Some(reason) = self.debugger.check(&self.cpu, &self.ram, &self.display) {
+                    self.debugger.set_stopped_at(Some(self.cpu.pc()));
+                    return Ok(reason);
+                }
+            }
+
+            resuming = false;
+            self.tick()?;
+        }
+
+        Ok(debugger::BreakReason::FrameLimit)
+    }
+
+    // stop when the PC reaches `addr`
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.debugger.add_breakpoint(addr, None);
+    }
+
+    // stop when the PC reaches `addr` and Vx <compare> value holds
+    pub fn add_conditional_breakpoint(&mut self, addr: usize, reg: u8, compare: debugger::Compare, value: u8) {
+        self.debugger.add_breakpoint(addr, Some(debugger::Condition::new(reg, compare, value)));
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.debugger.remove_breakpoint(addr)
+    }
+
+    // stop wherever Vx <compare> value goes from false to true
+    pub fn add_register_break(&mut self, reg: u8, compare: debugger::Compare, value: u8) {
+        self.debugger.add_register_break(debugger::Condition::new(reg, compare, value));
+    }
+
+    // stop before an instruction that reads or writes any of `len` bytes from `addr` through I
+    pub fn add_watchpoint(&mut self, addr: usize, len: usize, kind: debugger::WatchKind) {
+        self.debugger.add_watchpoint(addr, len, kind);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.debugger.remove_watchpoint(addr)
+    }
+
+    // stop before opcodes matching a pattern like "DXYN" or "FX0A", false if it isn't one
+    pub fn break_on_opcode(&mut self, pattern: &str) -> bool {
+        self.debugger.add_opcode_break(pattern)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear();
+    }
+
+    // ram address behind the last ReadWatch or WriteWatch
+    pub fn watch_hit(&self) -> Option<usize> {
+        self.debugger.watch_hit()
+    }
+
+    // Reset the machine and record every key_change from here on
+    pub fn start_recording(&mut self) {
+        self.playback = None;
+        self.reset();
+        self.recording = Some(movie::Movie {
+            rom_hash: movie::Movie::rom_hash(&self.rom),
+            rng_kind: self.rng.kind(),
+            seed: self.rng.seed(),
+            quirks: self.cpu.quirks(),
+            timing: self.timing,
+            instructions_per_frame: self.config.instructions_per_frame,
+            timer_frequency: self.config.timer_frequency,
+            memory_policy: self.ram.policy(),
+            ram_size: self.ram.len() as u32,
+            stack_depth: self.cpu.stack_depth() as u16,
+            frames: 0,
+            events: Vec::new()
+        });
+    }
+
+    // the finished movie file, empty if nothing was being recorded
+    pub fn stop_recording(&mut self) -> Vec<u8> {
+        match self.recording.take() {
+            Some(mut movie) => {
+                movie.frames = self.frame;
+                movie.to_bytes()
+            }
+            None => Vec::new()
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
 
-        self.timer.decrement();
-        for _ in 0 .. ticks_per_frame{
-            self.cpu.tick(&mut self.ram, &self.keyboard, &mut self.display, &mut self.timer)
+    // Reset into the movie's settings and replay its input on the following frames.
+    // The rom it was recorded with must already be loaded. Live key_change calls are
+    // ignored until playback finishes.
+    pub fn play_movie(&mut self, movie: &[u8]) -> Result<(), movie::MovieError> {
+        let movie = movie::Movie::from_bytes(movie)?;
+        if movie.rom_hash != movie::Movie::rom_hash(&self.rom) {
+            return Err(movie::MovieError::RomMismatch);
         }
+
+        self.recording = None;
+        self.rng.reseed(movie.rng_kind, movie.seed);
+        self.cpu = cpu::Cpu::with_stack_depth(movie.stack_depth as usize);
+        self.cpu.set_quirks(movie.quirks);
+        self.timing = movie.timing;
+        self.config.instructions_per_frame = movie.instructions_per_frame;
+        self.config.timer_frequency = movie.timer_frequency;
+        self.ram = memory::Memory::new(movie.ram_size as usize, movie.memory_policy);
+        self.reset();
+        self.playback = Some((movie, 0));
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // PCM samples for the frame just run, sample_rate / RENDER_RATE of them on average
+    pub fn audio_samples(&mut self, sample_rate: u32) -> Vec<f32> {
+        self.audio.render(sample_rate, RENDER_RATE)
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.audio.is_playing()
+    }
+
+    pub fn set_audio_frequency(&mut self, frequency: f32) {
+        self.audio.set_frequency(frequency);
+    }
+
+    // 0.0 - 1.0
+    pub fn set_audio_volume(&mut self, volume: f32) {
+        self.audio.set_volume(volume);
+    }
+
+    // Snapshot of the whole machine, see state.rs for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.ram.save_state(&mut w);
+        self.cpu.save_state(&mut w);
+        self.display.save_state(&mut w);
+        self.keyboard.save_state(&mut w);
+        self.timer.save_state(&mut w);
+        self.audio.save_state(&mut w);
+        self.rng.save_state(&mut w);
+        w.u32(self.frame);
+        w.u32(self.timer_phase);
+        w.finish()
+    }
+
+    // Restore a snapshot from save_state. Nothing changes unless the whole state is valid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(state)?;
+
+        let mut ram = memory::Memory::new(RAM_SIZE, memory::MemoryPolicy::Trap);
+        let mut cpu = cpu::Cpu::new();
+        let mut display = display::DisplayFrame::new();
+        let mut keyboard = keyboard::Keyboard::new();
+        let mut timer = timer::Timer::new();
+        let mut audio = audio::Audio::new();
+        let mut rng = rng::Rng::default();
+
+        ram.load_state(&mut r)?;
+        cpu.load_state(&mut r)?;
+        display.load_state(&mut r)?;
+        keyboard.load_state(&mut r)?;
+        timer.load_state(&mut r)?;
+        audio.load_state(&mut r)?;
+        rng.load_state(&mut r)?;
+        let frame = r.u32()?;
+        let timer_phase = r.u32()?;
+        r.finish()?;
+        if timer_phase >= RENDER_RATE {
+            return Err(StateError::Invalid);
+        }
+
+        self.ram = ram;
+        self.cpu = cpu;
+        self.display = display;
+        self.keyboard = keyboard;
+        self.timer = timer;
+        self.audio = audio;
+        self.rng = rng;
+        self.frame = frame;
+        self.in_frame = false;
+        self.cycle = 0;
+        self.owed = 0.0;
+        self.timer_phase = timer_phase;
+        self.resync_movie();
+        Ok(())
+    }
+
+    // Start keeping a snapshot every `interval` frames, up to `capacity` of them.
+    // Snapshots are delta-compressed, so a few thousand cost little more than a few.
+    pub fn enable_rewind(&mut self, interval: u32, capacity: usize) {
+        let mut rewind = rewind::Rewind::new(interval, capacity);
+        rewind.capture(self.save_state());
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    // how far back rewind() can currently go, in frames
+    pub fn rewind_available(&self) -> u32 {
+        self.rewind.as_ref().map_or(0, |r| r.available_frames())
+    }
+
+    // Go back at least `frames` frames, or as far as the buffer reaches.
+    // Returns the number of frames actually rewound, 0 when rewind is disabled.
+    pub fn rewind(&mut self, frames: u32) -> u32 {
+        let (state, rewound) = match self.rewind.as_mut().and_then(|r| r.rewind(frames)) {
+            Some(step) => step,
+            None => return 0
+        };
+
+        self.load_state(&state).expect("rewind snapshot is a valid state");
+        rewound
+    }
+
+    // palette index of a single pixel, coordinates wrap around the screen
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.display.pixel(x, y)
+    }
+
+    // see DisplayFrame::hash
+    pub fn display_hash(&self) -> u32 {
+        self.display.hash()
     }
 
     pub fn key_change(&mut self, key: usize, pressed: bool) {
+        if self.playback.is_some() {
+            return;
+        }
+
+        if let (Some(movie), 0 ..= 0xf) = (self.recording.as_mut(), key) {
+            movie.events.push(movie::KeyEvent { frame: self.frame, key: key as u8, pressed });
+        }
+
         self.keyboard.key_change(key, pressed)
     }
 
+    pub fn quirks(&self) -> quirks::Quirks {
+        self.cpu.quirks()
+    }
+
+    pub fn set_quirks(&mut self, quirks: &quirks::Quirks) {
+        self.cpu.set_quirks(*quirks);
+    }
+
+    pub fn timing(&self) -> timing::Timing {
+        self.timing
+    }
+
+    // Timing::Vip is meant to go with Quirks::vip(), whose display_wait has DXYN wait
+    // for the interrupt as it did on the VIP
+    pub fn set_timing(&mut self, timing: timing::Timing) {
+        self.timing = timing;
+    }
+
+    pub fn config(&self) -> config::EmulatorConfig {
+        self.config
+    }
+
+    // takes effect from the next instruction, zeros count as 1
+    pub fn set_config(&mut self, config: &config::EmulatorConfig) {
+        self.config = config::EmulatorConfig {
+            instructions_per_frame: config.instructions_per_frame.max(1),
+            timer_frequency: config.timer_frequency.max(1)
+        };
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.rng.seed()
+    }
+
+    pub fn rng_kind(&self) -> rng::RngKind {
+        self.rng.kind()
+    }
+
+    // restarts the random sequence, use RngKind::Vip for the VIP interpreter's algorithm
+    pub fn set_rng(&mut self, kind: rng::RngKind, seed: u32) {
+        self.rng.reseed(kind, seed);
+    }
+
+    // how instructions addressing past the end of ram behave, Trap by default
+    pub fn memory_policy(&self) -> memory::MemoryPolicy {
+        self.ram.policy()
+    }
+
+    pub fn set_memory_policy(&mut self, policy: memory::MemoryPolicy) {
+        self.ram.set_policy(policy);
+    }
+
+    // XO-CHIP roms address 64 KiB of ram, call this before loading one
+    pub fn set_extended_memory(&mut self, extended: bool) {
+        self.ram.resize(if extended { XO_RAM_SIZE } else { RAM_SIZE });
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.cpu.stack_depth()
+    }
+
+    // resizing the stack resets the cpu, so do this before loading a rom
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        let quirks = self.cpu.quirks();
+        self.cpu = cpu::Cpu::with_stack_depth(depth);
+        self.cpu.set_quirks(quirks);
+    }
+
+    // Keep the last `capacity` executed instructions, read them back with trace_log
+    pub fn trace_to_buffer(&mut self, capacity: usize) {
+        self.trace.log_to_buffer(capacity);
+    }
+
+    // log every executed instruction to the browser console, or stderr outside the browser
+    pub fn trace_to_console(&mut self) {
+        self.trace.log_to_console();
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.trace.stop();
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_enabled()
+    }
+
+    // only trace instructions at start .. end
+    pub fn set_trace_range(&mut self, start: usize, end: usize) {
+        self.trace.set_range(Some((start, end)));
+    }
+
+    // only trace these classes, 1 << OpcodeClass for each
+    pub fn set_trace_classes(&mut self, mask: u32) {
+        self.trace.set_classes(mask);
+    }
+
+    pub fn clear_trace_filter(&mut self) {
+        self.trace.set_range(None);
+        self.trace.set_classes(trace::ALL_CLASSES);
+    }
+
+    // the buffered trace, oldest first, one instruction per line:
+    // address, opcode, disassembly and the registers it changed
+    pub fn trace_log(&self) -> String {
+        self.trace.entries().iter().map(|entry| format!("{}\n", entry)).collect()
+    }
+
+    pub fn clear_trace(&mut self) {
+        self.trace.clear();
+    }
+
+    // address of the next instruction to execute
+    pub fn pc(&self) -> usize {
+        self.cpu.pc()
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.cpu.set_pc(pc);
+    }
+
+    // V0 - VF
+    pub fn registers(&self) -> Vec<u8> {
+        self.cpu.v().to_vec()
+    }
+
+    // writes to registers past VF are ignored
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        if let Some(v) = self.cpu.v_mut().get_mut(x) {
+            *v = value;
+        }
+    }
+
+    // the I register
+    pub fn index(&self) -> u16 {
+        self.cpu.i()
+    }
+
+    pub fn set_index(&mut self, i: u16) {
+        self.cpu.set_i(i);
+    }
+
+    // `len` bytes of RAM from `addr`, fewer if that runs past the end
+    pub fn peek(&self, addr: usize, len: usize) -> Vec<u8> {
+        let ram = self.ram.as_slice();
+        let start = addr.min(ram.len());
+        ram[start .. addr.saturating_add(len).min(ram.len())].to_vec()
+    }
+
+    // bytes that don't fit in RAM are dropped
+    pub fn poke(&mut self, addr: usize, bytes: &[u8]) {
+        for (dst, byte) in self.ram.as_mut_slice().iter_mut().skip(addr).zip(bytes) {
+            *dst = *byte;
+        }
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.timer.get()
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.timer.set(value);
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.audio.timer()
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.audio.set_timer(value);
+    }
+
+    pub fn stack_pointer(&self) -> usize {
+        self.cpu.sp()
+    }
+
+    // return addresses currently on the call stack, oldest first
+    pub fn stack(&self) -> Vec<u16> {
+        self.cpu.stack().to_vec()
+    }
+
+    // set once the rom executes 00FD (SUPER-CHIP exit)
+    pub fn is_halted(&self) -> bool {
+        self.cpu.halted()
+    }
+
+    // Assembler-style listing of `len` bytes of RAM from `start`. Code is found by following
+    // execution from `start`, the program start, the current PC and the return addresses.
+    pub fn disassemble(&self, start: usize, len: usize) -> String {
+        let mut entries = vec![start, PRG_OFFSET, self.cpu.pc()];
+        entries.extend(self.cpu.stack().iter().map(|&addr| addr as usize));
+
+        let lines = disasm::disassemble(self.ram.as_slice(), start, len, &entries);
+        disasm::listing(&lines)
+    }
+
+    // current display size, 128x64 while in SUPER-CHIP hires mode
     pub fn width(&self) -> u32 {
-        WIDTH
+        self.display.width()
     }
 
     pub fn height(&self) -> u32 {
-        HEIGHT
+        self.display.height()
+    }
+
+    // Run one cpu tick, starting a new frame first if needed and finishing it once the
+    // frame's budget is used up or the cpu halts or waits for vblank
+    fn tick(&mut self) -> Result<(), ExecError> {
+        if !self.in_frame {
+            self.begin_frame();
+        }
+
+        let pending = if self.trace.is_enabled() {
+            self.trace.begin(&self.cpu, &self.ram, &self.timer, &self.audio)
+        } else {
+            None
+        };
+
+        let cycles = self.cpu.tick(&mut self.ram, &self.keyboard, &mut self.display, &mut self.timer, &mut self.audio, &mut self.rng)?;
+        self.cycle += match self.timing {
+            timing::Timing::Fixed => 1,
+            timing::Timing::Vip => cycles
+        };
+
+        if let Some(pending) = pending {
+            self.trace.finish(pending, &self.cpu, &self.timer, &self.audio);
+        }
+
+        if self.cycle >= self.frame_budget() || self.cpu.halted() || self.cpu.waiting_vblank() {
+            self.end_frame();
+        }
+        Ok(())
+    }
+
+    fn begin_frame(&mut self) {
+        self.replay_keys();
+        let phase = self.timer_phase as u64 + self.config.timer_frequency as u64;
+        // the timers are a byte, counting down further changes nothing
+        for _ in 0 .. (phase / RENDER_RATE as u64).min(u8::MAX as u64) {
+            self.timer.decrement();
+            self.audio.decrement();
+        }
+        self.timer_phase = (phase % RENDER_RATE as u64) as u32;
+        self.rng.vblank();
+        self.cpu.vblank();
+        self.in_frame = true;
+    }
+
+    fn frame_budget(&self) -> u32 {
+        match self.timing {
+            timing::Timing::Fixed => self.config.instructions_per_frame,
+            timing::Timing::Vip => timing::VIP_FRAME_BUDGET
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.in_frame = false;
+        // an instruction still running when the frame ran out finishes in the next one
+        self.cycle = self.cycle.saturating_sub(self.frame_budget());
+        self.frame += 1;
+        if let Some((movie, _)) = &self.playback {
+            if self.frame >= movie.frames {
+                self.playback = None;
+            }
+        }
+
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.frame_done(|| self.save_state());
+            self.rewind = Some(rewind);
+        }
+    }
+
+    // feed the movie's key events for the frame about to run
+    fn replay_keys(&mut self) {
+        if let Some((movie, next)) = self.playback.as_mut() {
+            while let Some(event) = movie.events.get(*next) {
+                if event.frame > self.frame {
+                    break;
+                }
+
+                self.keyboard.key_change(event.key as usize, event.pressed);
+                *next += 1;
+            }
+        }
+    }
+
+    // After load_state or rewind moved the frame count, drop the recorded input the
+    // restored machine never saw and record the keys it holds, so the movie replays to
+    // the same machine. A movie being played picks up at the restored frame.
+    fn resync_movie(&mut self) {
+        let frame = self.frame;
+        if let Some(movie) = self.recording.as_mut() {
+            movie.events.retain(|e| e.frame < frame);
+
+            let mut held = [false; 16];
+            for event in &movie.events {
+                held[event.key as usize] = event.pressed;
+            }
+            for (key, &was) in held.iter().enumerate() {
+                let pressed = self.keyboard.is_pressed(key);
+                if pressed != was {
+                    movie.events.push(movie::KeyEvent { frame, key: key as u8, pressed });
+                }
+            }
+        }
+
+        if let Some((movie, next)) = self.playback.as_mut() {
+            *next = movie.events.partition_point(|e| e.frame < frame);
+        }
     }
 
     fn load_fonts(&mut self) {
-        // TODO: move this data to a proper data file
-
-        // 0
-        let mut addr = 0;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0x90; // 1001
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 1
-        addr += 5;
-        self.ram[addr + 0] = 0x20; // 0010
-        self.ram[addr + 1] = 0x60; // 0110
-        self.ram[addr + 2] = 0x20; // 0010
-        self.ram[addr + 3] = 0x20; // 0010
-        self.ram[addr + 4] = 0x70; // 0111
-
-        // 2
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x10; // 0001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x80; // 1000
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 3
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x10; // 0001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x10; // 0001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 4
-        addr += 5;
-        self.ram[addr + 0] = 0x90; // 1001
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x10; // 0001
-        self.ram[addr + 4] = 0x10; // 0001
-
-        // 5
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x80; // 1000
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x10; // 0001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 6
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x80; // 1000
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 7
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x10; // 0001
-        self.ram[addr + 2] = 0x20; // 0010
-        self.ram[addr + 3] = 0x40; // 0100
-        self.ram[addr + 4] = 0x40; // 0100
-
-        // 8
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // 9
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x10; // 0001
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // A
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0x90; // 1001
-
-        // B
-        addr += 5;
-        self.ram[addr + 0] = 0xe0; // 1110
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0xe0; // 1110
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0xe0; // 1110
-
-        // C
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x80; // 1000
-        self.ram[addr + 2] = 0x80; // 1000
-        self.ram[addr + 3] = 0x80; // 1000
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // D
-        addr += 5;
-        self.ram[addr + 0] = 0xe0; // 1110
-        self.ram[addr + 1] = 0x90; // 1001
-        self.ram[addr + 2] = 0x90; // 1001
-        self.ram[addr + 3] = 0x90; // 1001
-        self.ram[addr + 4] = 0xe0; // 1110
-
-        // E
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x80; // 1000
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x80; // 1000
-        self.ram[addr + 4] = 0xf0; // 1111
-
-        // F
-        addr += 5;
-        self.ram[addr + 0] = 0xf0; // 1111
-        self.ram[addr + 1] = 0x80; // 1000
-        self.ram[addr + 2] = 0xf0; // 1111
-        self.ram[addr + 3] = 0x80; // 1000
-        self.ram[addr + 4] = 0x80; // 1000
+        let ram = self.ram.as_mut_slice();
+        ram[FONT_OFFSET .. FONT_OFFSET + FONT_SET.len()].copy_from_slice(&FONT_SET);
+        ram[BIG_FONT_OFFSET .. BIG_FONT_OFFSET + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+    }
+}
+
+// Not available to JS
+impl Emulator {
+    // write each executed instruction to `writer` as a line of trace_log
+    pub fn trace_to_writer(&mut self, writer: Box<dyn std::io::Write>) {
+        self.trace.log_to_writer(writer);
+    }
+
+    pub fn trace_entries(&self) -> Vec<trace::Entry> {
+        self.trace.entries()
+    }
+
+    pub fn display(&self) -> &display::DisplayFrame {
+        &self.display
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
     }
 }
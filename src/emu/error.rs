@@ -0,0 +1,92 @@
+use std::{ error, fmt };
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpcode,
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfBounds,
+    PcOutOfRange
+}
+
+// Raised by Cpu::tick instead of panicking, so the host can pause or inspect the machine.
+// `pc` is the address of the faulting instruction, `addr` the offending address (if any).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecError {
+    kind: ErrorKind,
+    pc: u16,
+    opcode: u16,
+    // wider than pc, an XO-CHIP access can run past 0xFFFF
+    addr: u32
+}
+
+impl ExecError {
+    pub fn new(kind: ErrorKind, pc: u16, opcode: u16, addr: u32) -> ExecError {
+        ExecError {
+            kind,
+            pc,
+            opcode,
+            addr
+        }
+    }
+
+    pub fn unknown_opcode(pc: u16, opcode: u16) -> ExecError {
+        ExecError::new(ErrorKind::UnknownOpcode, pc, opcode, pc as u32)
+    }
+
+    pub fn stack_underflow(pc: u16, opcode: u16) -> ExecError {
+        ExecError::new(ErrorKind::StackUnderflow, pc, opcode, pc as u32)
+    }
+
+    pub fn stack_overflow(pc: u16, opcode: u16) -> ExecError {
+        ExecError::new(ErrorKind::StackOverflow, pc, opcode, pc as u32)
+    }
+
+    pub fn out_of_bounds(pc: u16, opcode: u16, addr: usize) -> ExecError {
+        ExecError::new(ErrorKind::MemoryOutOfBounds, pc, opcode, addr as u32)
+    }
+
+    pub fn pc_out_of_range(pc: u16) -> ExecError {
+        ExecError::new(ErrorKind::PcOutOfRange, pc, 0, pc as u32)
+    }
+}
+
+#[wasm_bindgen]
+impl ExecError {
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    pub fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::UnknownOpcode => write!(f, "Unknown opcode 0x{:04x} at 0x{:03x}", self.opcode, self.pc),
+            ErrorKind::StackUnderflow => write!(f, "Stack underflow on return at 0x{:03x}", self.pc),
+            ErrorKind::StackOverflow => write!(f, "Stack overflow on call at 0x{:03x}", self.pc),
+            ErrorKind::MemoryOutOfBounds => write!(f, "Memory access out of bounds (0x{:x}) at 0x{:03x}", self.addr, self.pc),
+            ErrorKind::PcOutOfRange => write!(f, "Program counter out of range at 0x{:03x}", self.pc)
+        }
+    }
+}
+
+impl error::Error for ExecError {}
@@ -0,0 +1,308 @@
+// GDB remote serial protocol over TCP, enough for gdb or any RSP client to inspect
+// and drive the emulator. There is no CHIP-8 architecture in gdb, so registers go out
+// in this order, each in big endian like the rest of the machine:
+//
+//   V0 - VF  1 byte each
+//   I        2 bytes
+//   PC       2 bytes
+//   SP       1 byte, read only
+//   DT, ST   1 byte each
+
+use std::io::{ self, Read, Write };
+use std::net::TcpStream;
+use super::debugger::BreakReason;
+use super::emulator::Emulator;
+use super::error::{ ErrorKind, ExecError };
+
+// frames run between checks for an interrupt from the client
+const FRAMES_PER_POLL: u32 = 1;
+
+// (size in bytes) for each register in the order above
+const REGISTERS: [usize; 21] = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1];
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct Server<'a> {
+    emulator: &'a mut Emulator,
+    stream: TcpStream,
+    // bytes read from the stream but not yet consumed
+    pending: Vec<u8>,
+    // cleared by QStartNoAckMode
+    ack: bool
+}
+
+// Serve one client until it detaches, kills the session or disconnects
+pub fn serve(emulator: &mut Emulator, stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut server = Server {
+        emulator,
+        stream,
+        pending: Vec::new(),
+        ack: true
+    };
+    server.run()
+}
+
+impl Server<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.write_packet("OK")?;
+                    return Ok(());
+                }
+                _ => self.handle(&packet)
+            };
+            self.write_packet(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> String {
+        if packet.is_empty() {
+            return String::new();
+        }
+
+        let (command, args) = packet.split_at(1);
+        let result = match command {
+            "?" => Some(format!("S{:02x}", SIGTRAP)),
+            "g" => Some(to_hex(&self.registers())),
+            "G" => from_hex(args).map(|bytes| {
+                self.set_registers(&bytes);
+                "OK".to_string()
+            }),
+            "p" => usize::from_str_radix(args, 16).ok().map(|n| self.register(n)),
+            "m" => parse_range(args).and_then(|(addr, len)| {
+                let bytes = self.emulator.peek(addr, len);
+                if bytes.is_empty() && len > 0 { None } else { Some(to_hex(&bytes)) }
+            }),
+            "M" => args.split_once(':')
+                .and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)))
+                .filter(|((addr, len), bytes)| {
+                    bytes.len() == *len && addr.checked_add(*len).is_some_and(|end| end <= self.emulator.ram_size())
+                })
+                .map(|((addr, _), bytes)| {
+                    self.emulator.poke(addr, &bytes);
+                    "OK".to_string()
+                }),
+            "s" | "c" => {
+                if let Ok(addr) = usize::from_str_radix(args, 16) {
+                    self.emulator.set_pc(addr);
+                }
+                Some(if command == "s" { self.step() } else { self.resume() })
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => Some("OK".to_string()),
+            "q" | "Q" => Some(self.query(packet)),
+            // anything else is unsupported, which gdb takes as an empty reply
+            _ => Some(String::new())
+        };
+
+        result.unwrap_or_else(|| "E01".to_string())
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        match packet.split(':').next().unwrap_or("") {
+            "qSupported" => "PacketSize=1000;QStartNoAckMode+".to_string(),
+            "QStartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new()
+        }
+    }
+
+    // Z0 and Z1, software and hardware breakpoints, both end up in the debugger
+    fn breakpoint(&mut self, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let addr = usize::from_str_radix(fields.next()?, 16).ok()?;
+        if kind != "0" && kind != "1" {
+            return Some(String::new());
+        }
+
+        if insert {
+            self.emulator.add_breakpoint(addr);
+        } else {
+            self.emulator.remove_breakpoint(addr);
+        }
+        Some("OK".to_string())
+    }
+
+    fn step(&mut self) -> String {
+        let result = self.emulator.step();
+        self.stop_reply(result)
+    }
+
+    // Run until a breakpoint, a fault or an interrupt (0x03) from the client
+    fn resume(&mut self) -> String {
+        loop {
+            match self.emulator.run_until_break(FRAMES_PER_POLL) {
+                Ok(BreakReason::FrameLimit) => (),
+                result => return self.stop_reply(result)
+            }
+
+            if self.interrupted() {
+                return format!("S{:02x}", SIGINT);
+            }
+        }
+    }
+
+    fn stop_reply(&self, result: Result<BreakReason, ExecError>) -> String {
+        let signal = match result {
+            Ok(BreakReason::Halted) => return "W00".to_string(),
+            Ok(_) => SIGTRAP,
+            Err(e) if e.kind() == ErrorKind::UnknownOpcode => SIGILL,
+            Err(_) => SIGSEGV
+        };
+        format!("S{:02x}", signal)
+    }
+
+    // whether the client sent a break while the emulator was running
+    fn interrupted(&mut self) -> bool {
+        let mut buf = [0; 64];
+        if self.stream.set_nonblocking(true).is_ok() {
+            if let Ok(n) = self.stream.read(&mut buf) {
+                self.pending.extend_from_slice(&buf[.. n]);
+            }
+            self.stream.set_nonblocking(false).ok();
+        }
+
+        match self.pending.iter().position(|&b| b == 0x03) {
+            Some(at) => {
+                self.pending.remove(at);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let e = &self.emulator;
+        let mut bytes = e.registers();
+        bytes.extend_from_slice(&e.index().to_be_bytes());
+        bytes.extend_from_slice(&(e.pc() as u16).to_be_bytes());
+        bytes.extend_from_slice(&[e.stack_pointer() as u8, e.delay_timer(), e.sound_timer()]);
+        bytes
+    }
+
+    fn register(&self, n: usize) -> String {
+        let offset: usize = REGISTERS.iter().take(n).sum();
+        match REGISTERS.get(n) {
+            Some(&size) => to_hex(&self.registers()[offset .. offset + size]),
+            None => "E01".to_string()
+        }
+    }
+
+    fn set_registers(&mut self, bytes: &[u8]) {
+        let e = &mut self.emulator;
+        let word = |at: usize| bytes.get(at .. at + 2).map(|w| u16::from_be_bytes([w[0], w[1]]));
+
+        for (x, &value) in bytes.iter().take(16).enumerate() {
+            e.set_register(x, value);
+        }
+        if let Some(i) = word(16) {
+            e.set_index(i);
+        }
+        if let Some(pc) = word(18) {
+            e.set_pc(pc as usize);
+        }
+        if let Some(&dt) = bytes.get(21) {
+            e.set_delay_timer(dt);
+        }
+        if let Some(&st) = bytes.get(22) {
+            e.set_sound_timer(st);
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            let mut buf = [0; 1024];
+            let n = self.stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            self.pending.extend_from_slice(&buf[.. n]);
+        }
+
+        Ok(Some(self.pending.remove(0)))
+    }
+
+    // The next well formed packet's contents, None once the client disconnects.
+    // Acks and stray interrupts between packets are skipped.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(_) => continue
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b)
+                }
+            }
+
+            let mut checksum = [0; 2];
+            for c in &mut checksum {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b) => *c = b
+                }
+            }
+
+            let valid = std::str::from_utf8(&checksum).ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .is_some_and(|c| c == checksum_of(&data));
+            if self.ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0 .. hex.len()).step_by(2)
+        .map(|i| hex.get(i .. i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+// "addr,len" in hex
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
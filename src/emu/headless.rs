@@ -0,0 +1,145 @@
+use super::debugger::BreakReason;
+use super::display::DisplayFrame;
+use super::emulator::Emulator;
+use super::error::ExecError;
+use super::movie::KeyEvent;
+
+// Conditions that end a headless run before its frame limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Until {
+    // the PC arrives at this address, the instruction there hasn't run yet
+    Pc(usize),
+    // DisplayFrame::hash matches at the end of a frame
+    DisplayHash(u32),
+    // this many instructions have run
+    Cycles(u64)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    FrameLimit,
+    Until(Until),
+    // the rom executed 00FD
+    Halted,
+    Fault(ExecError)
+}
+
+// Where a headless run ended up
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub reason: StopReason,
+    // frames since the rom was loaded
+    pub frames: u32,
+    // instructions run by this run
+    pub cycles: u64,
+    pub display: DisplayFrame,
+    pub registers: Vec<u8>,
+    pub pc: usize,
+    pub i: u16
+}
+
+// Runs a rom with no screen, keyboard or clock, for tests and CI. Input comes from a
+// script of key events, each applied before the first instruction of its frame, with
+// frames counted from when the rom was loaded as frame_count does.
+pub struct Headless {
+    emulator: Emulator,
+    // sorted by frame, events for the same frame kept in the order they were added
+    keys: Vec<KeyEvent>,
+    next_key: usize
+}
+
+impl Headless {
+    pub fn new(rom: Vec<u8>) -> Headless {
+        Headless::with_emulator(Emulator::new(), rom)
+    }
+
+    // `emulator` keeps its settings (quirks, timing, config, rng)
+    pub fn with_emulator(mut emulator: Emulator, rom: Vec<u8>) -> Headless {
+        emulator.load_rom(rom);
+        Headless {
+            emulator,
+            keys: Vec::new(),
+            next_key: 0
+        }
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+
+    // Press or release `key` at the start of `frame`. Events for frames already run are
+    // applied before the next instruction.
+    pub fn key(&mut self, frame: u32, key: u8, pressed: bool) -> &mut Headless {
+        let at = self.next_key + self.keys[self.next_key ..].partition_point(|e| e.frame <= frame);
+        self.keys.insert(at, KeyEvent { frame, key, pressed });
+        self
+    }
+
+    // hold `key` down for `frames` frames starting at `frame`
+    pub fn tap(&mut self, frame: u32, key: u8, frames: u32) -> &mut Headless {
+        self.key(frame, key, true).key(frame + frames, key, false)
+    }
+
+    // Run for up to `frames` frames, stopping early at the first of `until` to hold
+    pub fn run(&mut self, frames: u32, until: &[Until]) -> Outcome {
+        let start = self.emulator.frame_count();
+        let mut cycles = 0;
+
+        let reason = loop {
+            self.feed_keys();
+
+            if self.emulator.frame_count().wrapping_sub(start) >= frames {
+                break StopReason::FrameLimit;
+            }
+
+            let pc = self.emulator.pc();
+            let hit = until.iter().find(|u| match **u {
+                Until::Pc(addr) => cycles > 0 && addr == pc,
+                Until::Cycles(limit) => cycles >= limit,
+                Until::DisplayHash(_) => false
+            });
+            if let Some(&hit) = hit {
+                break StopReason::Until(hit);
+            }
+
+            let frame = self.emulator.frame_count();
+            match self.emulator.step() {
+                Ok(BreakReason::Halted) => break StopReason::Halted,
+                Ok(_) => cycles += 1,
+                Err(e) => break StopReason::Fault(e)
+            }
+
+            if self.emulator.frame_count() != frame {
+                let hash = self.emulator.display_hash();
+                if let Some(&hit) = until.iter().find(|&&u| u == Until::DisplayHash(hash)) {
+                    break StopReason::Until(hit);
+                }
+            }
+        };
+
+        Outcome {
+            reason,
+            frames: self.emulator.frame_count(),
+            cycles,
+            display: self.emulator.display().clone(),
+            registers: self.emulator.registers(),
+            pc: self.emulator.pc(),
+            i: self.emulator.index()
+        }
+    }
+
+    fn feed_keys(&mut self) {
+        while let Some(event) = self.keys.get(self.next_key) {
+            if event.frame > self.emulator.frame_count() {
+                break;
+            }
+
+            self.emulator.key_change(event.key as usize, event.pressed);
+            self.next_key += 1;
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+
+// A decoded opcode. Cpu::tick and the disassembler both go through Instruction::decode,
+// so anything the cpu executes is something the disassembler can name and vice versa.
+// Registers are indexes into V, immediates keep the width they have in the opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // 0NNN, machine code routine (not supported)
+    Sys(u16),
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    // 00DN (XO-CHIP)
+    ScrollUp(u8),
+    // 00FB (SUPER-CHIP)
+    ScrollRight,
+    // 00FC (SUPER-CHIP)
+    ScrollLeft,
+    // 00FD (SUPER-CHIP)
+    Exit,
+    // 00FE (SUPER-CHIP)
+    Lores,
+    // 00FF (SUPER-CHIP)
+    Hires,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqImm(u8, u8),
+    // 4XNN
+    SkipNeImm(u8, u8),
+    // 5XY0
+    SkipEq(u8, u8),
+    // 5XY2 (XO-CHIP)
+    SaveRange(u8, u8),
+    // 5XY3 (XO-CHIP)
+    LoadRange(u8, u8),
+    // 6XNN
+    LoadImm(u8, u8),
+    // 7XNN
+    AddImm(u8, u8),
+    // 8XY0
+    Move(u8, u8),
+    // 8XY1
+    Or(u8, u8),
+    // 8XY2
+    And(u8, u8),
+    // 8XY3
+    Xor(u8, u8),
+    // 8XY4
+    Add(u8, u8),
+    // 8XY5
+    Sub(u8, u8),
+    // 8XY6
+    ShiftRight(u8, u8),
+    // 8XY7
+    SubReverse(u8, u8),
+    // 8XYE
+    ShiftLeft(u8, u8),
+    // 9XY0
+    SkipNe(u8, u8),
+    // ANNN
+    LoadI(u16),
+    // BNNN, or BXNN under the jump_vx quirk
+    JumpOffset(u16),
+    // CXNN
+    Random(u8, u8),
+    // DXYN
+    Draw(u8, u8, u8),
+    // EX9E
+    SkipKey(u8),
+    // EXA1
+    SkipNotKey(u8),
+    // F000 NNNN (XO-CHIP), the address is the word after the opcode
+    LoadLongI,
+    // FN01 (XO-CHIP)
+    Plane(u8),
+    // F002 (XO-CHIP)
+    Audio,
+    // FX07
+    GetDelay(u8),
+    // FX0A
+    WaitKey(u8),
+    // FX15
+    SetDelay(u8),
+    // FX18
+    SetSound(u8),
+    // FX1E
+    AddI(u8),
+    // FX29
+    Font(u8),
+    // FX30 (SUPER-CHIP)
+    BigFont(u8),
+    // FX33
+    Bcd(u8),
+    // FX3A (XO-CHIP)
+    Pitch(u8),
+    // FX55
+    Store(u8),
+    // FX65
+    Load(u8),
+    // FX75 (SUPER-CHIP)
+    SaveFlags(u8),
+    // FX85 (SUPER-CHIP)
+    LoadFlags(u8)
+}
+
+impl Instruction {
+    // None for opcodes no supported platform defines
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        use self::Instruction::*;
+
+        // Decompose opcode into 4 nibbles
+        let a = (opcode >> 12) as u8;
+        let x = (opcode >> 8 & 0xf) as u8;
+        let y = (opcode >> 4 & 0xf) as u8;
+        let n = (opcode & 0xf) as u8;
+        let nn = (opcode & 0xff) as u8;
+        let nnn = opcode & 0xfff;
+
+        let instruction = match (a, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => Cls,
+            (0x0, 0x0, 0xE, 0xE) => Ret,
+            (0x0, 0x0, 0xC, n) => ScrollDown(n),
+            (0x0, 0x0, 0xD, n) => ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Exit,
+            (0x0, 0x0, 0xF, 0xE) => Lores,
+            (0x0, 0x0, 0xF, 0xF) => Hires,
+            (0x0, _, _, _) => Sys(nnn),
+            (0x1, _, _, _) => Jump(nnn),
+            (0x2, _, _, _) => Call(nnn),
+            (0x3, x, _, _) => SkipEqImm(x, nn),
+            (0x4, x, _, _) => SkipNeImm(x, nn),
+            (0x5, x, y, 0x0) => SkipEq(x, y),
+            (0x5, x, y, 0x2) => SaveRange(x, y),
+            (0x5, x, y, 0x3) => LoadRange(x, y),
+            (0x6, x, _, _) => LoadImm(x, nn),
+            (0x7, x, _, _) => AddImm(x, nn),
+            (0x8, x, y, 0x0) => Move(x, y),
+            (0x8, x, y, 0x1) => Or(x, y),
+            (0x8, x, y, 0x2) => And(x, y),
+            (0x8, x, y, 0x3) => Xor(x, y),
+            (0x8, x, y, 0x4) => Add(x, y),
+            (0x8, x, y, 0x5) => Sub(x, y),
+            (0x8, x, y, 0x6) => ShiftRight(x, y),
+            (0x8, x, y, 0x7) => SubReverse(x, y),
+            (0x8, x, y, 0xE) => ShiftLeft(x, y),
+            (0x9, x, y, 0x0) => SkipNe(x, y),
+            (0xA, _, _, _) => LoadI(nnn),
+            (0xB, _, _, _) => JumpOffset(nnn),
+            (0xC, x, _, _) => Random(x, nn),
+            (0xD, x, y, n) => Draw(x, y, n),
+            (0xE, x, 0x9, 0xE) => SkipKey(x),
+            (0xE, x, 0xA, 0x1) => SkipNotKey(x),
+            (0xF, 0x0, 0x0, 0x0) => LoadLongI,
+            (0xF, n, 0x0, 0x1) => Plane(n),
+            (0xF, 0x0, 0x0, 0x2) => Audio,
+            (0xF, x, 0x0, 0x7) => GetDelay(x),
+            (0xF, x, 0x0, 0xA) => WaitKey(x),
+            (0xF, x, 0x1, 0x5) => SetDelay(x),
+            (0xF, x, 0x1, 0x8) => SetSound(x),
+            (0xF, x, 0x1, 0xE) => AddI(x),
+            (0xF, x, 0x2, 0x9) => Font(x),
+            (0xF, x, 0x3, 0x0) => BigFont(x),
+            (0xF, x, 0x3, 0x3) => Bcd(x),
+            (0xF, x, 0x3, 0xA) => Pitch(x),
+            (0xF, x, 0x5, 0x5) => Store(x),
+            (0xF, x, 0x6, 0x5) => Load(x),
+            (0xF, x, 0x7, 0x5) => SaveFlags(x),
+            (0xF, x, 0x8, 0x5) => LoadFlags(x),
+            _ => return None
+        };
+
+        Some(instruction)
+    }
+
+    // Inverse of decode, for the assembler. Operands are masked to the width of their field.
+    pub fn encode(&self) -> u16 {
+        use self::Instruction::*;
+
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16 & 0xf) << 8 | (y as u16 & 0xf) << 4 | n;
+        let xnn = |op: u16, x: u8, nn: u8| op | (x as u16 & 0xf) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16 & 0xf) << 8 | nn;
+        match *self {
+            Sys(nnn) => nnn & 0xfff,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xf),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xf),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump(nnn) => 0x1000 | (nnn & 0xfff),
+            Call(nnn) => 0x2000 | (nnn & 0xfff),
+            SkipEqImm(x, nn) => xnn(0x3000, x, nn),
+            SkipNeImm(x, nn) => xnn(0x4000, x, nn),
+            SkipEq(x, y) => xy(0x5000, x, y, 0x0),
+            SaveRange(x, y) => xy(0x5000, x, y, 0x2),
+            LoadRange(x, y) => xy(0x5000, x, y, 0x3),
+            LoadImm(x, nn) => xnn(0x6000, x, nn),
+            AddImm(x, nn) => xnn(0x7000, x, nn),
+            Move(x, y) => xy(0x8000, x, y, 0x0),
+            Or(x, y) => xy(0x8000, x, y, 0x1),
+            And(x, y) => xy(0x8000, x, y, 0x2),
+            Xor(x, y) => xy(0x8000, x, y, 0x3),
+            Add(x, y) => xy(0x8000, x, y, 0x4),
+            Sub(x, y) => xy(0x8000, x, y, 0x5),
+            ShiftRight(x, y) => xy(0x8000, x, y, 0x6),
+            SubReverse(x, y) => xy(0x8000, x, y, 0x7),
+            ShiftLeft(x, y) => xy(0x8000, x, y, 0xE),
+            SkipNe(x, y) => xy(0x9000, x, y, 0x0),
+            LoadI(nnn) => 0xA000 | (nnn & 0xfff),
+            JumpOffset(nnn) => 0xB000 | (nnn & 0xfff),
+            Random(x, nn) => xnn(0xC000, x, nn),
+            Draw(x, y, n) => xy(0xD000, x, y, n as u16 & 0xf),
+            SkipKey(x) => xnn(0xE000, x, 0x9E),
+            SkipNotKey(x) => xnn(0xE000, x, 0xA1),
+            LoadLongI => 0xF000,
+            Plane(n) => fx(n, 0x01),
+            Audio => 0xF002,
+            GetDelay(x) => fx(x, 0x07),
+            WaitKey(x) => fx(x, 0x0A),
+            SetDelay(x) => fx(x, 0x15),
+            SetSound(x) => fx(x, 0x18),
+            AddI(x) => fx(x, 0x1E),
+            Font(x) => fx(x, 0x29),
+            BigFont(x) => fx(x, 0x30),
+            Bcd(x) => fx(x, 0x33),
+            Pitch(x) => fx(x, 0x3A),
+            Store(x) => fx(x, 0x55),
+            Load(x) => fx(x, 0x65),
+            SaveFlags(x) => fx(x, 0x75),
+            LoadFlags(x) => fx(x, 0x85)
+        }
+    }
+
+    // size in bytes, including the address word of F000 NNNN
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLongI => 4,
+            _ => 2
+        }
+    }
+
+    // Whether execution can continue with the instruction after this one. Skips can also
+    // jump over the next instruction, which callers handle separately.
+    pub fn falls_through(&self) -> bool {
+        !matches!(self, Instruction::Ret | Instruction::Exit | Instruction::Jump(_) | Instruction::JumpOffset(_))
+    }
+
+    pub fn is_skip(&self) -> bool {
+        matches!(self,
+            Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..) |
+            Instruction::SkipEq(..) | Instruction::SkipNe(..) |
+            Instruction::SkipKey(_) | Instruction::SkipNotKey(_))
+    }
+
+    // Mnemonic with the address operand rendered by `addr`, so the disassembler can
+    // substitute labels. `long` is the word following F000.
+    pub fn format<F: Fn(u16) -> String>(&self, long: u16, addr: F) -> String {
+        use self::Instruction::*;
+
+        let v = |r: &u8| format!("V{:X}", r);
+        match self {
+            Sys(nnn) => format!("SYS {}", addr(*nnn)),
+            Cls => "CLS".to_string(),
+            Ret => "RET".to_string(),
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Lores => "LOW".to_string(),
+            Hires => "HIGH".to_string(),
+            Jump(nnn) => format!("JP {}", addr(*nnn)),
+            Call(nnn) => format!("CALL {}", addr(*nnn)),
+            SkipEqImm(x, nn) => format!("SE {}, 0x{:02X}", v(x), nn),
+            SkipNeImm(x, nn) => format!("SNE {}, 0x{:02X}", v(x), nn),
+            SkipEq(x, y) => format!("SE {}, {}", v(x), v(y)),
+            SaveRange(x, y) => format!("SAVE {} - {}", v(x), v(y)),
+            LoadRange(x, y) => format!("LOAD {} - {}", v(x), v(y)),
+            LoadImm(x, nn) => format!("LD {}, 0x{:02X}", v(x), nn),
+            AddImm(x, nn) => format!("ADD {}, 0x{:02X}", v(x), nn),
+            Move(x, y) => format!("LD {}, {}", v(x), v(y)),
+            Or(x, y) => format!("OR {}, {}", v(x), v(y)),
+            And(x, y) => format!("AND {}, {}", v(x), v(y)),
+            Xor(x, y) => format!("XOR {}, {}", v(x), v(y)),
+            Add(x, y) => format!("ADD {}, {}", v(x), v(y)),
+            Sub(x, y) => format!("SUB {}, {}", v(x), v(y)),
+            ShiftRight(x, y) => format!("SHR {}, {}", v(x), v(y)),
+            SubReverse(x, y) => format!("SUBN {}, {}", v(x), v(y)),
+            ShiftLeft(x, y) => format!("SHL {}, {}", v(x), v(y)),
+            SkipNe(x, y) => format!("SNE {}, {}", v(x), v(y)),
+            LoadI(nnn) => format!("LD I, {}", addr(*nnn)),
+            JumpOffset(nnn) => format!("JP V0, {}", addr(*nnn)),
+            Random(x, nn) => format!("RND {}, 0x{:02X}", v(x), nn),
+            Draw(x, y, n) => format!("DRW {}, {}, {}", v(x), v(y), n),
+            SkipKey(x) => format!("SKP {}", v(x)),
+            SkipNotKey(x) => format!("SKNP {}", v(x)),
+            LoadLongI => format!("LD I, LONG {}", addr(long)),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            GetDelay(x) => format!("LD {}, DT", v(x)),
+            WaitKey(x) => format!("LD {}, K", v(x)),
+            SetDelay(x) => format!("LD DT, {}", v(x)),
+            SetSound(x) => format!("LD ST, {}", v(x)),
+            AddI(x) => format!("ADD I, {}", v(x)),
+            Font(x) => format!("LD F, {}", v(x)),
+            BigFont(x) => format!("LD HF, {}", v(x)),
+            Bcd(x) => format!("LD B, {}", v(x)),
+            Pitch(x) => format!("PITCH {}", v(x)),
+            Store(x) => format!("LD [I], {}", v(x)),
+            Load(x) => format!("LD {}, [I]", v(x)),
+            SaveFlags(x) => format!("LD R, {}", v(x)),
+            LoadFlags(x) => format!("LD {}, R", v(x))
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addr = |a: u16| format!("0x{:03X}", a);
+        match self {
+            // the address isn't part of the opcode
+            Instruction::LoadLongI => write!(f, "LD I, LONG"),
+            _ => write!(f, "{}", self.format(0, addr))
+        }
+    }
+}
@@ -1,10 +1,4 @@
-extern crate web_sys;
-
-macro_rules! log {
-    ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
-    }
-}
+use super::state::{ StateError, StateReader, StateWriter };
 
 const N_KEYS: usize = 16;
 
@@ -21,15 +15,39 @@ impl Keyboard {
         }
     }
 
+    // keys outside of the 16-key hex pad are ignored
     pub fn key_change(&mut self, key: usize, pressed: bool) {
-        self.keys[key] = pressed;
+        if let Some(k) = self.keys.get_mut(key) {
+            *k = pressed;
+        }
     }
 
+    // only the low nibble is significant, as on the VIP
     pub fn is_pressed(&self, key: usize) -> bool {
-        self.keys[key]
+        self.keys[key & 0xf]
     }
 
     pub fn current_key(&self) -> Option<usize> {
         self.keys.iter().position(|&k| k)
     }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        for &key in &self.keys {
+            w.bool(key);
+        }
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for key in self.keys.iter_mut() {
+            *key = r.bool()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
 }
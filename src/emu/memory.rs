@@ -0,0 +1,99 @@
+use wasm_bindgen::prelude::*;
+use super::emulator;
+use super::state::{ StateError, StateReader, StateWriter };
+
+// What happens when an instruction addresses memory past the end of ram
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    // addresses wrap around modulo the ram size, like the COSMAC VIP
+    Wrap,
+    // the access faults with ErrorKind::MemoryOutOfBounds
+    Trap
+}
+
+pub struct Memory {
+    bytes: Vec<u8>,
+    policy: MemoryPolicy
+}
+
+impl Memory {
+    pub fn new(size: usize, policy: MemoryPolicy) -> Memory {
+        let bytes = vec![0; size];
+
+        Memory {
+            bytes,
+            policy
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn policy(&self) -> MemoryPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: MemoryPolicy) {
+        self.policy = policy;
+    }
+
+    // grow or shrink ram, keeping the contents that still fit
+    pub fn resize(&mut self, size: usize) {
+        self.bytes.resize(size, 0);
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    // Map an address onto ram according to the policy, None if it faults
+    pub fn resolve(&self, addr: usize) -> Option<usize> {
+        match self.policy {
+            MemoryPolicy::Wrap => Some(addr % self.bytes.len()),
+            MemoryPolicy::Trap if addr < self.bytes.len() => Some(addr),
+            MemoryPolicy::Trap => None
+        }
+    }
+
+    pub fn read(&self, addr: usize) -> Option<u8> {
+        self.resolve(addr).map(|a| self.bytes[a])
+    }
+
+    pub fn write(&mut self, addr: usize, val: u8) -> Option<()> {
+        let a = self.resolve(addr)?;
+        self.bytes[a] = val;
+        Some(())
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.policy as u8);
+        w.bytes(&self.bytes);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.policy = match r.u8()? {
+            0 => MemoryPolicy::Wrap,
+            1 => MemoryPolicy::Trap,
+            _ => return Err(StateError::Invalid)
+        };
+
+        // anything smaller than the fonts would panic on the next load_rom
+        let bytes = r.bytes()?;
+        if bytes.len() != emulator::RAM_SIZE && bytes.len() != emulator::XO_RAM_SIZE {
+            return Err(StateError::Invalid);
+        }
+
+        self.bytes = bytes.to_vec();
+        Ok(())
+    }
+}
@@ -5,7 +5,65 @@ pub use self::universe::Cell;
 pub mod emulator;
 pub use self::emulator::Emulator;
 
+pub mod error;
+pub use self::error::{ ExecError, ErrorKind };
+
+pub mod memory;
+pub use self::memory::MemoryPolicy;
+
+pub mod quirks;
+pub use self::quirks::Quirks;
+
+pub mod state;
+pub use self::state::StateError;
+
+pub mod rewind;
+
+pub mod rng;
+pub use self::rng::RngKind;
+
+pub mod timing;
+pub use self::timing::Timing;
+
+pub mod config;
+pub use self::config::EmulatorConfig;
+
+pub mod movie;
+pub use self::movie::{ Movie, MovieError };
+
+pub mod instruction;
+pub use self::instruction::Instruction;
+
+pub mod disasm;
+
+pub mod asm;
+pub use self::asm::{ AsmError, Program };
+
+pub mod octo;
+
+pub mod debugger;
+pub use self::debugger::{ BreakReason, Compare, WatchKind };
+
+pub mod trace;
+pub use self::trace::OpcodeClass;
+
+// servers and golden files need a socket, stdio or the file system
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dap;
+
+pub mod headless;
+pub use self::headless::Headless;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshot;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::snapshot::Snapshot;
+
 pub mod cpu;
+pub mod audio;
 pub mod display;
 pub mod keyboard;
 pub mod timer;
@@ -0,0 +1,169 @@
+use std::{ error, fmt };
+use wasm_bindgen::prelude::*;
+use super::{ emulator, memory, quirks, rng, timing };
+use super::state::{ self, StateError, StateReader, StateWriter };
+
+// Movie layout, sharing the save state framing: magic, version, ROM CRC-32, rng kind
+// and seed, quirks (as in save states), timing, instructions per frame, timer frequency,
+// memory policy, ram size, stack depth, length in frames, then the key events, followed
+// by a CRC-32 of everything before it. Any other version is rejected.
+pub const MOVIE_MAGIC: [u8; 4] = *b"SKYM";
+pub const MOVIE_VERSION: u16 = 1;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion,
+    ChecksumMismatch,
+    Truncated,
+    Invalid,
+    // the movie was recorded with a different ROM than the one loaded
+    RomMismatch
+}
+
+impl From<StateError> for MovieError {
+    fn from(e: StateError) -> MovieError {
+        match e {
+            StateError::BadMagic => MovieError::BadMagic,
+            StateError::UnsupportedVersion => MovieError::UnsupportedVersion,
+            StateError::ChecksumMismatch => MovieError::ChecksumMismatch,
+            StateError::Truncated => MovieError::Truncated,
+            StateError::Invalid => MovieError::Invalid
+        }
+    }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            MovieError::BadMagic => "Not a skylark movie",
+            MovieError::UnsupportedVersion => "Movie version is not supported",
+            MovieError::ChecksumMismatch => "Movie checksum mismatch",
+            MovieError::Truncated => "Movie is truncated",
+            MovieError::Invalid => "Movie contains invalid data",
+            MovieError::RomMismatch => "Movie was recorded with a different ROM"
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl error::Error for MovieError {}
+
+// Emulator::key_change calls, stamped with the number of frames run before them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool
+}
+
+// Everything needed to rerun a session bit for bit from a reset machine
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    pub rom_hash: u32,
+    pub rng_kind: rng::RngKind,
+    pub seed: u32,
+    pub quirks: quirks::Quirks,
+    pub timing: timing::Timing,
+    pub instructions_per_frame: u32,
+    pub timer_frequency: u32,
+    pub memory_policy: memory::MemoryPolicy,
+    pub ram_size: u32,
+    pub stack_depth: u16,
+    pub frames: u32,
+    pub events: Vec<KeyEvent>
+}
+
+impl Movie {
+    pub fn rom_hash(rom: &[u8]) -> u32 {
+        state::crc32(rom)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::with_header(MOVIE_MAGIC, MOVIE_VERSION);
+        w.u32(self.rom_hash);
+        w.u8(self.rng_kind as u8);
+        w.u32(self.seed);
+        self.quirks.save_state(&mut w);
+        w.u8(self.timing as u8);
+        w.u32(self.instructions_per_frame);
+        w.u32(self.timer_frequency);
+        w.u8(self.memory_policy as u8);
+        w.u32(self.ram_size);
+        w.u16(self.stack_depth);
+        w.u32(self.frames);
+
+        w.u32(self.events.len() as u32);
+        for event in &self.events {
+            w.u32(event.frame);
+            w.u8(event.key);
+            w.bool(event.pressed);
+        }
+
+        w.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut r = StateReader::with_header(bytes, MOVIE_MAGIC, MOVIE_VERSION)?;
+
+        let rom_hash = r.u32()?;
+        let rng_kind = match r.u8()? {
+            0 => rng::RngKind::Xorshift,
+            1 => rng::RngKind::Vip,
+            _ => return Err(MovieError::Invalid)
+        };
+        let seed = r.u32()?;
+        let mut quirks = quirks::Quirks::default();
+        quirks.load_state(&mut r)?;
+        let timing = match r.u8()? {
+            0 => timing::Timing::Fixed,
+            1 => timing::Timing::Vip,
+            _ => return Err(MovieError::Invalid)
+        };
+        let instructions_per_frame = r.u32()?.max(1);
+        let timer_frequency = r.u32()?.max(1);
+        let memory_policy = match r.u8()? {
+            0 => memory::MemoryPolicy::Wrap,
+            1 => memory::MemoryPolicy::Trap,
+            _ => return Err(MovieError::Invalid)
+        };
+        let ram_size = r.u32()?;
+        let stack_depth = r.u16()?;
+        let frames = r.u32()?;
+
+        let count = r.u32()?;
+        let mut events = Vec::new();
+        for _ in 0 .. count {
+            let frame = r.u32()?;
+            let key = r.u8()?;
+            let pressed = r.bool()?;
+            events.push(KeyEvent { frame, key, pressed });
+        }
+        r.finish()?;
+
+        if events.windows(2).any(|w| w[0].frame > w[1].frame) {
+            return Err(MovieError::Invalid);
+        }
+        // the only sizes the emulator itself uses, anything else is corrupt
+        if ram_size as usize != emulator::RAM_SIZE && ram_size as usize != emulator::XO_RAM_SIZE {
+            return Err(MovieError::Invalid);
+        }
+
+        Ok(Movie {
+            rom_hash,
+            rng_kind,
+            seed,
+            quirks,
+            timing,
+            instructions_per_frame,
+            timer_frequency,
+            memory_policy,
+            ram_size,
+            stack_depth,
+            frames,
+            events
+        })
+    }
+}
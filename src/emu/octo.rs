@@ -0,0 +1,841 @@
+use std::collections::{ BTreeMap, HashMap };
+use super::asm::{ AsmError, Program, SourceLine };
+use super::emulator;
+use super::instruction::Instruction;
+
+// Compiler for Octo (https://github.com/JohnEarnest/Octo) programs. Covers the
+// instruction forms (`v0 := 5`, `i := long label`, `sprite v0 v1 5`, ...), labels and
+// calls by name, :const, :alias, :macro, :calc, :byte, :org, :unpack, :next, the
+// structured `if ... then`, `if ... begin ... else ... end`, `loop ... while ... again`,
+// and numbers in the program body as data bytes (how sprites are written).
+//
+// Like Octo, 0x200 holds a jump to the `main` label and the program follows it.
+
+// guards against macros that expand into themselves
+const MAX_EXPANSIONS: usize = 100_000;
+
+pub fn compile(name: &str, source: &str) -> Result<Program, AsmError> {
+    let mut compiler = Compiler::new(name, tokenize(source));
+    compiler.run()?;
+    compiler.finish()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize
+}
+
+// Octo is whitespace separated, comments run from # to the end of the line
+fn tokenize(source: &str) -> Vec<Token> {
+    source.lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace()
+                .map(move |word| Token { text: word.to_string(), line: index + 1 })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// how a label used before its definition gets patched in
+#[derive(Clone, Copy)]
+enum Fixup {
+    // low 12 bits of the opcode at the address
+    Addr,
+    // the word after F000
+    Long,
+    // low byte of v0 := NN, high nibble fixed
+    UnpackHigh(u8),
+    // low byte of v1 := NN
+    UnpackLow
+}
+
+struct Pending {
+    addr: usize,
+    fixup: Fixup,
+    name: String,
+    line: usize
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Key,
+    NotKey
+}
+
+impl Cmp {
+    fn negate(self) -> Cmp {
+        match self {
+            Cmp::Eq => Cmp::Ne,
+            Cmp::Ne => Cmp::Eq,
+            Cmp::Lt => Cmp::Ge,
+            Cmp::Ge => Cmp::Lt,
+            Cmp::Gt => Cmp::Le,
+            Cmp::Le => Cmp::Gt,
+            Cmp::Key => Cmp::NotKey,
+            Cmp::NotKey => Cmp::Key
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Rhs {
+    Reg(u8),
+    Imm(u8)
+}
+
+struct Loop {
+    start: usize,
+    // jumps out of the loop from `while`, patched at `again`
+    exits: Vec<usize>
+}
+
+struct Compiler {
+    file: String,
+    tokens: Vec<Token>,
+    pos: usize,
+    line: usize,
+    here: usize,
+    rom: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    expansions: usize,
+    pending: Vec<Pending>,
+    loops: Vec<Loop>,
+    // jumps of open if/else blocks, patched at else and end
+    branches: Vec<usize>,
+    lines: Vec<SourceLine>
+}
+
+impl Compiler {
+    fn new(file: &str, tokens: Vec<Token>) -> Compiler {
+        Compiler {
+            file: file.to_string(),
+            tokens,
+            pos: 0,
+            line: 1,
+            // 0x200 is kept for the jump to main
+            here: emulator::PRG_OFFSET + 2,
+            rom: vec![0; 2],
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            pending: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+            lines: Vec::new()
+        }
+    }
+
+    fn error(&self, message: String) -> AsmError {
+        AsmError::new(&self.file, self.line, message)
+    }
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        while self.pos < self.tokens.len() {
+            self.line = self.tokens[self.pos].line;
+            self.statement().map_err(|m| self.error(m))?;
+        }
+
+        if !self.loops.is_empty() {
+            return Err(self.error("loop without again".to_string()));
+        }
+        if !self.branches.is_empty() {
+            return Err(self.error("begin without end".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        let main = match self.labels.get("main") {
+            Some(&main) => main,
+            None => return Err(self.error("program has no main label".to_string()))
+        };
+        self.put_word(emulator::PRG_OFFSET, Instruction::Jump(main as u16).encode());
+
+        for p in std::mem::take(&mut self.pending) {
+            let target = match self.labels.get(&p.name) {
+                Some(&target) => target,
+                None => return Err(AsmError::new(&self.file, p.line, format!("{} is not defined", p.name)))
+            };
+            self.patch(p.addr, p.fixup, target).map_err(|m| AsmError::new(&self.file, p.line, m))?;
+        }
+
+        let labels = self.labels.iter()
+            .map(|(name, &addr)| (name.clone(), addr as u16))
+            .collect::<BTreeMap<_, _>>();
+
+        Ok(Program {
+            origin: emulator::PRG_OFFSET as u16,
+            bytes: self.rom,
+            labels,
+            lines: self.lines
+        })
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.text.clone())
+            }
+            None => Err("unexpected end of program".to_string())
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        let found = self.next()?;
+        if found == word {
+            Ok(())
+        } else {
+            Err(format!("expected {}, found {}", word, found))
+        }
+    }
+
+    fn put(&mut self, addr: usize, byte: u8) -> Result<(), String> {
+        if addr >= emulator::XO_RAM_SIZE {
+            return Err("program runs past the end of RAM".to_string());
+        }
+
+        let index = addr - emulator::PRG_OFFSET;
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = byte;
+        Ok(())
+    }
+
+    fn put_word(&mut self, addr: usize, word: u16) {
+        let index = addr - emulator::PRG_OFFSET;
+        self.rom[index] = (word >> 8) as u8;
+        self.rom[index + 1] = word as u8;
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.lines.last().map(|l| l.line) != Some(self.line) {
+            self.lines.push(SourceLine {
+                file: self.file.clone(),
+                line: self.line,
+                addr: self.here as u16
+            });
+        }
+
+        self.put(self.here, byte)?;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), String> {
+        let opcode = instruction.encode();
+        self.byte((opcode >> 8) as u8)?;
+        self.byte(opcode as u8)
+    }
+
+    fn patch(&mut self, addr: usize, fixup: Fixup, target: usize) -> Result<(), String> {
+        let index = addr - emulator::PRG_OFFSET;
+        match fixup {
+            Fixup::Addr => {
+                if target > 0xFFF {
+                    return Err(format!("address 0x{:X} doesn't fit in 12 bits", target));
+                }
+                self.rom[index] = self.rom[index] & 0xF0 | (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            }
+            Fixup::Long => {
+                self.rom[index] = (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            }
+            Fixup::UnpackHigh(nibble) => self.rom[index + 1] = nibble << 4 | (target >> 8 & 0xf) as u8,
+            Fixup::UnpackLow => self.rom[index + 1] = target as u8
+        }
+
+        Ok(())
+    }
+
+    // a label's address now, or a note to patch it in once it's defined
+    fn address(&mut self, addr: usize, fixup: Fixup) -> Result<usize, String> {
+        let name = self.next()?;
+        if let Some(&target) = self.labels.get(&name) {
+            return Ok(target);
+        }
+        if let Some(value) = self.lookup(&name) {
+            return Ok(value as usize);
+        }
+        if !is_name(&name) {
+            return Err(format!("expected an address, found {}", name));
+        }
+
+        self.pending.push(Pending {
+            addr,
+            fixup,
+            name,
+            line: self.line
+        });
+        Ok(0)
+    }
+
+    // numbers and constants
+    fn lookup(&self, word: &str) -> Option<i64> {
+        self.constants.get(word).cloned().or_else(|| parse_number(word))
+    }
+
+    fn value(&mut self) -> Result<i64, String> {
+        let word = self.next()?;
+        match self.lookup(&word) {
+            Some(value) => Ok(value),
+            None => match self.labels.get(&word) {
+                Some(&addr) => Ok(addr as i64),
+                None => Err(format!("{} is not a known value", word))
+            }
+        }
+    }
+
+    fn imm(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.value()?;
+        if value < min || value > max {
+            return Err(format!("value {} is out of range", value));
+        }
+        Ok(value)
+    }
+
+    fn byte_value(&mut self) -> Result<u8, String> {
+        Ok(self.imm(-0x80, 0xFF)? as u8)
+    }
+
+    fn nibble_value(&mut self) -> Result<u8, String> {
+        Ok(self.imm(0, 0xF)? as u8)
+    }
+
+    fn register_named(&self, word: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(word) {
+            return Some(reg);
+        }
+
+        let lower = word.to_ascii_lowercase();
+        let digit = lower.strip_prefix('v')?;
+        if digit.len() == 1 { u8::from_str_radix(digit, 16).ok() } else { None }
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let word = self.next()?;
+        self.register_named(&word).ok_or_else(|| format!("expected a register, found {}", word))
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<(), String> {
+        if !is_name(&name) {
+            return Err(format!("{} can't be used as a name", name));
+        }
+        if self.labels.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let word = self.next()?;
+
+        if let Some(reg) = self.register_named(&word) {
+            return self.assignment(reg);
+        }
+
+        match word.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name, self.here)
+            }
+            ":next" => {
+                // names the second byte of the next instruction, for self-modifying code
+                let name = self.next()?;
+                self.define_label(name, self.here + 1)
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.next()?;
+                    self.calc()?
+                } else {
+                    self.value()?
+                };
+                self.byte(value as u8)
+            }
+            ":org" => {
+                let addr = self.value()?;
+                if addr < emulator::PRG_OFFSET as i64 + 2 || addr >= emulator::XO_RAM_SIZE as i64 {
+                    return Err(format!(":org 0x{:X} is outside the program", addr));
+                }
+                self.here = addr as usize;
+                Ok(())
+            }
+            ":unpack" => {
+                let nibble = self.nibble_value()?;
+                let at = self.here;
+                let addr = self.address(at, Fixup::UnpackHigh(nibble))?;
+                self.emit(Instruction::LoadImm(0, nibble << 4 | (addr >> 8 & 0xf) as u8))?;
+                if let Some(p) = self.pending.last_mut().filter(|p| p.addr == at) {
+                    // the low byte needs its own fixup
+                    let (name, line) = (p.name.clone(), p.line);
+                    self.pending.push(Pending { addr: at + 2, fixup: Fixup::UnpackLow, name, line });
+                }
+                self.emit(Instruction::LoadImm(1, addr as u8))
+            }
+            ":macro" => self.define_macro(),
+            ":proto" | ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            }
+            "clear" => self.emit(Instruction::Cls),
+            "return" | ";" => self.emit(Instruction::Ret),
+            "exit" => self.emit(Instruction::Exit),
+            "lores" => self.emit(Instruction::Lores),
+            "hires" => self.emit(Instruction::Hires),
+            "scroll-down" => {
+                let n = self.nibble_value()?;
+                self.emit(Instruction::ScrollDown(n))
+            }
+            "scroll-up" => {
+                let n = self.nibble_value()?;
+                self.emit(Instruction::ScrollUp(n))
+            }
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "audio" => self.emit(Instruction::Audio),
+            "plane" => {
+                let n = self.nibble_value()?;
+                self.emit(Instruction::Plane(n))
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::Bcd(x))
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let range = if self.peek() == Some("-") {
+                    self.next()?;
+                    Some(self.register()?)
+                } else {
+                    None
+                };
+
+                self.emit(match (word.as_str(), range) {
+                    ("save", None) => Instruction::Store(x),
+                    ("load", None) => Instruction::Load(x),
+                    ("save", Some(y)) => Instruction::SaveRange(x, y),
+                    (_, Some(y)) => Instruction::LoadRange(x, y),
+                    _ => unreachable!()
+                })
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::SaveFlags(x))
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags(x))
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble_value()?;
+                self.emit(Instruction::Draw(x, y, n))
+            }
+            "jump" | "jump0" | "native" | ":call" => {
+                let at = self.here;
+                let addr = self.address(at, Fixup::Addr)? as u16;
+                self.emit(match word.as_str() {
+                    "jump" => Instruction::Jump(addr & 0xfff),
+                    "jump0" => Instruction::JumpOffset(addr & 0xfff),
+                    "native" => Instruction::Sys(addr & 0xfff),
+                    _ => Instruction::Call(addr & 0xfff)
+                })
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match word.as_str() {
+                    "delay" => Instruction::SetDelay(x),
+                    "buzzer" => Instruction::SetSound(x),
+                    _ => Instruction::Pitch(x)
+                })
+            }
+            "i" => self.index(),
+            "if" => self.conditional(),
+            "else" => {
+                let open = self.branches.pop().ok_or("else without if")?;
+                let jump = self.here;
+                self.emit(Instruction::Jump(0))?;
+                self.patch(open, Fixup::Addr, self.here)?;
+                self.branches.push(jump);
+                Ok(())
+            }
+            "end" => {
+                let open = self.branches.pop().ok_or("end without if")?;
+                self.patch(open, Fixup::Addr, self.here)
+            }
+            "loop" => {
+                self.loops.push(Loop { start: self.here, exits: Vec::new() });
+                Ok(())
+            }
+            "while" => {
+                let (x, cmp, rhs) = self.condition()?;
+                self.skip_when(x, cmp, rhs)?;
+                let exit = self.here;
+                self.emit(Instruction::Jump(0))?;
+                self.loops.last_mut().ok_or("while outside of a loop")?.exits.push(exit);
+                Ok(())
+            }
+            "again" => {
+                let l = self.loops.pop().ok_or("again without loop")?;
+                self.emit(Instruction::Jump(l.start as u16))?;
+                for exit in l.exits {
+                    self.patch(exit, Fixup::Addr, self.here)?;
+                }
+                Ok(())
+            }
+            _ => {
+                if self.macros.contains_key(&word) {
+                    return self.expand(&word);
+                }
+
+                // data bytes, which is how sprites are written
+                if let Some(value) = self.lookup(&word) {
+                    if !(-0x80 ..= 0xFF).contains(&value) {
+                        return Err(format!("value {} doesn't fit in a byte", value));
+                    }
+                    return self.byte(value as u8);
+                }
+
+                // anything else names a subroutine
+                if !is_name(&word) {
+                    return Err(format!("unknown instruction {}", word));
+                }
+                self.pos -= 1;
+                let at = self.here;
+                let addr = self.address(at, Fixup::Addr)? as u16;
+                self.emit(Instruction::Call(addr & 0xfff))
+            }
+        }
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), String> {
+        let op = self.next()?;
+        let word = self.peek().unwrap_or("").to_string();
+        let rhs_reg = self.register_named(&word);
+
+        let instruction = match (op.as_str(), rhs_reg) {
+            (":=", Some(y)) => Instruction::Move(x, y),
+            (":=", None) => match word.as_str() {
+                "random" => {
+                    self.next()?;
+                    Instruction::Random(x, self.byte_value()?)
+                }
+                "delay" => Instruction::GetDelay(x),
+                "key" => Instruction::WaitKey(x),
+                _ => Instruction::LoadImm(x, self.byte_value()?)
+            },
+            ("+=", Some(y)) => Instruction::Add(x, y),
+            ("+=", None) => Instruction::AddImm(x, self.byte_value()?),
+            ("-=", Some(y)) => Instruction::Sub(x, y),
+            ("-=", None) => Instruction::AddImm(x, self.byte_value()?.wrapping_neg()),
+            ("=-", Some(y)) => Instruction::SubReverse(x, y),
+            ("|=", Some(y)) => Instruction::Or(x, y),
+            ("&=", Some(y)) => Instruction::And(x, y),
+            ("^=", Some(y)) => Instruction::Xor(x, y),
+            (">>=", Some(y)) => Instruction::ShiftRight(x, y),
+            ("<<=", Some(y)) => Instruction::ShiftLeft(x, y),
+            _ => return Err(format!("can't use {} {} here", op, word))
+        };
+
+        // keyword and register operands are still waiting to be consumed
+        if rhs_reg.is_some() || (op == ":=" && (word == "delay" || word == "key")) {
+            self.next()?;
+        }
+
+        self.emit(instruction)
+    }
+
+    fn index(&mut self) -> Result<(), String> {
+        let op = self.next()?;
+        match op.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddI(x))
+            }
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let big = self.next()? == "bighex";
+                    let x = self.register()?;
+                    self.emit(if big { Instruction::BigFont(x) } else { Instruction::Font(x) })
+                }
+                Some("long") => {
+                    self.next()?;
+                    let at = self.here + 2;
+                    let addr = self.address(at, Fixup::Long)?;
+                    if addr > 0xFFFF {
+                        return Err(format!("address 0x{:X} doesn't fit in 16 bits", addr));
+                    }
+                    self.emit(Instruction::LoadLongI)?;
+                    self.byte((addr >> 8) as u8)?;
+                    self.byte(addr as u8)
+                }
+                _ => {
+                    let at = self.here;
+                    let addr = self.address(at, Fixup::Addr)?;
+                    if addr > 0xFFF {
+                        return Err(format!("address 0x{:X} doesn't fit in 12 bits, use i := long", addr));
+                    }
+                    self.emit(Instruction::LoadI(addr as u16))
+                }
+            },
+            _ => Err(format!("can't use i {} here", op))
+        }
+    }
+
+    fn condition(&mut self) -> Result<(u8, Cmp, Rhs), String> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let cmp = match op.as_str() {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "<" => Cmp::Lt,
+            ">" => Cmp::Gt,
+            "<=" => Cmp::Le,
+            ">=" => Cmp::Ge,
+            "key" => return Ok((x, Cmp::Key, Rhs::Reg(x))),
+            "-key" => return Ok((x, Cmp::NotKey, Rhs::Reg(x))),
+            _ => return Err(format!("unknown comparison {}", op))
+        };
+
+        let word = self.peek().unwrap_or("").to_string();
+        let rhs = match self.register_named(&word) {
+            Some(y) => {
+                self.next()?;
+                Rhs::Reg(y)
+            }
+            None => Rhs::Imm(self.byte_value()?)
+        };
+
+        Ok((x, cmp, rhs))
+    }
+
+    // `if c then` skips the next statement unless c holds, `if c begin` jumps past the
+    // block unless it does
+    fn conditional(&mut self) -> Result<(), String> {
+        let (x, cmp, rhs) = self.condition()?;
+        match self.next()?.as_str() {
+            "then" => self.skip_when(x, cmp.negate(), rhs),
+            "begin" => {
+                self.skip_when(x, cmp, rhs)?;
+                self.branches.push(self.here);
+                self.emit(Instruction::Jump(0))
+            }
+            other => Err(format!("expected then or begin, found {}", other))
+        }
+    }
+
+    // emit code that skips the next instruction when `vx cmp rhs` holds
+    fn skip_when(&mut self, x: u8, cmp: Cmp, rhs: Rhs) -> Result<(), String> {
+        let instruction = match (cmp, rhs) {
+            (Cmp::Eq, Rhs::Imm(n)) => Instruction::SkipEqImm(x, n),
+            (Cmp::Eq, Rhs::Reg(y)) => Instruction::SkipEq(x, y),
+            (Cmp::Ne, Rhs::Imm(n)) => Instruction::SkipNeImm(x, n),
+            (Cmp::Ne, Rhs::Reg(y)) => Instruction::SkipNe(x, y),
+            (Cmp::Key, _) => Instruction::SkipKey(x),
+            (Cmp::NotKey, _) => Instruction::SkipNotKey(x),
+            // the rest compare through VF's no-borrow flag: a >= b is vf := a, vf -= b
+            // leaving 1, a < b leaves 0
+            (Cmp::Ge, _) | (Cmp::Lt, _) => {
+                self.flag_of_difference(Rhs::Reg(x), rhs)?;
+                Instruction::SkipEqImm(0xF, if cmp == Cmp::Ge { 1 } else { 0 })
+            }
+            (Cmp::Le, _) | (Cmp::Gt, _) => {
+                self.flag_of_difference(rhs, Rhs::Reg(x))?;
+                Instruction::SkipEqImm(0xF, if cmp == Cmp::Le { 1 } else { 0 })
+            }
+        };
+
+        self.emit(instruction)
+    }
+
+    // leave VF = 1 if a >= b, else 0
+    fn flag_of_difference(&mut self, a: Rhs, b: Rhs) -> Result<(), String> {
+        match (a, b) {
+            (Rhs::Reg(a), Rhs::Reg(b)) => {
+                self.emit(Instruction::Move(0xF, a))?;
+                self.emit(Instruction::Sub(0xF, b))
+            }
+            (Rhs::Reg(a), Rhs::Imm(b)) => {
+                self.emit(Instruction::LoadImm(0xF, b))?;
+                self.emit(Instruction::SubReverse(0xF, a))
+            }
+            (Rhs::Imm(a), Rhs::Reg(b)) => {
+                self.emit(Instruction::LoadImm(0xF, a))?;
+                self.emit(Instruction::Sub(0xF, b))
+            }
+            (Rhs::Imm(_), Rhs::Imm(_)) => Err("comparison needs a register".to_string())
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next()?;
+        let mut params = Vec::new();
+        loop {
+            let word = self.next()?;
+            if word == "{" {
+                break;
+            }
+            params.push(word);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.get(self.pos).cloned().ok_or("macro without }")?;
+            self.pos += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, (params, body));
+        Ok(())
+    }
+
+    // splice the macro body in place of its invocation, arguments substituted
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(format!("macro {} expands forever", name));
+        }
+
+        let (params, body) = self.macros[name].clone();
+        let args = (0 .. params.len()).map(|_| self.next()).collect::<Result<Vec<_>, _>>()?;
+        let line = self.line;
+        let expanded: Vec<Token> = body.into_iter()
+            .map(|t| {
+                let text = match params.iter().position(|p| *p == t.text) {
+                    Some(i) => args[i].clone(),
+                    None => t.text
+                };
+                // report errors inside the macro at the invocation
+                Token { text, line }
+            })
+            .collect();
+
+        self.tokens.splice(self.pos .. self.pos, expanded);
+        Ok(())
+    }
+
+    // Octo's :calc has no precedence, operators apply right to left: 2 * 3 + 1 is 8.
+    // Reads up to and including the closing } or ).
+    fn calc(&mut self) -> Result<i64, String> {
+        let lhs = self.calc_term()?;
+        let op = self.next()?;
+        if op == "}" || op == ")" {
+            return Ok(lhs);
+        }
+
+        let rhs = self.calc()?;
+        Ok(match op.as_str() {
+            "+" => lhs.wrapping_add(rhs),
+            "-" => lhs.wrapping_sub(rhs),
+            "*" => lhs.wrapping_mul(rhs),
+            "/" | "%" if rhs == 0 => return Err("division by zero".to_string()),
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => lhs & rhs,
+            "|" => lhs | rhs,
+            "^" => lhs ^ rhs,
+            "<<" => lhs.wrapping_shl(rhs as u32),
+            ">>" => lhs.wrapping_shr(rhs as u32),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => return Err(format!("unknown operator {}", op))
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<i64, String> {
+        let word = self.next()?;
+        match word.as_str() {
+            "(" => self.calc(),
+            "-" => Ok(self.calc_term()?.wrapping_neg()),
+            "~" => Ok(!self.calc_term()?),
+            "!" => Ok((self.calc_term()? == 0) as i64),
+            "HERE" => Ok(self.here as i64),
+            _ => {
+                self.pos -= 1;
+                self.value()
+            }
+        }
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '-') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, word)
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.chars().all(|c| c.is_ascii_digit()) && !digits.is_empty() {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
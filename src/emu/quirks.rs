@@ -0,0 +1,121 @@
+use wasm_bindgen::prelude::*;
+use super::state::{ StateError, StateReader, StateWriter };
+
+// Behaviors that differ between CHIP-8 interpreters. Each flag is consulted by the
+// opcode it affects, so a ROM written for one platform can run on the others.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift Vy into Vx instead of shifting Vx in place
+    pub shift_vy: bool,
+    // FX55/FX65 leave I pointing past the last register transferred
+    pub load_store_inc_i: bool,
+    // FX55/FX65 leave I pointing at the last register transferred, unless load_store_inc_i
+    pub load_store_inc_i_by_x: bool,
+    // BXNN jumps to XNN + Vx instead of BNNN jumping to NNN + V0
+    pub jump_vx: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    // DXYN waits for vblank, so at most one sprite is drawn per frame
+    pub display_wait: bool
+}
+
+#[wasm_bindgen]
+impl Quirks {
+    // the behavior skylark has always had
+    pub fn new() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_inc_i: false,
+            load_store_inc_i_by_x: false,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false
+        }
+    }
+
+    // original COSMAC VIP interpreter
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_inc_i: true,
+            load_store_inc_i_by_x: false,
+            jump_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true
+        }
+    }
+
+    // HP48 CHIP-48
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_inc_i: false,
+            load_store_inc_i_by_x: true,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false
+        }
+    }
+
+    // SUPER-CHIP 1.1
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_inc_i: false,
+            load_store_inc_i_by_x: false,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false
+        }
+    }
+
+    // XO-CHIP, as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_inc_i: true,
+            load_store_inc_i_by_x: false,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::new()
+    }
+}
+
+impl Quirks {
+    // shared by save states and movies
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.shift_vy);
+        w.bool(self.load_store_inc_i);
+        w.bool(self.load_store_inc_i_by_x);
+        w.bool(self.jump_vx);
+        w.bool(self.vf_reset);
+        w.bool(self.clip_sprites);
+        w.bool(self.display_wait);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.shift_vy = r.bool()?;
+        self.load_store_inc_i = r.bool()?;
+        self.load_store_inc_i_by_x = r.bool()?;
+        self.jump_vx = r.bool()?;
+        self.vf_reset = r.bool()?;
+        self.clip_sprites = r.bool()?;
+        self.display_wait = r.bool()?;
+        Ok(())
+    }
+}
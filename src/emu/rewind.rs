@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+// Ring buffer of save states taken every `interval` frames. Only the newest snapshot is
+// kept whole; every older one is stored as a delta against the snapshot taken after it,
+// so walking backwards means undoing deltas from the back of the queue.
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    // frames run since the newest snapshot
+    frame: u32,
+    newest: Option<Vec<u8>>,
+    // deltas[i] turns snapshot i + 1 back into snapshot i, oldest at the front
+    deltas: VecDeque<Vec<u8>>
+}
+
+impl Rewind {
+    // keep up to `capacity` snapshots, one every `interval` frames
+    pub fn new(interval: u32, capacity: usize) -> Rewind {
+        let interval = interval.max(1);
+        let capacity = capacity.max(1);
+        let frame = 0;
+        let newest = None;
+        let deltas = VecDeque::new();
+
+        Rewind {
+            interval,
+            capacity,
+            frame,
+            newest,
+            deltas
+        }
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn snapshots(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    // how many frames back the oldest snapshot is
+    pub fn available_frames(&self) -> u32 {
+        match self.newest {
+            Some(_) => self.frame + self.deltas.len() as u32 * self.interval,
+            None => 0
+        }
+    }
+
+    // Called after every frame with a closure producing the current state, so states
+    // are only serialized on the frames that are actually captured.
+    pub fn frame_done<F: FnOnce() -> Vec<u8>>(&mut self, save: F) {
+        self.frame += 1;
+        if self.newest.is_none() || self.frame >= self.interval {
+            self.capture(save());
+        }
+    }
+
+    pub fn capture(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&state, &newest));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+
+        self.newest = Some(state);
+        self.frame = 0;
+    }
+
+    // Step back to the latest snapshot at least `frames` frames old, or the oldest one
+    // available. Returns the state to load and how many frames were actually rewound.
+    pub fn rewind(&mut self, frames: u32) -> Option<(Vec<u8>, u32)> {
+        let mut state = self.newest.take()?;
+        let mut rewound = self.frame;
+
+        while rewound < frames {
+            match self.deltas.pop_back() {
+                Some(delta) => state = apply_delta(&state, &delta),
+                None => break
+            }
+            rewound += self.interval;
+        }
+
+        self.newest = Some(state.clone());
+        self.frame = 0;
+        Some((state, rewound))
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frame = 0;
+    }
+}
+
+// Delta format: target length, then runs of (unchanged bytes, changed bytes, XOR of the
+// changed bytes), all lengths as LEB128. States mostly differ in a few registers and
+// pixels, so the unchanged runs make up nearly all of it.
+pub fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, target.len());
+
+    let diff: Vec<u8> = target.iter().enumerate()
+        .map(|(i, &b)| b ^ base.get(i).cloned().unwrap_or(0))
+        .collect();
+
+    let mut pos = 0;
+    while pos < diff.len() {
+        let skip = diff[pos ..].iter().take_while(|&&d| d == 0).count();
+        let start = pos + skip;
+        let len = diff[start ..].iter().take_while(|&&d| d != 0).count();
+        if len == 0 {
+            break;
+        }
+
+        write_varint(&mut delta, skip);
+        write_varint(&mut delta, len);
+        delta.extend_from_slice(&diff[start .. start + len]);
+        pos = start + len;
+    }
+
+    delta
+}
+
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut out = base.to_vec();
+    out.resize(len, 0);
+
+    let mut at = 0;
+    while pos < delta.len() {
+        at += read_varint(delta, &mut pos);
+        let run = read_varint(delta, &mut pos);
+        for (dst, d) in out[at .. at + run].iter_mut().zip(&delta[pos .. pos + run]) {
+            *dst ^= d;
+        }
+        at += run;
+        pos += run;
+    }
+
+    out
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut val: usize) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        val |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,117 @@
+use wasm_bindgen::prelude::*;
+use super::state::{ StateError, StateReader, StateWriter };
+
+pub const DEFAULT_SEED: u32 = 0x5eed;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngKind {
+    // xorshift64*, uniform over the whole byte
+    Xorshift,
+    // The VIP interpreter's RND routine: R9 is stepped every frame and on every CXNN,
+    // and its high byte accumulates the byte R9.0 points at in the interpreter page.
+    // Skylark keeps its fonts rather than the VIP interpreter below 0x200, so this has
+    // the VIP's timing dependence and bias, not its exact sequence.
+    Vip
+}
+
+// Random source for CXNN, owned by the emulator so runs are reproducible from the seed
+pub struct Rng {
+    kind: RngKind,
+    seed: u32,
+    state: u64
+}
+
+impl Rng {
+    pub fn new(kind: RngKind, seed: u32) -> Rng {
+        let state = Rng::initial_state(kind, seed);
+
+        Rng {
+            kind,
+            seed,
+            state
+        }
+    }
+
+    fn initial_state(kind: RngKind, seed: u32) -> u64 {
+        match kind {
+            // splitmix64 so that nearby seeds give unrelated sequences, never 0
+            RngKind::Xorshift => {
+                let mut z = (seed as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                (z ^ (z >> 31)).max(1)
+            }
+            RngKind::Vip => (seed & 0xffff) as u64
+        }
+    }
+
+    pub fn kind(&self) -> RngKind {
+        self.kind
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    // restart the sequence from the beginning
+    pub fn reseed(&mut self, kind: RngKind, seed: u32) {
+        *self = Rng::new(kind, seed);
+    }
+
+    // called once per 60Hz frame, like the VIP's interrupt routine
+    pub fn vblank(&mut self) {
+        if self.kind == RngKind::Vip {
+            self.state = (self.state + 1) & 0xffff;
+        }
+    }
+
+    // `ram` is only read by the VIP algorithm
+    pub fn next_byte(&mut self, ram: &[u8]) -> u8 {
+        match self.kind {
+            RngKind::Xorshift => {
+                let mut x = self.state;
+                x ^= x >> 12;
+                x ^= x << 25;
+                x ^= x >> 27;
+                self.state = x;
+                (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8
+            }
+            RngKind::Vip => {
+                let r9 = (self.state + 1) & 0xffff;
+                let lo = r9 & 0xff;
+                let hi = ((r9 >> 8) + ram.get(lo as usize).cloned().unwrap_or(0) as u64) & 0xff;
+                self.state = hi << 8 | lo;
+                hi as u8
+            }
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.kind as u8);
+        w.u32(self.seed);
+        w.u64(self.state);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.kind = match r.u8()? {
+            0 => RngKind::Xorshift,
+            1 => RngKind::Vip,
+            _ => return Err(StateError::Invalid)
+        };
+        self.seed = r.u32()?;
+        self.state = r.u64()?;
+
+        if self.kind == RngKind::Xorshift && self.state == 0 {
+            return Err(StateError::Invalid);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new(RngKind::Xorshift, DEFAULT_SEED)
+    }
+}
@@ -0,0 +1,209 @@
+use std::{ env, fs };
+use std::path::Path;
+use super::display::DisplayFrame;
+
+// Golden-image testing. A snapshot keeps only which pixels are lit, so XO-CHIP colors
+// compare equal as long as the same pixels are on.
+
+// set to write golden files instead of checking against them
+pub const BLESS_VAR: &str = "SKYLARK_BLESS";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    width: usize,
+    height: usize,
+    lit: Vec<bool>
+}
+
+impl Snapshot {
+    pub fn of(display: &DisplayFrame) -> Snapshot {
+        let (width, height) = (display.width(), display.height());
+        let lit = (0 .. height)
+            .flat_map(|y| (0 .. width).map(move |x| (x, y)))
+            .map(|(x, y)| display.pixel(x, y) != 0)
+            .collect();
+
+        Snapshot { width: width as usize, height: height as usize, lit }
+    }
+
+    // DisplayFrame's text rendering, ◼ for lit and ◻ for unlit, or # and . for the same
+    pub fn from_text(text: &str) -> Result<Snapshot, String> {
+        let mut lit = Vec::new();
+        let mut width = None;
+        let mut height = 0;
+
+        for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let row: Vec<bool> = line.trim_end().chars()
+                .map(|c| match c {
+                    '◼' | '#' => Ok(true),
+                    '◻' | '.' => Ok(false),
+                    c => Err(format!("line {}: unexpected {:?}", n + 1, c))
+                })
+                .collect::<Result<_, _>>()?;
+
+            if *width.get_or_insert(row.len()) != row.len() {
+                return Err(format!("line {}: {} pixels wide, expected {}", n + 1, row.len(), width.unwrap_or(0)));
+            }
+            lit.extend(row);
+            height += 1;
+        }
+
+        Ok(Snapshot { width: width.unwrap_or(0), height, lit })
+    }
+
+    // plain (P1) or raw (P4) PBM, where 1 is lit
+    pub fn from_pbm(bytes: &[u8]) -> Result<Snapshot, String> {
+        let mut at = 0;
+        let magic = pbm_token(bytes, &mut at).ok_or("missing PBM header")?;
+        let raw = match magic.as_str() {
+            "P1" => false,
+            "P4" => true,
+            _ => return Err(format!("{} is not a PBM header", magic))
+        };
+
+        let mut dimension = || pbm_token(bytes, &mut at)
+            .and_then(|t| t.parse::<usize>().ok())
+            .ok_or("bad PBM size");
+        let (width, height) = (dimension()?, dimension()?);
+
+        let lit = if raw {
+            // one whitespace byte, then rows packed most significant bit first
+            let stride = width.div_ceil(8);
+            let data = bytes.get(at + 1 .. at + 1 + stride * height).ok_or("PBM data is truncated")?;
+            (0 .. height)
+                .flat_map(|y| (0 .. width).map(move |x| (x, y)))
+                .map(|(x, y)| data[y * stride + x / 8] >> (7 - x % 8) & 1 != 0)
+                .collect()
+        } else {
+            let lit: Vec<bool> = bytes[at ..].iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .map(|&b| match b {
+                    b'1' => Ok(true),
+                    b'0' => Ok(false),
+                    b => Err(format!("unexpected {:?} in PBM data", b as char))
+                })
+                .collect::<Result<_, _>>()?;
+            if lit.len() != width * height {
+                return Err(format!("PBM has {} pixels, expected {}", lit.len(), width * height));
+            }
+            lit
+        };
+
+        Ok(Snapshot { width, height, lit })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.lit[y * self.width + x]
+    }
+
+    // the same text as DisplayFrame's Display
+    pub fn to_text(&self) -> String {
+        self.rows()
+            .map(|row| row.iter().map(|&l| if l { '◼' } else { '◻' }).chain(Some('\n')).collect::<String>())
+            .collect()
+    }
+
+    // plain PBM, one line per row
+    pub fn to_pbm(&self) -> String {
+        let mut pbm = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.rows() {
+            let row: Vec<&str> = row.iter().map(|&l| if l { "1" } else { "0" }).collect();
+            pbm += &row.join(" ");
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    // None if `actual` matches, otherwise which pixels differ. Only the rows with
+    // differences are drawn, with + for pixels lit only in `actual` and - for pixels
+    // lit only here.
+    pub fn diff(&self, actual: &Snapshot) -> Option<String> {
+        if (self.width, self.height) != (actual.width, actual.height) {
+            return Some(format!("expected a {}x{} display, got {}x{}", self.width, self.height, actual.width, actual.height));
+        }
+
+        let differing: Vec<(usize, usize)> = (0 .. self.height)
+            .flat_map(|y| (0 .. self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.is_lit(x, y) != actual.is_lit(x, y))
+            .collect();
+        let &(first_x, first_y) = differing.first()?;
+
+        let mut report = format!("{} pixels differ, the first at ({}, {})\n", differing.len(), first_x, first_y);
+        report += "+ lit only in the actual display, - lit only in the golden one\n";
+        for y in 0 .. self.height {
+            if !differing.iter().any(|&(_, dy)| dy == y) {
+                continue;
+            }
+
+            let row: String = (0 .. self.width)
+                .map(|x| match (self.is_lit(x, y), actual.is_lit(x, y)) {
+                    (true, true) => '#',
+                    (false, false) => '.',
+                    (false, true) => '+',
+                    (true, false) => '-'
+                })
+                .collect();
+            report += &format!("{:3} {}\n", y, row);
+        }
+
+        Some(report)
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.lit.chunks(self.width.max(1))
+    }
+}
+
+// Compare `display` with the golden file at `path`, read as PBM if it ends in .pbm and as
+// text otherwise. With SKYLARK_BLESS set in the environment the golden file is written
+// from `display` instead, to create or update it.
+pub fn check(path: impl AsRef<Path>, display: &DisplayFrame) -> Result<(), String> {
+    let path = path.as_ref();
+    let actual = Snapshot::of(display);
+    let pbm = path.extension().is_some_and(|e| e == "pbm");
+
+    if env::var_os(BLESS_VAR).is_some() {
+        let contents = if pbm { actual.to_pbm() } else { actual.to_text() };
+        return fs::write(path, contents).map_err(|e| format!("couldn't write {}: {}", path.display(), e));
+    }
+
+    let bytes = fs::read(path)
+        .map_err(|e| format!("couldn't read {}: {} (set {} to create it)", path.display(), e, BLESS_VAR))?;
+    let golden = if pbm {
+        Snapshot::from_pbm(&bytes)
+    } else {
+        Snapshot::from_text(&String::from_utf8_lossy(&bytes))
+    }.map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    match golden.diff(&actual) {
+        Some(diff) => Err(format!("{} doesn't match:\n{}", path.display(), diff)),
+        None => Ok(())
+    }
+}
+
+// the next whitespace separated token of a PBM header, skipping # comments
+fn pbm_token(bytes: &[u8], at: &mut usize) -> Option<String> {
+    loop {
+        match bytes.get(*at)? {
+            b'#' => while bytes.get(*at).is_some_and(|&b| b != b'\n') {
+                *at += 1;
+            },
+            b if b.is_ascii_whitespace() => *at += 1,
+            _ => break
+        }
+    }
+
+    let start = *at;
+    while bytes.get(*at).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *at += 1;
+    }
+    Some(String::from_utf8_lossy(&bytes[start .. *at]).into_owned())
+}
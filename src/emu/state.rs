@@ -0,0 +1,210 @@
+use std::{ error, fmt };
+use wasm_bindgen::prelude::*;
+
+// Save state layout: magic, version (u16), then every component in a fixed order
+// (see Emulator::save_state), followed by a CRC-32 of everything before it.
+// Any other version is rejected.
+// All integers are little endian, byte strings are prefixed with their u32 length.
+pub const STATE_MAGIC: [u8; 4] = *b"SKY8";
+pub const STATE_VERSION: u16 = 1;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion,
+    ChecksumMismatch,
+    Truncated,
+    Invalid
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            StateError::BadMagic => "Not a skylark save state",
+            StateError::UnsupportedVersion => "Save state version is not supported",
+            StateError::ChecksumMismatch => "Save state checksum mismatch",
+            StateError::Truncated => "Save state is truncated",
+            StateError::Invalid => "Save state contains invalid machine state"
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl error::Error for StateError {}
+
+pub struct StateWriter {
+    buf: Vec<u8>
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter::with_header(STATE_MAGIC, STATE_VERSION)
+    }
+
+    // for other files sharing the layout (magic, version, fields, CRC-32)
+    pub fn with_header(magic: [u8; 4], version: u16) -> StateWriter {
+        let mut buf = magic.to_vec();
+        buf.extend_from_slice(&version.to_le_bytes());
+
+        StateWriter {
+            buf
+        }
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, val: f32) {
+        self.u32(val.to_bits());
+    }
+
+    pub fn f64(&mut self, val: f64) {
+        self.u64(val.to_bits());
+    }
+
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.u32(val.len() as u32);
+        self.buf.extend_from_slice(val);
+    }
+
+    // append the checksum and hand back the finished state
+    pub fn finish(mut self) -> Vec<u8> {
+        let crc = crc32(&self.buf);
+        self.u32(crc);
+        self.buf
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header and checksum before anything is read
+    pub fn new(state: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        StateReader::with_header(state, STATE_MAGIC, STATE_VERSION)
+    }
+
+    // accepts `version` only
+    pub fn with_header(state: &'a [u8], magic: [u8; 4], version: u16) -> Result<StateReader<'a>, StateError> {
+        if state.len() < magic.len() || state[.. magic.len()] != magic {
+            return Err(StateError::BadMagic);
+        }
+
+        if state.len() < magic.len() + 2 + 4 {
+            return Err(StateError::Truncated);
+        }
+
+        let (body, crc) = state.split_at(state.len() - 4);
+        if crc32(body).to_le_bytes() != crc {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        if u16::from_le_bytes([body[4], body[5]]) != version {
+            return Err(StateError::UnsupportedVersion);
+        }
+
+        Ok(StateReader {
+            buf: body,
+            pos: magic.len() + 2
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos.checked_add(len).ok_or(StateError::Truncated)?;
+        let bytes = self.buf.get(self.pos .. end).ok_or(StateError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid)
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let b = self.take(8)?;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(b);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, StateError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, StateError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    // trailing data means the state was written by something else
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.pos == self.buf.len() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid)
+        }
+    }
+}
+
+// CRC-32 (IEEE 802.3), bit by bit since states are small
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0 .. 8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
@@ -1,3 +1,5 @@
+use super::state::{ StateError, StateReader, StateWriter };
+
 pub struct Timer {
     val: u8
 }
@@ -24,4 +26,19 @@ impl Timer {
             self.val -= 1;
         }
     }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.val);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.val = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Timer {
+        Timer::new()
+    }
 }
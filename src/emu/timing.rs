@@ -0,0 +1,75 @@
+use wasm_bindgen::prelude::*;
+use super::instruction::Instruction;
+
+// How instructions are scheduled into frames
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    // CLOCK_RATE / RENDER_RATE instructions per frame, whatever they are
+    Fixed,
+    // each instruction costs what it took the COSMAC VIP interpreter, against the
+    // machine cycles the VIP had per frame
+    Vip
+}
+
+// The VIP runs its 1802 at 1.76064 MHz, 8 clocks to a machine cycle
+pub const VIP_CLOCK_RATE: u32 = 1_760_640;
+pub const VIP_CYCLES_PER_FRAME: u32 = VIP_CLOCK_RATE / 8 / 60;
+
+// of which the 1861's DMA takes 8 bytes for each of its 128 lines, and the interrupt
+// routine that sets it up and counts the timers down about 46 more
+pub const VIP_DISPLAY_CYCLES: u32 = 128 * 8 + 46;
+
+// what the interpreter has left for instructions each frame
+pub const VIP_FRAME_BUDGET: u32 = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+
+// fetching an instruction and dispatching on its first nibble
+const FETCH: u32 = 40;
+
+// extra cost of a skip that is taken
+pub const VIP_SKIP: u32 = 4;
+
+// Machine cycles the VIP interpreter spends on `instruction`, given the registers before it
+// runs. Costs are averaged where the listing's loops depend on more than that, and
+// instructions the VIP doesn't have cost the same as a jump.
+pub fn vip_cycles(instruction: &Instruction, v: &[u8]) -> u32 {
+    let vx = |x: u8| v.get(x as usize).copied().unwrap_or(0) as u32;
+
+    FETCH + match *instruction {
+        // 256 bytes of display ram, 12 cycles each
+        Instruction::Cls => 24 + 256 * 12,
+        Instruction::Ret => 10,
+        Instruction::Jump(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..) => 10,
+        Instruction::SkipEq(..) | Instruction::SkipNe(..) => 14,
+        Instruction::LoadImm(..) => 6,
+        Instruction::AddImm(..) => 10,
+        Instruction::Move(..) => 12,
+        // the ALU ops run a patched 1802 instruction from ram
+        Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..) | Instruction::Add(..)
+            | Instruction::Sub(..) | Instruction::ShiftRight(..) | Instruction::SubReverse(..)
+            | Instruction::ShiftLeft(..) => 44,
+        Instruction::LoadI(_) => 12,
+        Instruction::JumpOffset(_) => 22,
+        Instruction::Random(..) => 36,
+        // sprites that don't start on a byte boundary are shifted a bit at a time
+        Instruction::Draw(x, _, n) => {
+            let rows = if n == 0 { 16 } else { n as u32 };
+            let shift = vx(x) % 8;
+            26 + rows * (34 + shift * 4)
+        }
+        Instruction::SkipKey(_) | Instruction::SkipNotKey(_) => 14,
+        Instruction::GetDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+        // one pass of the keypad scan, repeated while no key is down
+        Instruction::WaitKey(_) => 18,
+        Instruction::AddI(_) | Instruction::Font(_) => 16,
+        // each digit is found by repeated subtraction
+        Instruction::Bcd(x) => {
+            let n = vx(x);
+            84 + 16 * (n / 100 + n / 10 % 10 + n % 10)
+        }
+        Instruction::Store(x) | Instruction::Load(x) => 14 + 14 * (x as u32 + 1),
+        _ => 12
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::{ fmt, io };
+use wasm_bindgen::prelude::*;
+use super::{ audio, cpu, memory, timer };
+use super::instruction::Instruction;
+
+// Groups of instructions a trace can be limited to, as bits of a mask (1 << class)
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpcodeClass {
+    // jumps, calls, returns and exit
+    Flow = 0,
+    Skip = 1,
+    // register arithmetic and logic, including RND
+    Alu = 2,
+    // anything that sets I
+    Index = 3,
+    // loads and stores through I, BCD and the RPL flags
+    Memory = 4,
+    // clear, draw, scroll, resolution and planes
+    Display = 5,
+    // delay and sound timers, key waits
+    Timer = 6,
+    // XO-CHIP audio pattern and pitch
+    Audio = 7
+}
+
+pub const ALL_CLASSES: u32 = 0xff;
+
+impl OpcodeClass {
+    pub fn of(instruction: &Instruction) -> OpcodeClass {
+        use self::Instruction::*;
+
+        match instruction {
+            Sys(_) | Ret | Exit | Jump(_) | Call(_) | JumpOffset(_) => OpcodeClass::Flow,
+            SkipEqImm(..) | SkipNeImm(..) | SkipEq(..) | SkipNe(..) | SkipKey(_) | SkipNotKey(_) => OpcodeClass::Skip,
+            LoadImm(..) | AddImm(..) | Move(..) | Or(..) | And(..) | Xor(..) | Add(..) | Sub(..) |
+            ShiftRight(..) | SubReverse(..) | ShiftLeft(..) | Random(..) => OpcodeClass::Alu,
+            LoadI(_) | LoadLongI | AddI(_) | Font(_) | BigFont(_) => OpcodeClass::Index,
+            SaveRange(..) | LoadRange(..) | Bcd(_) | Store(_) | Load(_) | SaveFlags(_) | LoadFlags(_) => OpcodeClass::Memory,
+            Cls | ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft | Lores | Hires | Plane(_) | Draw(..) => OpcodeClass::Display,
+            GetDelay(_) | WaitKey(_) | SetDelay(_) | SetSound(_) => OpcodeClass::Timer,
+            Audio | Pitch(_) => OpcodeClass::Audio
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    DT,
+    ST
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::DT => write!(f, "DT"),
+            Register::ST => write!(f, "ST")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub register: Register,
+    pub before: u16,
+    pub after: u16
+}
+
+// One executed instruction and the registers it changed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub class: OpcodeClass,
+    pub text: String,
+    pub changes: Vec<Change>
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = self.changes.iter()
+            .map(|c| match c.register {
+                Register::I => format!("I {:03X}->{:03X}", c.before, c.after),
+                r => format!("{} {:02X}->{:02X}", r, c.before, c.after)
+            })
+            .collect();
+
+        let line = format!("0x{:03X}  {:04X}  {:<24} {}", self.pc, self.opcode, self.text, changes.join(" "));
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Registers {
+    v: Vec<u8>,
+    i: u16,
+    dt: u8,
+    st: u8
+}
+
+impl Registers {
+    fn capture(cpu: &cpu::Cpu, timer: &timer::Timer, audio: &audio::Audio) -> Registers {
+        Registers {
+            v: cpu.v().to_vec(),
+            i: cpu.i(),
+            dt: timer.get(),
+            st: audio.timer()
+        }
+    }
+
+    fn changes(&self, after: &Registers) -> Vec<Change> {
+        let mut changes: Vec<Change> = self.v.iter().zip(&after.v).enumerate()
+            .filter(|(_, (b, a))| b != a)
+            .map(|(x, (&b, &a))| Change { register: Register::V(x as u8), before: b as u16, after: a as u16 })
+            .collect();
+
+        let others = [
+            (Register::I, self.i, after.i),
+            (Register::DT, self.dt as u16, after.dt as u16),
+            (Register::ST, self.st as u16, after.st as u16)
+        ];
+        changes.extend(others.iter()
+            .filter(|(_, b, a)| b != a)
+            .map(|&(register, before, after)| Change { register, before, after }));
+        changes
+    }
+}
+
+// An instruction that passed the filter, waiting for its registers after execution
+pub struct Pending {
+    pc: u16,
+    opcode: u16,
+    instruction: Instruction,
+    text: String,
+    before: Registers
+}
+
+enum Sink {
+    // the last `capacity` entries
+    Buffer(VecDeque<Entry>, usize),
+    Console,
+    Writer(Box<dyn io::Write>)
+}
+
+// Per-instruction execution trace. The emulator only calls into this when a sink is set,
+// so a disabled trace costs one branch per instruction.
+pub struct Trace {
+    sink: Option<Sink>,
+    // instructions at start .. end only
+    range: Option<(usize, usize)>,
+    classes: u32
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace {
+            sink: None,
+            range: None,
+            classes: ALL_CLASSES
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub fn log_to_buffer(&mut self, capacity: usize) {
+        self.sink = Some(Sink::Buffer(VecDeque::with_capacity(capacity), capacity));
+    }
+
+    // web_sys::console under wasm, stderr elsewhere
+    pub fn log_to_console(&mut self) {
+        self.sink = Some(Sink::Console);
+    }
+
+    // one line per entry
+    pub fn log_to_writer(&mut self, writer: Box<dyn io::Write>) {
+        self.sink = Some(Sink::Writer(writer));
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(Sink::Writer(writer)) = self.sink.as_mut() {
+            writer.flush().ok();
+        }
+        self.sink = None;
+    }
+
+    pub fn set_range(&mut self, range: Option<(usize, usize)>) {
+        self.range = range;
+    }
+
+    pub fn set_classes(&mut self, mask: u32) {
+        self.classes = mask;
+    }
+
+    // what the buffer holds, oldest first, empty for other sinks
+    pub fn entries(&self) -> Vec<Entry> {
+        match &self.sink {
+            Some(Sink::Buffer(entries, _)) => entries.iter().cloned().collect(),
+            _ => Vec::new()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(Sink::Buffer(entries, _)) = self.sink.as_mut() {
+            entries.clear();
+        }
+    }
+
+    // Look at the instruction the cpu is about to run, None if it is filtered out or
+    // won't execute (halted, waiting for vblank or about to fault on the fetch)
+    pub fn begin(&self, cpu: &cpu::Cpu, ram: &memory::Memory, timer: &timer::Timer, audio: &audio::Audio) -> Option<Pending> {
+        if cpu.halted() || cpu.waiting_vblank() {
+            return None;
+        }
+
+        let pc = cpu.pc();
+        if self.range.is_some_and(|(start, end)| pc < start || pc >= end) {
+            return None;
+        }
+
+        let bytes = ram.as_slice();
+        let word = |addr: usize| bytes.get(addr .. addr + 2).map(|w| (w[0] as u16) << 8 | w[1] as u16);
+        let opcode = word(pc)?;
+        let instruction = Instruction::decode(opcode)?;
+        if self.classes & 1 << OpcodeClass::of(&instruction) as u32 == 0 {
+            return None;
+        }
+
+        let text = instruction.format(word(pc + 2).unwrap_or(0), |a| format!("0x{:03X}", a));
+        Some(Pending {
+            pc: pc as u16,
+            opcode,
+            instruction,
+            text,
+            before: Registers::capture(cpu, timer, audio)
+        })
+    }
+
+    pub fn finish(&mut self, pending: Pending, cpu: &cpu::Cpu, timer: &timer::Timer, audio: &audio::Audio) {
+        let after = Registers::capture(cpu, timer, audio);
+        let entry = Entry {
+            pc: pending.pc,
+            opcode: pending.opcode,
+            class: OpcodeClass::of(&pending.instruction),
+            text: pending.text,
+            changes: pending.before.changes(&after)
+        };
+
+        match self.sink.as_mut() {
+            Some(Sink::Buffer(entries, capacity)) => {
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                if *capacity > 0 {
+                    entries.push_back(entry);
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            Some(Sink::Console) => {
+                log!("{}", entry);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(Sink::Console) => {
+                eprintln!("{}", entry);
+            }
+            Some(Sink::Writer(writer)) => {
+                // a failing writer shouldn't stop the emulator
+                writeln!(writer, "{}", entry).ok();
+            }
+            None => ()
+        }
+    }
+}
+
+impl Default for Trace {
+    fn default() -> Trace {
+        Trace::new()
+    }
+}
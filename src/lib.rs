@@ -1,3 +1,4 @@
+#[macro_use]
 mod utils;
 pub mod emu;
 
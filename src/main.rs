@@ -1,31 +1,367 @@
 use skylark::emu;
-use std::{thread, time, fs, env};
+use std::{thread, time, fs, env, net, process};
+use std::io::{ self, BufRead, Write };
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    run_emulator(&args[1]);
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("disasm"), Some(file_name)) => disassemble(file_name),
+        (Some("asm"), Some(file_name)) => assemble(file_name, args.get(3)),
+        (Some("debug"), Some(file_name)) => debug(file_name),
+        (Some("gdb"), Some(file_name)) => gdb_server(file_name, args.get(3)),
+        (Some("dap"), None) => dap_server(),
+        (Some(file_name), _) if !["disasm", "asm", "debug", "gdb", "dap"].contains(&file_name) => {
+            let (trace, config) = run_options(&args[2 ..]);
+            run_emulator(file_name, trace, &config)
+        }
+        _ => usage()
+    }
 }
 
-fn run_emulator(file_name: &str){
-    let mut emulator = emu::Emulator::new();
+fn usage() -> ! {
+    eprintln!("usage: skylark <rom.ch8> [--trace trace.log] [--ipf instructions-per-frame] [--timer-hz frequency]");
+    eprintln!("       skylark disasm <rom.ch8>");
+    eprintln!("       skylark asm <source | source.8o> [rom.ch8]");
+    eprintln!("       skylark debug <rom.ch8>");
+    eprintln!("       skylark gdb <rom.ch8> [port]");
+    eprintln!("       skylark dap");
+    process::exit(2);
+}
 
-    // let mut file = File::open(file_name).expect("Failed to read file");
-    let rom_bytes = match fs::read(file_name) {
+fn read_rom(file_name: &str) -> Vec<u8> {
+    match fs::read(file_name) {
         Err(e) => panic!("couldn't open {}: {}", file_name, e),
         Ok(file) => file
+    }
+}
+
+// print the rom as it would be laid out in RAM, following code from the program start
+fn disassemble(file_name: &str) {
+    let rom = read_rom(file_name);
+    let mut ram = vec![0; emu::emulator::PRG_OFFSET];
+    ram.extend_from_slice(&rom);
+
+    let offset = emu::emulator::PRG_OFFSET;
+    let lines = emu::disasm::disassemble(&ram, offset, rom.len(), &[offset]);
+    print!("{}", emu::disasm::listing(&lines));
+}
+
+// .8o files are Octo, anything else goes to the assembler
+fn compile(file_name: &str) -> Result<emu::Program, emu::AsmError> {
+    if Path::new(file_name).extension().is_some_and(|e| e == "8o") {
+        let source = fs::read_to_string(file_name)
+            .map_err(|e| emu::AsmError::new(file_name, 0, format!("couldn't open {}: {}", file_name, e)))?;
+        emu::octo::compile(file_name, &source)
+    } else {
+        emu::asm::assemble_file(file_name)
+    }
+}
+
+// Writes the rom next to the source without its last extension (font_test.ch8.src becomes
+// font_test.ch8) unless told otherwise, and the labels and source line addresses next to
+// the rom as .sym
+fn assemble(file_name: &str, out: Option<&String>) {
+    let program = match compile(file_name) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
     };
 
-    emulator.load_rom(rom_bytes);
+    let out = match out {
+        Some(out) => PathBuf::from(out),
+        None => Path::new(file_name).with_extension("")
+    };
+    let sym = out.with_extension("sym");
 
+    if let Err(e) = fs::write(&out, &program.bytes).and_then(|_| fs::write(&sym, program.symbol_map() + &program.line_map())) {
+        eprintln!("couldn't write {}: {}", out.display(), e);
+        process::exit(1);
+    }
+}
+
+// `--trace FILE`, `--ipf N` and `--timer-hz N` in any order
+fn run_options(args: &[String]) -> (Option<&String>, emu::EmulatorConfig) {
+    let mut trace = None;
+    let mut config = emu::EmulatorConfig::new();
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--trace" => trace = Some(value),
+            "--ipf" => config.instructions_per_frame = value.parse().unwrap_or_else(|_| usage()),
+            "--timer-hz" => config.timer_frequency = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage()
+        }
+    }
+
+    (trace, config)
+}
+
+// `trace` gets a line for every instruction executed
+fn run_emulator(file_name: &str, trace: Option<&String>, config: &emu::EmulatorConfig) {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+    let mut emulator = emu::Emulator::with_seed(seed);
+    emulator.set_config(config);
+    emulator.load_rom(load(file_name));
+
+    if let Some(trace) = trace {
+        match fs::File::create(trace) {
+            Ok(file) => emulator.trace_to_writer(Box::new(io::LineWriter::new(file))),
+            Err(e) => {
+                eprintln!("couldn't create {}: {}", trace, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // sleep doesn't wake exactly on time, run_for makes up the difference
+    let mut last = time::Instant::now();
     loop {
-        emulator.tick_frame();
+        let now = time::Instant::now();
+        let result = emulator.run_for(now.duration_since(last).as_secs_f64() * 1000.0);
+        last = now;
+
         clear_screen();
         print!("{}", emulator.display_out());
 
+        if let Err(e) = result {
+            emulator.stop_trace();
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+
         thread::sleep(time::Duration::from_millis(16));
     }
 }
 
+const DEBUG_HELP: &str = "\
+s [n]                  step n instructions
+c [frames]             run until something breaks, 600 frames at most
+b ADDR [VX OP N]       break at ADDR, only while VX OP N holds if given
+r VX OP N              break when VX OP N becomes true (OP is == != < <= > >=)
+w ADDR [LEN] [r|w|rw]  break on access to LEN bytes at ADDR through I
+o PATTERN              break on opcodes like DXYN or FX0A
+d ADDR                 delete the breakpoint or watchpoint at ADDR
+clear                  delete everything
+x [ADDR] [LEN]         disassemble, from the PC by default
+i                      show the registers, timers and stack
+m ADDR [LEN]           dump memory
+t [n]                  show the last n instructions executed
+p                      print the screen
+q                      quit";
+
+// Line-based debugger on stdin, in the spirit of gdb's short commands
+fn debug(file_name: &str) {
+    let mut emulator = emu::Emulator::new();
+    emulator.load_rom(load(file_name));
+    emulator.trace_to_buffer(256);
+    println!("{}", current_instruction(&emulator));
+
+    let stdin = io::stdin();
+    loop {
+        print!("(skylark) ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match debug_command(&mut emulator, &words) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(message) => println!("{}", message)
+        }
+    }
+}
+
+// false once the session should end
+fn debug_command(emulator: &mut emu::Emulator, words: &[&str]) -> Result<bool, String> {
+    let number = |i: usize| words.get(i).map(|w| parse_number(w)).transpose();
+
+    match words.first().copied() {
+        None => (),
+        Some("q") => return Ok(false),
+        Some("s") => {
+            let mut reason = emu::BreakReason::Step;
+            for _ in 0 .. number(1)?.unwrap_or(1) {
+                reason = emulator.step().map_err(|e| e.to_string())?;
+                if reason == emu::BreakReason::Halted {
+                    break;
+                }
+            }
+            println!("{:?} {}", reason, current_instruction(emulator));
+        }
+        Some("c") => {
+            let reason = emulator.run_until_break(number(1)?.unwrap_or(600) as u32).map_err(|e| e.to_string())?;
+            match (reason, emulator.watch_hit()) {
+                (emu::BreakReason::ReadWatch, Some(addr)) | (emu::BreakReason::WriteWatch, Some(addr)) =>
+                    println!("{:?} of 0x{:03X} {}", reason, addr, current_instruction(emulator)),
+                _ => println!("{:?} {}", reason, current_instruction(emulator))
+            }
+        }
+        Some("b") if words.len() == 2 => emulator.add_breakpoint(number(1)?.unwrap_or(0)),
+        Some("b") if words.len() == 5 => {
+            let (reg, compare, value) = parse_condition(&words[2 ..])?;
+            emulator.add_conditional_breakpoint(number(1)?.unwrap_or(0), reg, compare, value);
+        }
+        Some("r") if words.len() == 4 => {
+            let (reg, compare, value) = parse_condition(&words[1 ..])?;
+            emulator.add_register_break(reg, compare, value);
+        }
+        Some("w") if words.len() >= 2 => {
+            let kind = match words.get(3).copied() {
+                None | Some("rw") => emu::WatchKind::ReadWrite,
+                Some("r") => emu::WatchKind::Read,
+                Some("w") => emu::WatchKind::Write,
+                Some(other) => return Err(format!("unknown watch kind {}", other))
+            };
+            emulator.add_watchpoint(number(1)?.unwrap_or(0), number(2)?.unwrap_or(1), kind);
+        }
+        Some("o") if words.len() == 2 => {
+            emulator.break_on_opcode(words[1])
+                .then_some(())
+                .ok_or_else(|| format!("{} is not an opcode pattern", words[1]))?;
+        }
+        Some("d") if words.len() == 2 => {
+            let addr = number(1)?.unwrap_or(0);
+            if !emulator.remove_breakpoint(addr) && !emulator.remove_watchpoint(addr) {
+                return Err(format!("nothing set at 0x{:03X}", addr));
+            }
+        }
+        Some("clear") => emulator.clear_breakpoints(),
+        Some("x") => {
+            let start = number(1)?.unwrap_or_else(|| emulator.pc());
+            print!("{}", emulator.disassemble(start, number(2)?.unwrap_or(16)));
+        }
+        Some("i") => print_registers(emulator),
+        Some("m") if words.len() >= 2 => {
+            let addr = number(1)?.unwrap_or(0);
+            for (row, chunk) in emulator.peek(addr, number(2)?.unwrap_or(16)).chunks(16).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+                println!("0x{:03X}  {}", addr + row * 16, hex.join(" "));
+            }
+        }
+        Some("t") => {
+            let log = emulator.trace_log();
+            let lines: Vec<&str> = log.lines().collect();
+            let n = number(1)?.unwrap_or(16).min(lines.len());
+            for line in &lines[lines.len() - n ..] {
+                println!("{}", line);
+            }
+        }
+        Some("p") => print!("{}", emulator.display_out()),
+        Some(_) => println!("{}", DEBUG_HELP)
+    }
+
+    Ok(true)
+}
+
+fn print_registers(emulator: &emu::Emulator) {
+    let v = emulator.registers();
+    for (half, regs) in v.chunks(8).enumerate() {
+        let hex: Vec<String> = regs.iter().map(|b| format!("{:02X}", b)).collect();
+        println!("V{:X}-V{:X}  {}", half * 8, half * 8 + 7, hex.join(" "));
+    }
+
+    let stack: Vec<String> = emulator.stack().iter().map(|a| format!("0x{:03X}", a)).collect();
+    println!("PC 0x{:03X}  I 0x{:03X}  DT {}  ST {}  stack [{}]",
+        emulator.pc(), emulator.index(), emulator.delay_timer(), emulator.sound_timer(), stack.join(" "));
+}
+
+// Wait for one gdb connection on localhost and serve it until it detaches
+fn gdb_server(file_name: &str, port: Option<&String>) {
+    let port = match port.map(|p| p.parse::<u16>()) {
+        None => 1234,
+        Some(Ok(port)) => port,
+        Some(Err(_)) => usage()
+    };
+
+    let mut emulator = emu::Emulator::new();
+    emulator.load_rom(load(file_name));
+
+    let result = net::TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        println!("waiting for gdb on port {}, use: target remote :{}", port, port);
+        let (stream, addr) = listener.accept()?;
+        println!("connected to {}", addr);
+        emu::gdb::serve(&mut emulator, stream)
+    });
+
+    if let Err(e) = result {
+        eprintln!("gdb server: {}", e);
+        process::exit(1);
+    }
+}
+
+// Debug adapter for editors on stdin and stdout, the editor's launch request names the rom
+fn dap_server() {
+    if let Err(e) = emu::dap::serve(io::BufReader::new(io::stdin()), io::stdout()) {
+        eprintln!("debug adapter: {}", e);
+        process::exit(1);
+    }
+}
+
+// hex with 0x, decimal otherwise
+fn parse_number(word: &str) -> Result<usize, String> {
+    let parsed = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => word.parse()
+    };
+    parsed.map_err(|_| format!("{} is not a number", word))
+}
+
+fn parse_condition(words: &[&str]) -> Result<(u8, emu::Compare, u8), String> {
+    let reg = words[0].strip_prefix(['v', 'V'])
+        .and_then(|x| u8::from_str_radix(x, 16).ok())
+        .filter(|&x| x < 16)
+        .ok_or_else(|| format!("{} is not a register", words[0]))?;
+
+    let compare = match words[1] {
+        "==" => emu::Compare::Eq,
+        "!=" => emu::Compare::Ne,
+        "<" => emu::Compare::Lt,
+        "<=" => emu::Compare::Le,
+        ">" => emu::Compare::Gt,
+        ">=" => emu::Compare::Ge,
+        other => return Err(format!("unknown comparison {}", other))
+    };
+
+    let value = parse_number(words[2])?;
+    if value > 0xff {
+        return Err(format!("{} doesn't fit in a register", words[2]));
+    }
+    Ok((reg, compare, value as u8))
+}
+
+// the line of the listing for the instruction at the PC
+fn current_instruction(emulator: &emu::Emulator) -> String {
+    let listing = emulator.disassemble(emulator.pc(), 2);
+    let line = listing.lines().find(|l| !l.ends_with(':')).unwrap_or("");
+    format!("at 0x{:03X}: {}", emulator.pc(), line.trim())
+}
+
+// .8o sources are compiled first, anything else is a rom
+fn load(file_name: &str) -> Vec<u8> {
+    if file_name.ends_with(".8o") {
+        match compile(file_name) {
+            Ok(program) => program.bytes,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        read_rom(file_name)
+    }
+}
+
+#[allow(dead_code)]
 fn run_universe(){
     let mut universe = emu::Universe::new();
 
@@ -33,7 +369,7 @@ fn run_universe(){
         thread::sleep(time::Duration::from_millis(10));
 
         clear_screen();
-        print!("{}", universe.to_string());
+        print!("{}", universe);
         universe.tick();
     }
 }
@@ -0,0 +1,102 @@
+use skylark::emu::{ asm, disasm };
+use std::fs;
+
+fn bytes_of(source: &str) -> Vec<u8> {
+    asm::assemble("test.asm", source).unwrap().bytes
+}
+
+#[test]
+fn raw_sources_still_build() {
+    for name in &["font_test", "scrolling_logo"] {
+        let program = asm::assemble_file(&format!("roms/{}.ch8.src", name)).unwrap();
+        assert_eq!(program.bytes, fs::read(format!("roms/{}.ch8", name)).unwrap(), "{}", name);
+    }
+
+    // the listing with an address column reassembles to the original rom
+    let program = asm::assemble_file("roms/pong_decompiled.ch8.src").unwrap();
+    assert_eq!(program.bytes, fs::read("roms/PONG.ch8").unwrap());
+}
+
+#[test]
+fn raw_hex_that_looks_like_a_directive() {
+    // lines that read as raw hex stay raw hex, DB 01 is DXYN with X = B
+    assert_eq!(bytes_of("DB 01\nDB 15 6A 02\nDB 0x01\nDB 1, 2\nDB 7"), [
+        0xDB, 0x01, 0xDB, 0x15, 0x6A, 0x02, 0x01, 0x01, 0x02, 0x07
+    ]);
+}
+
+#[test]
+fn mnemonics_labels_and_constants() {
+    let source = "
+        SPEED = 2 * (1 + 1)     ; constants may use expressions
+        start:
+            LD V0, SPEED
+            LD I, sprite
+            CALL draw
+            JP start
+        draw:
+            DRW V0, V1, END - sprite
+            RET
+        sprite:
+            DB 0b11110000, 0x90, \"hi\"
+        END = sprite + 4
+    ";
+    let program = asm::assemble("test.asm", source).unwrap();
+
+    assert_eq!(program.bytes, [
+        0x60, 0x04, 0xA2, 0x0C, 0x22, 0x08, 0x12, 0x00,
+        0xD0, 0x14, 0x00, 0xEE, 0xF0, 0x90, b'h', b'i'
+    ]);
+    assert_eq!(program.labels["draw"], 0x208);
+    assert_eq!(program.symbol_map(), "0x0200 start\n0x0208 draw\n0x020C sprite\n");
+    assert_eq!(program.address_of("test.asm", 9), Some(0x208));
+}
+
+#[test]
+fn directives() {
+    assert_eq!(bytes_of("DW 0x1234, -1"), [0x12, 0x34, 0xFF, 0xFF]);
+    assert_eq!(bytes_of("CLS\nORG 0x206\nRET"), [0x00, 0xE0, 0, 0, 0, 0, 0x00, 0xEE]);
+    assert_eq!(bytes_of("LD I, LONG 0x1234 // xo-chip"), [0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(bytes_of("ADD VB, -2\nSAVE V1 - V4\nSHR V3"), [0x7B, 0xFE, 0x51, 0x42, 0x83, 0x36]);
+}
+
+#[test]
+fn includes() {
+    let main = "CALL sub\nINCLUDE \"lib.asm\"";
+    let program = asm::assemble_with("main.asm", main, |from, path| {
+        assert_eq!((from, path), ("main.asm", "lib.asm"));
+        Ok(("lib.asm".to_string(), "sub: RET".to_string()))
+    }).unwrap();
+
+    assert_eq!(program.bytes, [0x22, 0x02, 0x00, 0xEE]);
+    assert_eq!(program.address_of("lib.asm", 1), Some(0x202));
+}
+
+#[test]
+fn errors_point_at_the_line() {
+    let cases = [
+        ("CLS\nJP nowhere", 2, "nowhere is not defined"),
+        ("LD V0, 256", 1, "value 256 is out of range"),
+        ("\n\nFOO V1", 3, "unknown instruction FOO"),
+        ("x:\nx:", 2, "x is already defined"),
+        ("CLS\n0204: 00E0", 2, "address 0x204 doesn't match 0x202"),
+        ("ONE = TWO\nTWO = ONE\nCLS", 1, "constant refers to itself"),
+        ("INCLUDE \"other.asm\"", 1, "can't include other.asm here")
+    ];
+
+    for &(source, line, message) in &cases {
+        let err = asm::assemble("bad.asm", source).unwrap_err();
+        assert_eq!((err.line, err.message.as_str()), (line, message), "{}", source);
+    }
+}
+
+#[test]
+fn disassembly_reassembles() {
+    let rom = fs::read("roms/PONG.ch8").unwrap();
+    let mut ram = vec![0; 0x200];
+    ram.extend_from_slice(&rom);
+
+    let lines = disasm::disassemble(&ram, 0x200, rom.len(), &[0x200]);
+    let program = asm::assemble("pong.asm", &disasm::listing(&lines)).unwrap();
+    assert_eq!(program.bytes, rom);
+}
@@ -0,0 +1,73 @@
+mod common;
+use common::emulator_with;
+
+// V0 = 3, sound = V0, loop
+const BEEP: [u8; 6] = [0x60, 0x03, 0xF0, 0x18, 0x12, 0x04];
+
+#[test]
+fn silent_without_sound_timer() {
+    let mut emulator = emulator_with(&[0x12, 0x00]);
+    emulator.tick_frame().unwrap();
+
+    let samples = emulator.audio_samples(48000);
+    assert_eq!(samples.len(), 800);
+    assert!(samples.iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn sound_timer_plays_square_wave() {
+    let mut emulator = emulator_with(&BEEP);
+    emulator.set_audio_frequency(480.0);
+    emulator.set_audio_volume(0.5);
+    emulator.tick_frame().unwrap();
+    assert!(emulator.is_beeping());
+
+    // 48000 / 480 = 100 samples per period, half high and half low
+    let samples = emulator.audio_samples(48000);
+    assert!(samples[.. 50].iter().all(|&s| s == 0.5));
+    assert!(samples[50 .. 100].iter().all(|&s| s == -0.5));
+    assert_eq!(samples[100], 0.5);
+}
+
+#[test]
+fn sound_timer_counts_down_at_60hz() {
+    // set the timer once, then spin
+    let mut emulator = emulator_with(&BEEP);
+    emulator.tick_frame().unwrap();
+
+    let mut frames_playing = 0;
+    for _ in 0 .. 10 {
+        if emulator.audio_samples(44100).iter().any(|&s| s != 0.0) {
+            frames_playing += 1;
+        }
+        emulator.tick_frame().unwrap();
+    }
+
+    assert_eq!(frames_playing, 3);
+    assert!(!emulator.is_beeping());
+}
+
+#[test]
+fn fractional_sample_counts_carry_over() {
+    let mut emulator = emulator_with(&[0x12, 0x00]);
+    let total: usize = (0 .. 60).map(|_| emulator.audio_samples(22050).len()).sum();
+
+    assert_eq!(total, 22050);
+}
+
+#[test]
+fn xo_chip_pattern_replaces_square_wave() {
+    // I = pattern, load it, pitch 64 (4000Hz playback), sound = 3, loop
+    let mut rom = vec![0xA2, 0x0E, 0xF0, 0x02, 0x60, 0x40, 0xF0, 0x3A, 0x60, 0x03, 0xF0, 0x18, 0x12, 0x0C];
+    rom.extend(vec![0xFF; 8]);
+    rom.extend(vec![0x00; 8]);
+
+    let mut emulator = emulator_with(&rom);
+    emulator.set_audio_volume(1.0);
+    emulator.tick_frame().unwrap();
+
+    // 4000Hz playback at a 12000Hz sample rate holds each pattern bit for 3 samples
+    let samples = emulator.audio_samples(12000);
+    assert!(samples[.. 192].iter().all(|&s| s == 1.0));
+    assert!(samples[192 ..].iter().all(|&s| s == -1.0));
+}
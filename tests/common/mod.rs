@@ -0,0 +1,22 @@
+// Fixtures shared by the integration tests, each of which uses only some of them
+#![allow(dead_code)]
+
+use skylark::emu::{ asm, Emulator };
+
+pub fn assemble(source: &str) -> Vec<u8> {
+    asm::assemble("test.asm", source).unwrap().bytes
+}
+
+// `emulator` keeps its settings (quirks, timing, config, extended memory) across the load
+pub fn load(mut emulator: Emulator, rom: &[u8]) -> Emulator {
+    emulator.load_rom(rom.to_vec());
+    emulator
+}
+
+pub fn emulator_with(rom: &[u8]) -> Emulator {
+    load(Emulator::new(), rom)
+}
+
+pub fn emulator_with_source(source: &str) -> Emulator {
+    emulator_with(&assemble(source))
+}
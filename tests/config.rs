@@ -0,0 +1,90 @@
+use skylark::emu::{ Emulator, EmulatorConfig };
+
+mod common;
+
+// counts in V0, two instructions a loop
+const COUNTER: &str = "start: ADD V0, 1\nJP start";
+
+fn emulator_with(source: &str, config: EmulatorConfig) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.set_config(&config);
+    common::load(emulator, &common::assemble(source))
+}
+
+fn config(instructions_per_frame: u32, timer_frequency: u32) -> EmulatorConfig {
+    EmulatorConfig { instructions_per_frame, timer_frequency }
+}
+
+#[test]
+fn defaults_match_the_old_constants() {
+    assert_eq!(EmulatorConfig::new(), config(10, 60));
+    assert_eq!(EmulatorConfig::with_clock_rate(1000), config(17, 60));
+    assert_eq!(Emulator::new().config(), EmulatorConfig::new());
+
+    // zeros would stall the emulator
+    let mut emulator = Emulator::new();
+    emulator.set_config(&config(0, 0));
+    assert_eq!(emulator.config(), config(1, 1));
+}
+
+#[test]
+fn instructions_per_frame_sets_the_speed() {
+    let mut emulator = emulator_with(COUNTER, config(20, 60));
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 10);
+
+    // and can change between frames
+    emulator.set_config(&config(4, 60));
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 12);
+}
+
+#[test]
+fn run_for_keeps_the_same_speed_at_any_call_rate() {
+    let mut at_60hz = emulator_with(COUNTER, config(4, 60));
+    for _ in 0 .. 60 {
+        at_60hz.run_for(1000.0 / 60.0).unwrap();
+    }
+
+    // fractions of a frame carry over between calls
+    let mut at_144hz = emulator_with(COUNTER, config(4, 60));
+    let frames: u32 = (0 .. 144).map(|_| at_144hz.run_for(1000.0 / 144.0).unwrap()).sum();
+
+    assert_eq!(at_60hz.frame_count(), 60);
+    assert_eq!(at_60hz.registers()[0], 120);
+    assert!((59 ..= 60).contains(&frames));
+    assert!((119 ..= 120).contains(&at_144hz.registers()[0]));
+
+    // a long gap isn't caught up on past a second
+    let mut emulator = emulator_with(COUNTER, config(4, 60));
+    assert_eq!(emulator.run_for(60_000.0).unwrap(), 60);
+}
+
+#[test]
+fn timer_frequency_leaves_the_speed_alone() {
+    // 10 instructions a frame is 600 a second whatever the timer frequency
+    let mut emulator = emulator_with(COUNTER, config(10, 120));
+    emulator.run_for(250.0).unwrap();
+    assert_eq!(emulator.frame_count(), 15);
+    assert_eq!(emulator.registers()[0], 75);
+
+    // and a frame's audio still lasts 1/60 s
+    assert_eq!(emulator.audio_samples(48000).len(), 800);
+}
+
+#[test]
+fn timer_frequency_sets_the_timer_rate() {
+    // DT = 100, then spin
+    let mut emulator = emulator_with("LD V0, 100\nLD DT, V0\nspin: JP spin", config(10, 120));
+    emulator.run_for(500.0).unwrap();
+    assert_eq!(emulator.frame_count(), 30);
+    // set in the first frame, then twice a frame for the other 29
+    assert_eq!(emulator.delay_timer(), 42);
+
+    // rates that don't divide into frames carry the rest over
+    let mut emulator = emulator_with("LD V0, 100\nLD DT, V0\nspin: JP spin", config(10, 90));
+    for _ in 0 .. 3 {
+        emulator.tick_frame().unwrap();
+    }
+    assert_eq!(emulator.delay_timer(), 97);
+}
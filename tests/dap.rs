@@ -0,0 +1,291 @@
+use skylark::emu::{ asm, dap };
+use std::io::{ self, BufRead, BufReader, Read, Write };
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::{ env, fs, process, thread };
+
+// the adapter keeps its JSON to itself, the client side of the test shares the file
+#[allow(dead_code)]
+#[path = "../src/emu/dap/json.rs"]
+mod json;
+use json::Json;
+
+// start:         0x200
+//     LD V0, 5
+//     CALL sub   0x202
+//     JP start   0x204
+// sub:
+//     ADD V1, 1  0x206
+//     RET        0x208
+const PROGRAM: &str = "start:
+    LD V0, 5
+    CALL sub
+    JP start
+sub:
+    ADD V1, 1
+    RET
+";
+
+// one end of an in-memory pipe
+struct Pipe {
+    chunks: mpsc::Receiver<Vec<u8>>,
+    chunk: io::Cursor<Vec<u8>>
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.position() as usize == self.chunk.get_ref().len() {
+            match self.chunks.recv() {
+                Ok(chunk) => self.chunk = io::Cursor::new(chunk),
+                Err(_) => return Ok(0)
+            }
+        }
+        self.chunk.read(buf)
+    }
+}
+
+struct Sender(mpsc::Sender<Vec<u8>>);
+
+impl Write for Sender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf.to_vec()).map_err(|_| io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn pipe() -> (Sender, BufReader<Pipe>) {
+    let (sender, chunks) = mpsc::channel();
+    (Sender(sender), BufReader::new(Pipe { chunks, chunk: io::Cursor::new(Vec::new()) }))
+}
+
+struct Client {
+    to_server: Sender,
+    from_server: BufReader<Pipe>,
+    seq: u64,
+    // events read while waiting for a response
+    events: Vec<Json>,
+    server: thread::JoinHandle<()>
+}
+
+impl Client {
+    fn start() -> Client {
+        let (to_server, input) = pipe();
+        let (output, from_server) = pipe();
+        let server = thread::spawn(move || dap::serve(input, output).unwrap());
+
+        Client { to_server, from_server, seq: 0, events: Vec::new(), server }
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.from_server.read_line(&mut header).unwrap();
+            match header.trim().strip_prefix("Content-Length: ") {
+                Some(n) => length = n.parse().unwrap(),
+                None if header.trim().is_empty() => break,
+                None => panic!("unexpected header {}", header)
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.from_server.read_exact(&mut body).unwrap();
+        Json::parse(std::str::from_utf8(&body).unwrap()).unwrap()
+    }
+
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        self.seq += 1;
+        let message = Json::object(vec![
+            ("seq", self.seq.into()),
+            ("type", "request".into()),
+            ("command", command.into()),
+            ("arguments", arguments)
+        ]).to_string();
+        write!(self.to_server, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+
+        loop {
+            let message = self.receive();
+            if message.get("type").as_str() == Some("event") {
+                self.events.push(message);
+            } else if message.get("request_seq").as_u64() == Some(self.seq) {
+                assert_eq!(message.get("command").as_str(), Some(command));
+                return message;
+            }
+        }
+    }
+
+    fn event(&mut self, name: &str) -> Json {
+        loop {
+            if let Some(at) = self.events.iter().position(|e| e.get("event").as_str() == Some(name)) {
+                return self.events.remove(at).get("body").clone();
+            }
+            let message = self.receive();
+            self.events.push(message);
+        }
+    }
+
+    fn stopped(&mut self) -> String {
+        self.event("stopped").get("reason").as_str().unwrap().to_string()
+    }
+
+    fn launch(&mut self, program: &str, stop_on_entry: bool) {
+        self.request("initialize", Json::object(vec![("adapterID", "skylark".into())]));
+        let launched = self.request("launch", Json::object(vec![
+            ("program", program.into()),
+            ("stopOnEntry", stop_on_entry.into())
+        ]));
+        assert_eq!(launched.get("success"), &Json::Bool(true), "{}", launched);
+        self.event("initialized");
+    }
+
+    fn variable(&mut self, name: &str) -> String {
+        let variables = self.request("variables", Json::object(vec![("variablesReference", 1u64.into())]));
+        let variable = variables.get("body").get("variables").as_array().iter()
+            .find(|v| v.get("name").as_str() == Some(name))
+            .unwrap()
+            .clone();
+        variable.get("value").as_str().unwrap().to_string()
+    }
+
+    fn disconnect(mut self) {
+        self.request("disconnect", Json::Null);
+        self.server.join().unwrap();
+    }
+}
+
+fn temp_file(name: &str, contents: &[u8]) -> String {
+    let path: PathBuf = env::temp_dir().join(format!("skylark-dap-{}-{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn set_breakpoints(client: &mut Client, path: &str, lines: &[u64]) -> Json {
+    let breakpoints: Vec<Json> = lines.iter().map(|&l| Json::object(vec![("line", l.into())])).collect();
+    client.request("setBreakpoints", Json::object(vec![
+        ("source", Json::object(vec![("path", path.into())])),
+        ("breakpoints", breakpoints.into())
+    ]))
+}
+
+#[test]
+fn breakpoints_on_source_lines() {
+    let path = temp_file("breakpoints.asm", PROGRAM.as_bytes());
+    let mut client = Client::start();
+    client.launch(&path, false);
+
+    // the label line moves down to the instruction after it
+    let response = set_breakpoints(&mut client, &path, &[5, 20]);
+    let results = response.get("body").get("breakpoints").as_array();
+    assert_eq!((results[0].get("verified"), results[0].get("line").as_u64()), (&Json::Bool(true), Some(6)));
+    assert_eq!(results[1].get("verified"), &Json::Bool(false));
+
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.stopped(), "breakpoint");
+
+    let trace = client.request("stackTrace", Json::object(vec![("threadId", 1u64.into())]));
+    let frames = trace.get("body").get("stackFrames").as_array();
+    let summary: Vec<(&str, u64)> = frames.iter()
+        .map(|f| (f.get("name").as_str().unwrap(), f.get("line").as_u64().unwrap()))
+        .collect();
+    assert_eq!(summary, [("sub", 6), ("start+2", 3)]);
+    assert!(frames[0].get("source").get("path").as_str().unwrap().ends_with("breakpoints.asm"));
+
+    assert_eq!((client.variable("V0"), client.variable("V1"), client.variable("PC")), ("0x05".into(), "0x00".into(), "0x206".into()));
+
+    client.request("continue", Json::object(vec![("threadId", 1u64.into())]));
+    assert_eq!(client.stopped(), "breakpoint");
+    assert_eq!(client.variable("V1"), "0x01");
+
+    let stack = client.request("variables", Json::object(vec![("variablesReference", 2u64.into())]));
+    assert_eq!(stack.get("body").get("variables").as_array()[0].get("value").as_str(), Some("0x204"));
+    client.disconnect();
+}
+
+#[test]
+fn stepping() {
+    let path = temp_file("stepping.asm", PROGRAM.as_bytes());
+    let mut client = Client::start();
+    client.launch(&path, true);
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.stopped(), "entry");
+
+    let mut step = |command: &str| {
+        client.request(command, Json::object(vec![("threadId", 1u64.into())]));
+        assert_eq!(client.stopped(), "step");
+        client.variable("PC")
+    };
+
+    assert_eq!(step("next"), "0x202");
+    // over the call
+    assert_eq!(step("next"), "0x204");
+    assert_eq!(step("stepIn"), "0x200");
+    assert_eq!(step("next"), "0x202");
+    // into it and back out
+    assert_eq!(step("stepIn"), "0x206");
+    assert_eq!(step("stepOut"), "0x204");
+
+    assert_eq!(client.variable("V1"), "0x02");
+    client.disconnect();
+}
+
+#[test]
+fn pause_faults_and_exit() {
+    let path = temp_file("loop.asm", b"loop: JP loop");
+    let mut client = Client::start();
+    client.launch(&path, false);
+    client.request("configurationDone", Json::Null);
+    client.request("pause", Json::object(vec![("threadId", 1u64.into())]));
+    assert_eq!(client.stopped(), "pause");
+    client.disconnect();
+
+    let path = temp_file("fault.asm", b"CLS\nDW 0x0123");
+    let mut client = Client::start();
+    client.launch(&path, false);
+    client.request("configurationDone", Json::Null);
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("reason").as_str(), Some("exception"));
+    assert!(stopped.get("text").as_str().unwrap().contains("0x202"));
+    client.disconnect();
+
+    let path = temp_file("exit.asm", b"EXIT");
+    let mut client = Client::start();
+    client.launch(&path, false);
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.event("exited").get("exitCode").as_u64(), Some(0));
+    client.event("terminated");
+    client.disconnect();
+}
+
+#[test]
+fn roms_use_the_symbol_map_beside_them() {
+    let source = temp_file("rom.asm", PROGRAM.as_bytes());
+    let program = asm::assemble_file(&source).unwrap();
+    let rom = temp_file("rom.ch8", &program.bytes);
+    temp_file("rom.sym", (program.symbol_map() + &program.line_map()).as_bytes());
+
+    let mut client = Client::start();
+    client.launch(&rom, false);
+    let response = set_breakpoints(&mut client, &source, &[6]);
+    assert_eq!(response.get("body").get("breakpoints").as_array()[0].get("verified"), &Json::Bool(true));
+
+    client.request("configurationDone", Json::Null);
+    assert_eq!(client.stopped(), "breakpoint");
+    assert_eq!(client.variable("PC"), "0x206");
+    client.disconnect();
+}
+
+#[test]
+fn unknown_requests_fail() {
+    let mut client = Client::start();
+    let response = client.request("evaluate", Json::object(vec![("expression", "V0".into())]));
+    assert_eq!(response.get("success"), &Json::Bool(false));
+    assert_eq!(response.get("message").as_str(), Some("evaluate is not supported"));
+
+    let response = client.request("continue", Json::Null);
+    assert_eq!(response.get("message").as_str(), Some("no program has been launched"));
+    client.disconnect();
+}
@@ -0,0 +1,118 @@
+use skylark::emu::{ BreakReason, Compare, Quirks, WatchKind };
+
+mod common;
+use common::emulator_with_source;
+
+// counts V0 up forever, storing it at 0x300 and drawing a digit each time round
+const COUNTER: &str = "
+    start:
+        ADD V0, 1
+        LD I, 0x300
+        LD [I], V0
+        LD F, V0
+        DRW V1, V1, 5
+        JP start
+";
+
+#[test]
+fn step_runs_one_instruction() {
+    let mut emulator = emulator_with_source(COUNTER);
+
+    assert_eq!(emulator.step(), Ok(BreakReason::Step));
+    assert_eq!(emulator.pc(), 0x202);
+    assert_eq!(emulator.step(), Ok(BreakReason::Step));
+    assert_eq!(emulator.pc(), 0x204);
+
+    // the rest of the frame still runs, then frames carry on as usual
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.frame_count(), 1);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.frame_count(), 2);
+}
+
+#[test]
+fn step_moves_past_a_vblank_wait() {
+    let mut emulator = emulator_with_source(COUNTER);
+    emulator.set_quirks(&Quirks::vip());
+
+    for _ in 0 .. 5 {
+        emulator.step().unwrap();
+    }
+    assert_eq!(emulator.frame_count(), 1);
+
+    emulator.step().unwrap();
+    assert_eq!(emulator.pc(), 0x200);
+}
+
+#[test]
+fn breakpoints_stop_before_the_instruction_and_resume() {
+    let mut emulator = emulator_with_source(COUNTER);
+    emulator.add_breakpoint(0x208);
+
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Breakpoint));
+    assert_eq!(emulator.pc(), 0x208);
+
+    // resuming runs the instruction at the breakpoint and stops there next time round
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Breakpoint));
+    assert_eq!(emulator.pc(), 0x208);
+
+    assert!(emulator.remove_breakpoint(0x208));
+    assert_eq!(emulator.run_until_break(3), Ok(BreakReason::FrameLimit));
+
+    // a break where execution starts fires before anything runs
+    let mut emulator = emulator_with_source(COUNTER);
+    emulator.add_breakpoint(0x200);
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Breakpoint));
+    assert_eq!((emulator.pc(), emulator.registers()[0]), (0x200, 0));
+}
+
+#[test]
+fn conditional_breakpoints_and_register_breaks() {
+    let mut emulator = emulator_with_source(COUNTER);
+    emulator.add_conditional_breakpoint(0x202, 0, Compare::Eq, 5);
+
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Breakpoint));
+    assert_eq!(emulator.pc(), 0x202);
+
+    emulator.clear_breakpoints();
+    emulator.add_register_break(0, Compare::Ge, 8);
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Register));
+    assert_eq!(emulator.pc(), 0x202);
+
+    // only the change to true fires
+    assert_eq!(emulator.run_until_break(2), Ok(BreakReason::FrameLimit));
+}
+
+#[test]
+fn watchpoints_see_accesses_through_i() {
+    let mut emulator = emulator_with_source(COUNTER);
+    emulator.add_watchpoint(0x300, 1, WatchKind::Write);
+
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::WriteWatch));
+    assert_eq!((emulator.pc(), emulator.watch_hit()), (0x204, Some(0x300)));
+
+    emulator.clear_breakpoints();
+    // the glyph for V0 = 1
+    emulator.add_watchpoint(0x5, 5, WatchKind::Read);
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::ReadWatch));
+    assert_eq!(emulator.pc(), 0x208);
+}
+
+#[test]
+fn opcode_breaks() {
+    let mut emulator = emulator_with_source(COUNTER);
+
+    assert!(!emulator.break_on_opcode("XYZ"));
+    assert!(!emulator.break_on_opcode("XXXX"));
+    assert!(emulator.break_on_opcode("DXYN"));
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Opcode));
+    assert_eq!(emulator.pc(), 0x208);
+}
+
+#[test]
+fn halted_roms_stop_the_debugger() {
+    let mut emulator = emulator_with_source("EXIT");
+
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Halted));
+    assert_eq!(emulator.step(), Ok(BreakReason::Halted));
+}
@@ -0,0 +1,114 @@
+use skylark::emu::{ disasm, Emulator, Instruction };
+
+fn lines_for(rom: &[u8]) -> Vec<disasm::Line> {
+    let mut ram = vec![0; 0x200];
+    ram.extend_from_slice(rom);
+    disasm::disassemble(&ram, 0x200, rom.len(), &[0x200])
+}
+
+#[test]
+fn mnemonics() {
+    let cases = [
+        (0x00E0, "CLS"),
+        (0x6060, "LD V0, 0x60"),
+        (0xDAB6, "DRW VA, VB, 6"),
+        (0x8AB6, "SHR VA, VB"),
+        (0xA2EA, "LD I, 0x2EA"),
+        (0xB300, "JP V0, 0x300"),
+        (0xF30A, "LD V3, K"),
+        (0xF233, "LD B, V2"),
+        (0x00C4, "SCD 4"),
+        (0x5232, "SAVE V2 - V3"),
+        (0xF201, "PLANE 2")
+    ];
+
+    for &(opcode, text) in &cases {
+        assert_eq!(Instruction::decode(opcode).unwrap().to_string(), text);
+    }
+}
+
+#[test]
+fn undefined_opcodes_do_not_decode() {
+    for &opcode in &[0x5001, 0x800F, 0xE000, 0xF0FF, 0xF101 + 0x10] {
+        assert_eq!(Instruction::decode(opcode), None, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn targets_get_labels() {
+    // call a subroutine, loop forever; the subroutine points I at a sprite and draws it
+    let rom = [
+        0x22, 0x04, // CALL sub_204
+        0x12, 0x02, // JP L202
+        0xA2, 0x0A, // LD I, data_20A
+        0xD0, 0x01, // DRW V0, V0, 1
+        0x00, 0xEE, // RET
+        0xF0        // sprite
+    ];
+    let lines = lines_for(&rom);
+    let text: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+
+    assert_eq!(text, ["CALL sub_204", "JP L202", "LD I, data_20A", "DRW V0, V0, 1", "RET", "DB 0xF0"]);
+    assert_eq!(lines[1].label.as_deref(), Some("L202"));
+    assert_eq!(lines[2].label.as_deref(), Some("sub_204"));
+    assert_eq!(lines[5].label.as_deref(), Some("data_20A"));
+}
+
+#[test]
+fn unreached_bytes_are_data() {
+    // the bytes after the jump decode as valid opcodes but are never executed
+    let rom = [0x12, 0x00, 0x60, 0x60, 0x00, 0xE0];
+    let lines = lines_for(&rom);
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].is_code());
+    assert!(!lines[1].is_code());
+    assert_eq!(lines[1].text, "DB 0x60, 0x60, 0x00, 0xE0");
+}
+
+#[test]
+fn skips_reach_both_paths() {
+    // skip over a jump to a routine that would otherwise look unreachable
+    let rom = [
+        0x30, 0x00, // SE V0, 0x00
+        0x12, 0x08, // JP L208
+        0x12, 0x04, // JP L204
+        0x00, 0x00,
+        0x00, 0xFD  // EXIT
+    ];
+    let lines = lines_for(&rom);
+
+    assert!(lines.iter().any(|l| l.addr == 0x204 && l.is_code()));
+    assert!(lines.iter().any(|l| l.addr == 0x208 && l.text == "EXIT"));
+    assert!(lines.iter().any(|l| l.addr == 0x206 && !l.is_code()));
+}
+
+#[test]
+fn long_load_covers_four_bytes() {
+    let rom = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD];
+    let lines = lines_for(&rom);
+
+    assert_eq!(lines[0].bytes, [0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(lines[0].text, "LD I, LONG 0x1234");
+    assert_eq!(lines[1].text, "EXIT");
+}
+
+#[test]
+fn emulator_disassembles_ram() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(std::fs::read("roms/PONG.ch8").unwrap());
+    let listing = emulator.disassemble(0x200, 0x10);
+
+    assert!(listing.starts_with("    LD VA, 0x02"));
+    assert!(listing.contains("LD I, 0x2EA"));
+    assert!(listing.contains("; 0x20A  DAB6"));
+}
+
+#[test]
+fn length_past_the_end_stops_at_ram() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(std::fs::read("roms/PONG.ch8").unwrap());
+    let listing = emulator.disassemble(0x200, usize::MAX);
+
+    assert!(listing.starts_with("    LD VA, 0x02"));    assert!(listing.contains("; 0x20A  DAB6"));
+}
@@ -0,0 +1,169 @@
+use skylark::emu::{ BreakReason, Compare, Emulator, ErrorKind, ExecError, MemoryPolicy };
+
+mod common;
+use common::emulator_with;
+
+fn run_until_error(emulator: &mut Emulator) -> ExecError {
+    (0 .. 60).find_map(|_| emulator.tick_frame().err()).expect("no error within 60 frames")
+}
+
+#[test]
+fn unknown_opcode_is_reported() {
+    // 6005: V0 = 5, then 0123 (RCA 1802 call)
+    let mut emulator = emulator_with(&[0x60, 0x05, 0x01, 0x23]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::UnknownOpcode);
+    assert_eq!(err.pc(), 0x202);
+    assert_eq!(err.opcode(), 0x0123);
+}
+
+#[test]
+fn return_without_call_underflows() {
+    let mut emulator = emulator_with(&[0x00, 0xEE]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::StackUnderflow);
+    assert_eq!(err.pc(), 0x200);
+}
+
+#[test]
+fn jump_past_ram_is_out_of_range() {
+    // jump to 0xFFF leaves no room for a full opcode
+    let mut emulator = emulator_with(&[0x1F, 0xFF]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::PcOutOfRange);
+    assert_eq!(err.pc(), 0xFFF);
+}
+
+#[test]
+fn register_dump_past_ram_is_out_of_bounds() {
+    // I = 0xFFE, then dump V0..V3
+    let mut emulator = emulator_with(&[0xAF, 0xFE, 0xF3, 0x55]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::MemoryOutOfBounds);
+    assert_eq!(err.pc(), 0x202);
+    assert_eq!(err.addr(), 0x1000);
+}
+
+#[test]
+fn register_dump_wraps_under_wrap_policy() {
+    // I = 0xFFE, V0 = 0xFF, dump V0..V3 (wrapping onto 0x000), then draw the top of glyph 0
+    let mut emulator = emulator_with(&[0xAF, 0xFE, 0x60, 0xFF, 0xF3, 0x55, 0xA0, 0x00, 0xD0, 0x01, 0x12, 0x0A]);
+    emulator.set_memory_policy(MemoryPolicy::Wrap);
+    emulator.tick_frame().unwrap();
+
+    // V2 (0) landed on 0x000, so glyph 0's first row is blank
+    assert!(emulator.display_out().lines().next().unwrap().chars().all(|c| c == '◻'));
+}
+
+#[test]
+fn sprite_fetch_past_ram_traps() {
+    let mut emulator = emulator_with(&[0xAF, 0xFC, 0xD0, 0x18, 0x12, 0x04]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::MemoryOutOfBounds);
+    assert_eq!(err.addr(), 0x1000);
+
+    emulator.set_memory_policy(MemoryPolicy::Wrap);
+    assert!(emulator.tick_frame().is_ok());
+}
+
+#[test]
+fn runaway_recursion_overflows_stack() {
+    // 0x200: call 0x200
+    let mut emulator = emulator_with(&[0x22, 0x00]);
+    let err = run_until_error(&mut emulator);
+
+    assert_eq!(err.kind(), ErrorKind::StackOverflow);
+    assert_eq!(emulator.stack_pointer(), 16);
+    assert_eq!(emulator.stack(), vec![0x202; 16]);
+}
+
+#[test]
+fn stack_depth_is_configurable() {
+    let mut emulator = Emulator::new();
+    emulator.set_stack_depth(12);
+    emulator.load_rom(vec![0x22, 0x00]);
+    let err = run_until_error(&mut emulator);
+
+    assert_eq!(err.kind(), ErrorKind::StackOverflow);
+    assert_eq!(emulator.stack().len(), 12);
+}
+
+#[test]
+fn call_and_return_restore_stack() {
+    // call 0x206, loop at 0x202 / 0x206: V0 = 1, return
+    let mut emulator = emulator_with(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x01, 0x00, 0xEE]);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(emulator.stack_pointer(), 0);
+    assert!(emulator.stack().is_empty());
+}
+
+#[test]
+fn machine_state_is_observable() {
+    // V3 = 0x2A, I = 0x300, BCD V3, delay = V3, call 0x20C / 0x20C: loop
+    let mut emulator = emulator_with(&[0x63, 0x2A, 0xA3, 0x00, 0xF3, 0x33, 0xF3, 0x15, 0x22, 0x0C, 0x00, 0x00, 0x12, 0x0C]);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(emulator.registers()[3], 0x2A);
+    assert_eq!(emulator.index(), 0x300);
+    assert_eq!(emulator.peek(0x300, 3), [0, 4, 2]);
+    assert_eq!(emulator.delay_timer(), 0x2A);
+    assert_eq!(emulator.sound_timer(), 0);
+    assert_eq!(emulator.stack(), [0x20A]);
+    assert_eq!(emulator.pc(), 0x20C);
+
+    // reads are cut short at the end of ram
+    assert_eq!(emulator.peek(0xFFE, 4).len(), 2);
+}
+
+#[test]
+fn machine_state_is_observable_at_a_breakpoint() {
+    // V0 += 1, I = 0x300, store V0, loop
+    let mut emulator = emulator_with(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]);
+    emulator.add_conditional_breakpoint(0x202, 0, Compare::Eq, 5);
+
+    assert_eq!(emulator.run_until_break(10), Ok(BreakReason::Breakpoint));
+    assert_eq!((emulator.pc(), emulator.registers()[0]), (0x202, 5));
+    // the store of 5 hasn't run yet
+    assert_eq!(emulator.peek(0x300, 1), [4]);
+}
+
+#[test]
+fn machine_state_is_writable() {
+    // V1 = V0, loop
+    let mut emulator = emulator_with(&[0x81, 0x00, 0x12, 0x02]);
+    emulator.set_register(0, 7);
+    emulator.set_register(16, 1);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[1], 7);
+
+    emulator.poke(0x200, &[0x60, 0x09, 0x12, 0x00]);
+    emulator.set_pc(0x200);
+    emulator.set_index(0x123);
+    emulator.set_delay_timer(10);
+    emulator.set_sound_timer(3);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(emulator.registers()[0], 9);
+    assert_eq!(emulator.index(), 0x123);
+    assert_eq!((emulator.delay_timer(), emulator.sound_timer()), (9, 2));
+
+    // bytes past the end are dropped
+    emulator.poke(0xFFF, &[1, 2]);
+    assert_eq!(emulator.peek(0xFFF, 1), [1]);
+}
+
+#[test]
+fn font_glyph_uses_the_low_digit() {
+    // V0 = 0x1A, I = sprite[V0], loop
+    let mut emulator = emulator_with(&[0x60, 0x1A, 0xF0, 0x29, 0x12, 0x04]);
+    emulator.tick_frame().unwrap();
+
+    // glyph A, not 0x1A glyphs past the start of the font
+    assert_eq!(emulator.index(), 0xA * 5);
+}
@@ -0,0 +1,393 @@
+use skylark::emu::cpu::Cpu;
+use skylark::emu::{ audio, display, keyboard, memory, rng, timer };
+use skylark::emu::{ ErrorKind, MemoryPolicy, Quirks };
+use proptest::array::uniform16;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::test_runner::TestCaseError;
+
+// Differential fuzzing of Cpu::tick against Reference, a plain model of the original
+// instruction set written from the spec rather than from cpu.rs. Random programs run on
+// both from the same random registers, keys and ram, and everything an instruction can
+// touch is compared after each one. PROPTEST_CASES sets how many programs to run, and a
+// failing case is shrunk and saved in fuzz.proptest-regressions to be rerun first.
+
+const STEPS: usize = 64;
+
+const RAM_SIZE: usize = 0x1000;
+const PROGRAM: usize = 0x200;
+const PROGRAM_LEN: usize = 0x100;
+// ANNN points at or past here, so stores never overwrite the program
+const DATA: usize = 0x400;
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const STACK_DEPTH: usize = 16;
+
+// a jump target inside the program
+fn target() -> impl Strategy<Value = u16> {
+    (0 .. PROGRAM_LEN / 2).prop_map(|at| (PROGRAM + 2 * at) as u16)
+}
+
+// an opcode from the original instruction set, jumps landing in the program
+fn opcode() -> impl Strategy<Value = u16> {
+    let operands = (0 .. 16u16, 0 .. 16u16, any::<u8>(), 1 .. 16u16, target());
+    // ANNN near the end of ram now and then, to fault
+    let addr = (DATA as u16 .. RAM_SIZE as u16, prop::bool::weighted(0.2))
+        .prop_map(|(addr, near_end)| if near_end { 0xFF0 | addr & 0xF } else { addr });
+    let alu = select(vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE]);
+    let misc = select(vec![0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65]);
+
+    (0 .. 28u8, operands, addr, alu, misc).prop_map(|(kind, (x, y, nn, n, target), addr, alu, misc)| {
+        let (x, y, nn) = (x << 8, y << 4, nn as u16);
+        match kind {
+            0 => 0x00E0,
+            1 => 0x00EE,
+            2 => 0x1000 | target,
+            3 => 0x2000 | target,
+            4 => 0x3000 | x | nn,
+            5 => 0x4000 | x | nn,
+            6 => 0x5000 | x | y,
+            7 | 8 => 0x6000 | x | nn,
+            9 => 0x7000 | x | nn,
+            10 ..= 12 => 0x8000 | x | y | alu,
+            13 => 0x9000 | x | y,
+            14 => 0xA000 | addr,
+            15 => 0xB000 | target,
+            16 => 0xC000 | x | nn,
+            17 | 18 => 0xD000 | x | y | n,
+            19 => 0xE09E | x,
+            20 => 0xE0A1 | x,
+            _ => 0xF000 | x | misc
+        }
+    })
+}
+
+// half the cases under the defaults, display_wait is left to tests/quirks.rs
+fn quirks() -> impl Strategy<Value = Quirks> {
+    prop_oneof![
+        Just(Quirks::new()),
+        any::<[bool; 6]>().prop_map(|[shift_vy, load_store_inc_i, load_store_inc_i_by_x, jump_vx, vf_reset, clip_sprites]| Quirks {
+            shift_vy,
+            load_store_inc_i,
+            load_store_inc_i_by_x,
+            jump_vx,
+            vf_reset,
+            clip_sprites,
+            display_wait: false
+        })
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Ran,
+    // CXNN, Vx is whatever the cpu drew masked with NN
+    Random(usize, u8),
+    Fault(ErrorKind),
+    // outside the original instruction set, the case ends here
+    Unmodelled
+}
+
+#[derive(Debug)]
+struct Reference {
+    v: [u8; 16],
+    i: u16,
+    pc: usize,
+    stack: Vec<u16>,
+    ram: Vec<u8>,
+    screen: Vec<bool>,
+    keys: [bool; 16],
+    delay: u8,
+    sound: u8,
+    quirks: Quirks
+}
+
+impl Reference {
+    fn step(&mut self) -> Step {
+        if self.pc + 1 >= RAM_SIZE {
+            return Step::Fault(ErrorKind::PcOutOfRange);
+        }
+
+        let op = (self.ram[self.pc] as u16) << 8 | self.ram[self.pc + 1] as u16;
+        let x = (op >> 8 & 0xF) as usize;
+        let y = (op >> 4 & 0xF) as usize;
+        let n = (op & 0xF) as usize;
+        let nn = op as u8;
+        let nnn = op & 0xFFF;
+        let (vx, vy) = (self.v[x], self.v[y]);
+        let mut next = self.pc + 2;
+        let mut skip = false;
+
+        match (op >> 12, n, nn) {
+            (0x0, _, 0xE0) if op == 0x00E0 => self.screen.iter_mut().for_each(|p| *p = false),
+            (0x0, _, 0xEE) if op == 0x00EE => match self.stack.pop() {
+                Some(addr) => next = addr as usize,
+                None => return Step::Fault(ErrorKind::StackUnderflow)
+            },
+            (0x1, _, _) => next = nnn as usize,
+            (0x2, _, _) => {
+                if self.stack.len() == STACK_DEPTH {
+                    return Step::Fault(ErrorKind::StackOverflow);
+                }
+                self.stack.push(next as u16);
+                next = nnn as usize;
+            }
+            (0x3, _, _) => skip = vx == nn,
+            (0x4, _, _) => skip = vx != nn,
+            (0x5, 0, _) => skip = vx == vy,
+            (0x6, _, _) => self.v[x] = nn,
+            (0x7, _, _) => self.v[x] = vx.wrapping_add(nn),
+            (0x8, 0x0, _) => self.v[x] = vy,
+            (0x8, 0x1 ..= 0x3, _) => {
+                self.v[x] = match n { 1 => vx | vy, 2 => vx & vy, _ => vx ^ vy };
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            // the flag is written after the result, so it wins when x is F
+            (0x8, 0x4, _) => self.set_with_flag(x, vx.wrapping_add(vy), vx as u16 + vy as u16 > 0xFF),
+            (0x8, 0x5, _) => self.set_with_flag(x, vx.wrapping_sub(vy), vx >= vy),
+            (0x8, 0x7, _) => self.set_with_flag(x, vy.wrapping_sub(vx), vy >= vx),
+            (0x8, 0x6, _) => {
+                let src = if self.quirks.shift_vy { vy } else { vx };
+                self.set_with_flag(x, src >> 1, src & 1 == 1)
+            }
+            (0x8, 0xE, _) => {
+                let src = if self.quirks.shift_vy { vy } else { vx };
+                self.set_with_flag(x, src << 1, src & 0x80 != 0)
+            }
+            (0x9, 0, _) => skip = vx != vy,
+            (0xA, _, _) => self.i = nnn,
+            (0xB, _, _) => {
+                let offset = if self.quirks.jump_vx { vx } else { self.v[0] };
+                next = nnn as usize + offset as usize;
+            }
+            (0xC, _, _) => {
+                self.pc = next;
+                return Step::Random(x, nn);
+            }
+            (0xD, 1 ..= 15, _) => {
+                let Some(sprite) = self.ram_at(self.i as usize, n) else {
+                    return Step::Fault(ErrorKind::MemoryOutOfBounds);
+                };
+                let collision = self.draw(vx as usize % WIDTH, vy as usize % HEIGHT, &sprite);
+                self.v[0xF] = collision as u8;
+            }
+            (0xE, _, 0x9E) => skip = self.keys[vx as usize & 0xF],
+            (0xE, _, 0xA1) => skip = !self.keys[vx as usize & 0xF],
+            (0xF, _, 0x07) => self.v[x] = self.delay,
+            (0xF, _, 0x0A) => match self.keys.iter().position(|&k| k) {
+                Some(key) => self.v[x] = key as u8,
+                None => next = self.pc
+            },
+            (0xF, _, 0x15) => self.delay = vx,
+            (0xF, _, 0x18) => self.sound = vx,
+            (0xF, _, 0x1E) => self.i = self.i.wrapping_add(vx as u16),
+            // the low digit of Vx, as on the VIP
+            (0xF, _, 0x29) => self.i = 5 * (vx & 0xF) as u16,
+            (0xF, _, 0x33) => {
+                if self.ram_at(self.i as usize, 3).is_none() {
+                    return Step::Fault(ErrorKind::MemoryOutOfBounds);
+                }
+                let at = self.i as usize;
+                self.ram[at .. at + 3].copy_from_slice(&[vx / 100, vx / 10 % 10, vx % 10]);
+            }
+            (0xF, _, 0x55) | (0xF, _, 0x65) => {
+                if self.ram_at(self.i as usize, x + 1).is_none() {
+                    return Step::Fault(ErrorKind::MemoryOutOfBounds);
+                }
+                let at = self.i as usize;
+                if nn == 0x55 {
+                    self.ram[at ..= at + x].copy_from_slice(&self.v[..= x]);
+                } else {
+                    self.v[..= x].copy_from_slice(&self.ram[at ..= at + x]);
+                }
+                if self.quirks.load_store_inc_i {
+                    self.i += x as u16 + 1;
+                } else if self.quirks.load_store_inc_i_by_x {
+                    self.i += x as u16;
+                }
+            }
+            _ => return Step::Unmodelled
+        }
+
+        if skip {
+            // XO-CHIP's 4 byte F000 NNNN would be skipped whole
+            if self.ram.get(next .. next + 2) == Some(&[0xF0, 0x00]) {
+                return Step::Unmodelled;
+            }
+            next += 2;
+        }
+
+        self.pc = next;
+        Step::Ran
+    }
+
+    fn set_with_flag(&mut self, x: usize, value: u8, flag: bool) {
+        self.v[x] = value;
+        self.v[0xF] = flag as u8;
+    }
+
+    fn ram_at(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        self.ram.get(addr .. addr + len).map(|bytes| bytes.to_vec())
+    }
+
+    // XOR a sprite onto the screen, true if it turned any pixel off
+    fn draw(&mut self, left: usize, top: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+        for (row, bits) in sprite.iter().enumerate() {
+            if self.quirks.clip_sprites && top + row >= HEIGHT {
+                break;
+            }
+
+            for col in (0 .. 8).filter(|col| bits << col & 0x80 != 0) {
+                if self.quirks.clip_sprites && left + col >= WIDTH {
+                    break;
+                }
+
+                let pixel = &mut self.screen[(top + row) % HEIGHT * WIDTH + (left + col) % WIDTH];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+
+        collision
+    }
+}
+
+// Cpu::tick and everything it works on
+struct Machine {
+    cpu: Cpu,
+    ram: memory::Memory,
+    keyboard: keyboard::Keyboard,
+    display: display::DisplayFrame,
+    timer: timer::Timer,
+    audio: audio::Audio,
+    rng: rng::Rng
+}
+
+impl Machine {
+    fn of(reference: &Reference) -> Machine {
+        let mut machine = Machine {
+            cpu: Cpu::new(),
+            ram: memory::Memory::new(RAM_SIZE, MemoryPolicy::Trap),
+            keyboard: keyboard::Keyboard::new(),
+            display: display::DisplayFrame::new(),
+            timer: timer::Timer::new(),
+            audio: audio::Audio::new(),
+            rng: rng::Rng::default()
+        };
+
+        machine.cpu.set_quirks(reference.quirks);
+        machine.cpu.v_mut().copy_from_slice(&reference.v);
+        machine.cpu.set_i(reference.i);
+        machine.ram.as_mut_slice().copy_from_slice(&reference.ram);
+        for (key, &pressed) in reference.keys.iter().enumerate() {
+            machine.keyboard.key_change(key, pressed);
+        }
+        machine.timer.set(reference.delay);
+        machine.audio.set_timer(reference.sound);
+        machine
+    }
+
+    fn tick(&mut self) -> Result<u32, skylark::emu::ExecError> {
+        self.cpu.tick(&mut self.ram, &self.keyboard, &mut self.display, &mut self.timer, &mut self.audio, &mut self.rng)
+    }
+
+    // the first thing that differs from `reference`, if anything does
+    fn differs(&self, reference: &Reference) -> Option<String> {
+        let stack: Vec<u16> = self.cpu.stack().to_vec();
+        let lit = |x: usize, y: usize| self.display.pixel(x as u32, y as u32) != 0;
+
+        if self.cpu.v() != reference.v {
+            Some(format!("V0-VF {:02X?}, expected {:02X?}", self.cpu.v(), reference.v))
+        } else if self.cpu.pc() != reference.pc {
+            Some(format!("PC {:#05X}, expected {:#05X}", self.cpu.pc(), reference.pc))
+        } else if self.cpu.i() != reference.i {
+            Some(format!("I {:#05X}, expected {:#05X}", self.cpu.i(), reference.i))
+        } else if stack != reference.stack {
+            Some(format!("stack {:03X?}, expected {:03X?}", stack, reference.stack))
+        } else if (self.timer.get(), self.audio.timer()) != (reference.delay, reference.sound) {
+            Some(format!("delay and sound timers {:?}, expected {:?}", (self.timer.get(), self.audio.timer()), (reference.delay, reference.sound)))
+        } else if let Some(addr) = (0 .. RAM_SIZE).find(|&a| self.ram.as_slice()[a] != reference.ram[a]) {
+            Some(format!("ram at {:#05X} is {:#04X}, expected {:#04X}", addr, self.ram.as_slice()[addr], reference.ram[addr]))
+        } else {
+            (0 .. WIDTH * HEIGHT)
+                .find(|&p| lit(p % WIDTH, p / WIDTH) != reference.screen[p])
+                .map(|at| format!("pixel ({}, {}) differs", at % WIDTH, at / WIDTH))
+        }
+    }
+}
+
+// random registers, keys, quirks and ram with a random program at 0x200
+fn reference() -> impl Strategy<Value = Reference> {
+    let ram = vec(any::<u8>(), RAM_SIZE);
+    let program = vec(opcode(), PROGRAM_LEN / 2);
+    let keys = uniform16(prop::bool::weighted(0.1));
+    let i = DATA as u16 .. RAM_SIZE as u16;
+
+    (ram, program, any::<[u8; 16]>(), keys, quirks(), i, any::<u8>(), any::<u8>()).prop_map(
+        |(mut ram, program, v, keys, quirks, i, delay, sound)| {
+            for (at, op) in (PROGRAM ..).step_by(2).zip(program) {
+                ram[at .. at + 2].copy_from_slice(&op.to_be_bytes());
+            }
+
+            Reference {
+                v,
+                i,
+                pc: PROGRAM,
+                stack: Vec::new(),
+                ram,
+                screen: vec![false; WIDTH * HEIGHT],
+                keys,
+                delay,
+                sound,
+                quirks
+            }
+        }
+    )
+}
+
+// run one program until it faults, leaves the original instruction set or runs out of
+// steps, describing the first difference
+fn run_case(mut reference: Reference) -> Result<(), String> {
+    let mut machine = Machine::of(&reference);
+
+    for step in 0 .. STEPS {
+        let pc = reference.pc;
+        let op = reference.ram.get(pc .. pc + 2).map_or(0, |b| (b[0] as u16) << 8 | b[1] as u16);
+        let at = format!("step {} at {:#05X} ({:04X}) under {:?}", step, pc, op, reference.quirks);
+
+        let expected = reference.step();
+        if expected == Step::Unmodelled {
+            return Ok(());
+        }
+
+        match (machine.tick(), expected) {
+            (Ok(_), Step::Random(x, mask)) => {
+                let got = machine.cpu.v()[x];
+                if got & !mask != 0 {
+                    return Err(format!("{}: V{:X} = {:#04X} isn't masked with {:#04X}", at, x, got, mask));
+                }
+                reference.v[x] = got;
+            }
+            (Ok(_), Step::Ran) => {}
+            (Err(e), Step::Fault(kind)) if e.kind() == kind => return Ok(()),
+            (result, expected) => return Err(format!("{}: got {:?}, expected {:?}", at, result, expected))
+        }
+
+        if let Some(difference) = machine.differs(&reference) {
+            return Err(format!("{}: {}", at, difference));
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn cpu_matches_reference(reference in reference()) {
+        run_case(reference).map_err(TestCaseError::fail)?;
+    }
+}
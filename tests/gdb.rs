@@ -0,0 +1,189 @@
+use skylark::emu::{ gdb, Emulator };
+use std::io::{ Read, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::thread;
+
+mod common;
+
+const COUNTER: &str = "
+    start:
+        ADD V0, 1
+        LD I, 0x300
+        LD [I], V0
+        JP start
+";
+
+// Minimal RSP client speaking to a server thread running `source`
+struct Client {
+    stream: TcpStream,
+    server: Option<thread::JoinHandle<()>>
+}
+
+impl Client {
+    fn connect(source: &str) -> Client {
+        let rom = common::assemble(source);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let mut emulator = Emulator::new();
+            emulator.load_rom(rom);
+            let (stream, _) = listener.accept().unwrap();
+            gdb::serve(&mut emulator, stream).unwrap();
+        });
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Client { stream, server: Some(server) }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut b = [0];
+        self.stream.read_exact(&mut b).unwrap();
+        b[0]
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+', "ack for {}", data);
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                b => data.push(b)
+            }
+        }
+
+        let checksum = [self.read_byte(), self.read_byte()];
+        let expected = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        assert_eq!(std::str::from_utf8(&checksum).unwrap(), format!("{:02x}", expected));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+
+    fn detach(mut self) {
+        assert_eq!(self.request("D"), "OK");
+        self.server.take().unwrap().join().unwrap();
+    }
+}
+
+#[test]
+fn registers_and_memory() {
+    let mut client = Client::connect(COUNTER);
+
+    assert_eq!(client.request("?"), "S05");
+    // V0 - VF, I, PC, SP, DT, ST
+    assert_eq!(client.request("g"), format!("{}00000200000000", "00".repeat(16)));
+    assert_eq!(client.request("m200,4"), "7001a300");
+
+    assert_eq!(client.request("M300,2:beef"), "OK");
+    assert_eq!(client.request("m300,2"), "beef");
+    assert_eq!(client.request("Mfff,2:0000"), "E01");
+    assert_eq!(client.request("Mffffffffffffffff,1:00"), "E01");
+
+    let regs = format!("2a{}01230204000307", "00".repeat(15));
+    assert_eq!(client.request(&format!("G{}", regs)), "OK");
+    assert_eq!(client.request("g"), regs);
+    assert_eq!(client.request("p10"), "0123");
+    assert_eq!(client.request("p11"), "0204");
+
+    // unsupported packets get an empty reply
+    assert_eq!(client.request("vMustReplyEmpty"), "");
+    client.detach();
+}
+
+#[test]
+fn step_and_continue_to_breakpoints() {
+    let mut client = Client::connect(COUNTER);
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p0"), "01");
+    assert_eq!(client.request("p11"), "0202");
+
+    assert_eq!(client.request("Z0,206,2"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p11"), "0206");
+    assert_eq!(client.request("m300,1"), "01");
+
+    // resuming goes round the loop once more
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("m300,1"), "02");
+
+    assert_eq!(client.request("z0,206,2"), "OK");
+    assert_eq!(client.request("Z2,300,1"), "");
+    client.detach();
+}
+
+#[test]
+fn breakpoint_on_the_first_instruction_of_a_frame() {
+    // ten instructions round the loop, so every frame starts at 0x200
+    let mut client = Client::connect("
+        start:
+            ADD V0, 1
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            LD V1, V0
+            JP start
+    ");
+
+    assert_eq!(client.request("Z0,200,2"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p11"), "0200");
+    assert_eq!(client.request("p0"), "00");
+
+    // resuming runs the frame once and stops at the start of the next
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p11"), "0200");
+    assert_eq!(client.request("p0"), "01");
+    client.detach();
+}
+
+#[test]
+fn interrupt_stops_a_running_program() {
+    let mut client = Client::connect(COUNTER);
+
+    client.send("c");
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.receive(), "S02");
+    client.detach();
+}
+
+#[test]
+fn faults_and_exit_are_reported() {
+    let mut client = Client::connect("CLS\nDW 0x0123");
+    assert_eq!(client.request("c"), "S04");
+    assert_eq!(client.request("p11"), "0202");
+    client.detach();
+
+    let mut client = Client::connect("EXIT");
+    assert_eq!(client.request("c"), "W00");
+    client.detach();
+}
+
+#[test]
+fn no_ack_mode() {
+    let mut client = Client::connect(COUNTER);
+
+    assert!(client.request("qSupported:swbreak+").contains("QStartNoAckMode+"));
+    assert_eq!(client.request("QStartNoAckMode"), "OK");
+
+    write!(client.stream, "$g#67").unwrap();
+    assert_eq!(client.read_byte(), b'$');
+    client.stream.write_all(b"$k#6b").unwrap();
+    client.server.take().unwrap().join().unwrap();
+}
@@ -0,0 +1,85 @@
+use skylark::emu::headless::{ StopReason, Until };
+use skylark::emu::{ Emulator, Headless };
+use std::fs;
+
+mod common;
+
+fn rom(name: &str) -> Vec<u8> {
+    fs::read(format!("roms/{}", name)).unwrap()
+}
+
+fn assembled(source: &str) -> Headless {
+    Headless::new(common::assemble(source))
+}
+
+#[test]
+fn runs_match_tick_frame() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(rom("scrolling_logo.ch8"));
+    for _ in 0 .. 45 {
+        emulator.tick_frame().unwrap();
+    }
+
+    let outcome = Headless::new(rom("scrolling_logo.ch8")).run(45, &[]);
+    assert_eq!(outcome.display.to_string(), emulator.display_out());
+    assert_eq!((outcome.registers, outcome.pc, outcome.i), (emulator.registers(), emulator.pc(), emulator.index()));
+}
+
+#[test]
+fn scripted_keys() {
+    // the game starts after a 96 frame delay, then hold 4 (the left paddle's down key)
+    let mut headless = Headless::new(rom("PONG.ch8"));
+    headless.tap(100, 0x4, 15);
+    let moved = headless.run(130, &[]);
+
+    let still = Headless::new(rom("PONG.ch8")).run(130, &[]);
+    assert_ne!(moved.display, still.display);
+
+    // the same script gives the same result
+    let mut again = Headless::new(rom("PONG.ch8"));
+    again.key(100, 0x4, true).key(115, 0x4, false);
+    assert_eq!(again.run(130, &[]).display, moved.display);
+}
+
+#[test]
+fn stop_conditions() {
+    let source = "
+        start:
+            ADD V0, 1
+            SE V0, 50
+            JP start
+            CLS
+        wait:
+            LD V1, K
+            EXIT
+    ";
+
+    let outcome = assembled(source).run(60, &[Until::Pc(0x206)]);
+    assert_eq!(outcome.reason, StopReason::Until(Until::Pc(0x206)));
+    assert_eq!((outcome.registers[0], outcome.cycles, outcome.frames), (50, 149, 14));
+
+    let outcome = assembled(source).run(60, &[Until::Pc(0x206), Until::Cycles(20)]);
+    assert_eq!(outcome.reason, StopReason::Until(Until::Cycles(20)));
+    assert_eq!(outcome.registers[0], 7);
+
+    // without a key FX0A keeps waiting until the frame limit
+    let mut headless = assembled(source);
+    assert_eq!(headless.run(30, &[]).reason, StopReason::FrameLimit);
+    headless.key(40, 0xb, true);
+    let outcome = headless.run(30, &[]);
+    assert_eq!((outcome.reason, outcome.frames, outcome.registers[1]), (StopReason::Halted, 41, 0xb));
+}
+
+#[test]
+fn display_hash_and_faults() {
+    let hash = Headless::new(rom("font_test.ch8")).run(60, &[]).display.hash();
+    let outcome = Headless::new(rom("font_test.ch8")).run(600, &[Until::DisplayHash(hash)]);
+    assert_eq!(outcome.reason, StopReason::Until(Until::DisplayHash(hash)));
+    assert!(outcome.frames <= 60);
+
+    let outcome = assembled("CLS\nDW 0x0123").run(10, &[]);
+    match outcome.reason {
+        StopReason::Fault(e) => assert_eq!((e.pc(), e.opcode()), (0x202, 0x0123)),
+        reason => panic!("{:?}", reason)
+    }
+}
@@ -0,0 +1,212 @@
+use skylark::emu::{ state, Emulator, EmulatorConfig, Movie, MovieError, Quirks, Timing };
+use skylark::emu::movie::MOVIE_VERSION;
+
+// wait for a key, draw its glyph at (V1, 0) and a random byte at (V1, 6), move right, repeat
+fn key_echo() -> Vec<u8> {
+    vec![
+        0x61, 0x00, 0x62, 0x00, 0x64, 0x06, // V1 = 0, V2 = 0, V4 = 6
+        0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x25, // 0x206: V0 = key, draw glyph V0 at (V1, V2)
+        0xC3, 0xFF, 0xA3, 0x00, 0xF3, 0x55, // V3 = rand, [0x300..0x303] = V0..V3
+        0xA3, 0x03, 0xD1, 0x41,             // draw [0x303] at (V1, V4)
+        0x71, 0x05, 0x12, 0x06              // V1 += 5, loop
+    ]
+}
+
+fn record(emulator: &mut Emulator) -> (Vec<u8>, String) {
+    emulator.start_recording();
+
+    let presses = [(3, 0x1), (10, 0xA), (17, 0x5), (30, 0xF)];
+    for frame in 0 .. 40 {
+        for &(at, key) in &presses {
+            if frame == at {
+                emulator.key_change(key, true);
+            }
+            if frame == at + 2 {
+                emulator.key_change(key, false);
+            }
+        }
+        emulator.tick_frame().unwrap();
+    }
+
+    (emulator.stop_recording(), emulator.display_out())
+}
+
+#[test]
+fn replay_is_bit_identical() {
+    let mut recorder = Emulator::with_seed(77);
+    recorder.set_quirks(&Quirks::vip());
+    recorder.load_rom(key_echo());
+    let (movie, expected) = record(&mut recorder);
+
+    // different settings on the player are overridden by the movie
+    let mut player = Emulator::with_seed(1);
+    player.load_rom(key_echo());
+    player.play_movie(&movie).unwrap();
+    assert!(player.is_replaying());
+
+    // live input is ignored during playback
+    player.key_change(0x2, true);
+    while player.is_replaying() {
+        player.tick_frame().unwrap();
+    }
+
+    assert_eq!(player.frame_count(), 40);
+    assert_eq!(player.quirks(), Quirks::vip());
+    assert_eq!(player.display_out(), expected);
+}
+
+#[test]
+fn movie_records_settings_and_events() {
+    let mut recorder = Emulator::with_seed(5);
+    recorder.load_rom(key_echo());
+    let (bytes, _) = record(&mut recorder);
+
+    let movie = Movie::from_bytes(&bytes).unwrap();
+    assert_eq!(movie.seed, 5);
+    assert_eq!(movie.frames, 40);
+    assert_eq!(movie.rom_hash, Movie::rom_hash(&key_echo()));
+    assert_eq!(movie.events.len(), 8);
+    assert_eq!((movie.events[0].frame, movie.events[0].key, movie.events[0].pressed), (3, 0x1, true));
+}
+
+#[test]
+fn movie_records_every_quirk() {
+    let mut recorder = Emulator::with_quirks(&Quirks::chip48());
+    recorder.load_rom(key_echo());
+    let (bytes, _) = record(&mut recorder);
+
+    assert_eq!(Movie::from_bytes(&bytes).unwrap().quirks, Quirks::chip48());
+}
+
+#[test]
+fn movie_requires_matching_rom() {
+    let mut recorder = Emulator::new();
+    recorder.load_rom(key_echo());
+    let (movie, _) = record(&mut recorder);
+
+    let mut player = Emulator::new();
+    player.load_rom(vec![0x12, 0x00]);
+    assert_eq!(player.play_movie(&movie), Err(MovieError::RomMismatch));
+
+    let mut corrupt = movie.clone();
+    corrupt[10] ^= 1;
+    assert_eq!(player.play_movie(&corrupt), Err(MovieError::ChecksumMismatch));
+}
+
+#[test]
+fn movie_records_timing() {
+    let mut recorder = Emulator::with_seed(9);
+    recorder.set_quirks(&Quirks::vip());
+    recorder.set_timing(Timing::Vip);
+    recorder.load_rom(key_echo());
+    let (movie, expected) = record(&mut recorder);
+    assert_eq!(Movie::from_bytes(&movie).unwrap().timing, Timing::Vip);
+
+    let mut player = Emulator::new();
+    player.load_rom(key_echo());
+    player.play_movie(&movie).unwrap();
+    while player.is_replaying() {
+        player.tick_frame().unwrap();
+    }
+
+    assert_eq!(player.timing(), Timing::Vip);
+    assert_eq!(player.display_out(), expected);
+}
+
+#[test]
+fn movie_records_speed() {
+    // at one instruction a frame the last key's glyph isn't drawn by the end
+    let mut recorder = Emulator::with_seed(9);
+    recorder.set_config(&EmulatorConfig { instructions_per_frame: 1, timer_frequency: 60 });
+    recorder.load_rom(key_echo());
+    let (movie, expected) = record(&mut recorder);
+    let parsed = Movie::from_bytes(&movie).unwrap();
+    assert_eq!((parsed.timing, parsed.instructions_per_frame), (Timing::Fixed, 1));
+
+    let mut full_speed = Emulator::with_seed(9);
+    full_speed.load_rom(key_echo());
+    assert_ne!(record(&mut full_speed).1, expected);
+
+    let mut player = Emulator::new();
+    player.load_rom(key_echo());
+    player.play_movie(&movie).unwrap();
+    while player.is_replaying() {
+        player.tick_frame().unwrap();
+    }
+
+    assert_eq!(player.config().instructions_per_frame, 1);
+    assert_eq!(player.display_out(), expected);
+}
+
+#[test]
+fn rewinding_or_loading_while_recording_still_replays() {
+    let mut recorder = Emulator::with_seed(3);
+    recorder.load_rom(key_echo());
+    recorder.start_recording();
+    recorder.enable_rewind(1, 100);
+
+    // key 2 is held when the state is saved and again once it's loaded
+    let mut saved = Vec::new();
+    for step in 0 .. 60 {
+        match step {
+            5 | 22 | 36 => recorder.key_change(step / 10, true),
+            7 | 24 | 38 => recorder.key_change(step / 10, false),
+            11 => recorder.key_change(0x2, true),
+            12 => saved = recorder.save_state(),
+            14 | 33 => recorder.key_change(0x2, false),
+            20 => assert!(recorder.rewind(6) >= 6),
+            30 => recorder.load_state(&saved).unwrap(),
+            _ => {}
+        }
+        recorder.tick_frame().unwrap();
+    }
+    let frames = recorder.frame_count();
+    let expected = recorder.display_out();
+    let movie = recorder.stop_recording();
+
+    let mut player = Emulator::new();
+    player.load_rom(key_echo());
+    player.play_movie(&movie).unwrap();
+    while player.is_replaying() {
+        player.tick_frame().unwrap();
+    }
+
+    assert_eq!(player.frame_count(), frames);
+    assert_eq!(player.display_out(), expected);
+}
+
+#[test]
+fn movie_with_bad_ram_size_is_rejected() {
+    let mut recorder = Emulator::new();
+    recorder.load_rom(key_echo());
+    let (bytes, _) = record(&mut recorder);
+
+    let mut player = Emulator::new();
+    player.load_rom(key_echo());
+    for ram_size in [0, 16, 0x800, u32::MAX] {
+        let mut movie = Movie::from_bytes(&bytes).unwrap();
+        movie.ram_size = ram_size;
+        assert_eq!(player.play_movie(&movie.to_bytes()), Err(MovieError::Invalid), "{}", ram_size);
+    }
+
+    let mut movie = Movie::from_bytes(&bytes).unwrap();
+    movie.ram_size = 0x10000;
+    assert_eq!(player.play_movie(&movie.to_bytes()), Ok(()));
+    assert_eq!(player.ram_size(), 0x10000);
+}
+
+#[test]
+fn other_movie_versions_are_rejected() {
+    let mut recorder = Emulator::new();
+    recorder.load_rom(key_echo());
+    let (bytes, _) = record(&mut recorder);
+    assert_eq!(bytes[4 .. 6], MOVIE_VERSION.to_le_bytes());
+
+    for version in [0u16, 2, 3, 4] {
+        let mut other = bytes[.. bytes.len() - 4].to_vec();
+        other[4 .. 6].copy_from_slice(&version.to_le_bytes());
+        let crc = state::crc32(&other);
+        other.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(Movie::from_bytes(&other).err(), Some(MovieError::UnsupportedVersion));
+    }
+}
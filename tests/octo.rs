@@ -0,0 +1,97 @@
+use skylark::emu::{ octo, Emulator };
+use std::fs;
+
+fn run(file: &str, frames: u32) -> Emulator {
+    let source = fs::read_to_string(file).unwrap();
+    let program = octo::compile(file, &source).unwrap();
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(program.bytes);
+    for _ in 0 .. frames {
+        emulator.tick_frame().unwrap();
+    }
+    emulator
+}
+
+// the top left of the screen, # for lit pixels
+fn screen(emulator: &Emulator, width: u32, height: u32) -> Vec<String> {
+    (0 .. height)
+        .map(|y| (0 .. width).map(|x| if emulator.pixel(x, y) != 0 { '#' } else { '.' }).collect())
+        .collect()
+}
+
+fn bytes_of(source: &str) -> Vec<u8> {
+    octo::compile("test.8o", source).unwrap().bytes
+}
+
+#[test]
+fn instructions() {
+    let source = "
+        : main
+            v0 := 5  v1 += v0  v2 -= 1  v3 =- v4  v5 := random 0x0F
+            i := hex v1  i += v2  delay := v0  buzzer := v0  v6 := key
+            save v3  load v2 - v4  sprite v0 v1 0  return
+    ";
+
+    assert_eq!(bytes_of(source), [
+        0x12, 0x02,
+        0x60, 0x05, 0x81, 0x04, 0x72, 0xFF, 0x83, 0x47, 0xC5, 0x0F,
+        0xF1, 0x29, 0xF2, 0x1E, 0xF0, 0x15, 0xF0, 0x18, 0xF6, 0x0A,
+        0xF3, 0x55, 0x52, 0x43, 0xD0, 0x10, 0x00, 0xEE
+    ]);
+}
+
+#[test]
+fn forward_references_are_patched() {
+    let source = "
+        : main
+            i := long data
+            jump end
+        : end
+            loop again
+        : data
+            0xAA
+    ";
+
+    assert_eq!(bytes_of(source), [0x12, 0x02, 0xF0, 0x00, 0x02, 0x0A, 0x12, 0x08, 0x12, 0x08, 0xAA]);
+}
+
+#[test]
+fn errors_point_at_the_line() {
+    let cases = [
+        (": main\n  jump nowhere", 2, "nowhere is not defined"),
+        (": main\n\n  v0 := 300", 3, "value 300 is out of range"),
+        ("v0 := 1", 1, "program has no main label"),
+        (": main\n  loop\n  v0 := 1", 3, "loop without again"),
+        (": main\n  else", 2, "else without if")
+    ];
+
+    for &(source, line, message) in &cases {
+        let err = octo::compile("bad.8o", source).unwrap_err();
+        assert_eq!((err.line, err.message.as_str()), (line, message), "{}", source);
+    }
+}
+
+#[test]
+fn digits_sample() {
+    let emulator = run("roms/octo/digits.8o", 10);
+
+    assert_eq!(screen(&emulator, 41, 7), [
+        ".........................................",
+        ".####...#..####.####.#..#.####.####.####.",
+        ".#..#..##.....#....#.#..#.#....#.......#.",
+        ".#..#...#..####.####.####.####.####...#..",
+        ".#..#...#..#.......#....#....#.#..#..#...",
+        ".####..###.####.####....#.####.####..#...",
+        "........................................."
+    ]);
+}
+
+#[test]
+fn features_sample() {
+    let emulator = run("roms/octo/features.8o", 10);
+
+    // nine blocks means every check passed
+    let row = "###...".repeat(9);
+    assert_eq!(screen(&emulator, 54, 4), [row.as_str(), row.as_str(), row.as_str(), &".".repeat(54)]);
+}
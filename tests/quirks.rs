@@ -0,0 +1,83 @@
+use skylark::emu::{ Emulator, Quirks };
+
+mod common;
+
+fn emulator_with(quirks: Quirks, rom: &[u8]) -> Emulator {
+    common::load(Emulator::with_quirks(&quirks), rom)
+}
+
+fn row(emulator: &Emulator, y: usize) -> String {
+    emulator.display_out().lines().nth(y).unwrap().to_string()
+}
+
+// V0 = 62, V1 = 0, I = glyph 0, draw, loop
+const EDGE_SPRITE: [u8; 10] = [0x60, 0x3E, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x15, 0x12, 0x08];
+
+#[test]
+fn sprites_wrap_by_default() {
+    let mut emulator = emulator_with(Quirks::new(), &EDGE_SPRITE);
+    emulator.tick_frame().unwrap();
+
+    assert!(row(&emulator, 0).starts_with('◼'));
+}
+
+#[test]
+fn sprites_clip_with_quirk() {
+    let mut emulator = emulator_with(Quirks::vip(), &EDGE_SPRITE);
+    emulator.tick_frame().unwrap();
+
+    assert!(row(&emulator, 0).starts_with('◻'));
+    assert!(row(&emulator, 0).ends_with("◻◼◼"));
+}
+
+// draw glyph 0 every 8 pixels, forever
+const DRAW_LOOP: [u8; 12] = [0x60, 0x00, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x15, 0x70, 0x08, 0x12, 0x06];
+
+#[test]
+fn display_wait_limits_one_sprite_per_frame() {
+    let mut emulator = emulator_with(Quirks::vip(), &DRAW_LOOP);
+    emulator.tick_frame().unwrap();
+    assert_eq!(row(&emulator, 0).matches('◼').count(), 4);
+
+    emulator.tick_frame().unwrap();
+    assert_eq!(row(&emulator, 0).matches('◼').count(), 8);
+
+    let mut emulator = emulator_with(Quirks::new(), &DRAW_LOOP);
+    emulator.tick_frame().unwrap();
+    assert!(row(&emulator, 0).matches('◼').count() > 8);
+}
+
+#[test]
+fn jump_uses_vx_with_quirk() {
+    // V0 = 0, V2 = 4, B208: lands on 0x208 (V0) or 0x20C (V2)
+    // 0x208: draw glyph 0 at (0, 0), 0x20C: draw glyph 0 at (V2, 0)
+    let rom = [
+        0x60, 0x00, 0x62, 0x04, 0xA0, 0x00, 0xB2, 0x08,
+        0xD0, 0x05, 0x12, 0x0A,
+        0xD2, 0x05, 0x12, 0x0E
+    ];
+
+    let mut emulator = emulator_with(Quirks::new(), &rom);
+    emulator.tick_frame().unwrap();
+    assert!(row(&emulator, 0).starts_with("◼◼◼◼◻"));
+
+    let mut emulator = emulator_with(Quirks::schip(), &rom);
+    emulator.tick_frame().unwrap();
+    assert!(row(&emulator, 0).starts_with("◻◻◻◻◼◼◼◼"));
+}
+
+#[test]
+fn register_dump_leaves_i_per_platform() {
+    // I = 0x300, dump V0..V2, loop
+    let rom = [0xA3, 0x00, 0xF2, 0x55, 0x12, 0x04];
+    let i_after = |quirks| {
+        let mut emulator = emulator_with(quirks, &rom);
+        emulator.tick_frame().unwrap();
+        emulator.index()
+    };
+
+    assert_eq!(i_after(Quirks::new()), 0x300);
+    assert_eq!(i_after(Quirks::vip()), 0x303);
+    assert_eq!(i_after(Quirks::chip48()), 0x302);
+    assert_eq!(i_after(Quirks::schip()), 0x300);
+}
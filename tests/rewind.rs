@@ -0,0 +1,88 @@
+use std::fs;
+use skylark::emu::{ Emulator, Quirks };
+use skylark::emu::rewind::{ apply_delta, encode_delta };
+
+mod common;
+
+fn run_recording(emulator: &mut Emulator, frames: usize) -> Vec<String> {
+    (0 .. frames)
+        .map(|_| {
+            emulator.tick_frame().unwrap();
+            emulator.display_out()
+        })
+        .collect()
+}
+
+fn scrolling_logo() -> Emulator {
+    common::emulator_with(&fs::read("roms/scrolling_logo.ch8").unwrap())
+}
+
+#[test]
+fn rewind_restores_earlier_frame() {
+    let mut emulator = scrolling_logo();
+    emulator.enable_rewind(1, 120);
+    let frames = run_recording(&mut emulator, 60);
+
+    assert_eq!(emulator.rewind(20), 20);
+    assert_eq!(emulator.display_out(), frames[39]);
+
+    // replaying from there reproduces the original run
+    let replay = run_recording(&mut emulator, 20);
+    assert_eq!(replay[..], frames[40 ..]);
+}
+
+#[test]
+fn rewind_keeps_non_default_quirks() {
+    let rom = fs::read("roms/scrolling_logo.ch8").unwrap();
+    let mut emulator = common::load(Emulator::with_quirks(&Quirks::chip48()), &rom);
+    emulator.enable_rewind(1, 120);
+    let frames = run_recording(&mut emulator, 60);
+
+    assert_eq!(emulator.rewind(20), 20);
+    assert_eq!(emulator.quirks(), Quirks::chip48());
+    let replay = run_recording(&mut emulator, 20);
+    assert_eq!(replay[..], frames[40 ..]);
+}
+
+#[test]
+fn rewind_rounds_to_snapshot_interval() {
+    let mut emulator = scrolling_logo();
+    emulator.enable_rewind(5, 100);
+    let frames = run_recording(&mut emulator, 32);
+
+    // 2 frames since the last snapshot at frame 30, then back 5 more
+    assert_eq!(emulator.rewind(7), 7);
+    assert_eq!(emulator.display_out(), frames[24]);
+
+    assert_eq!(emulator.rewind(3), 5);
+    assert_eq!(emulator.display_out(), frames[19]);
+}
+
+#[test]
+fn capacity_bounds_history() {
+    let mut emulator = scrolling_logo();
+    emulator.enable_rewind(1, 10);
+    run_recording(&mut emulator, 50);
+
+    assert_eq!(emulator.rewind_available(), 9);
+    assert_eq!(emulator.rewind(100), 9);
+    assert_eq!(emulator.rewind_available(), 0);
+}
+
+#[test]
+fn rewind_is_noop_when_disabled() {
+    let mut emulator = scrolling_logo();
+    run_recording(&mut emulator, 5);
+
+    assert_eq!(emulator.rewind(1), 0);
+}
+
+#[test]
+fn delta_round_trips_different_lengths() {
+    let a = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let b = vec![1, 2, 9, 4, 5, 6, 0, 8, 10, 11];
+
+    assert_eq!(apply_delta(&a, &encode_delta(&a, &b)), b);
+    assert_eq!(apply_delta(&b, &encode_delta(&b, &a)), a);
+    assert_eq!(encode_delta(&a, &a).len(), 1);
+}
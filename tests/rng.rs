@@ -0,0 +1,66 @@
+use skylark::emu::{ Emulator, RngKind };
+use skylark::emu::rng::Rng;
+
+// draw 32 random bytes down the left edge: V1 = y, V0 = rand, [0x300] = V0, draw, y++
+const RANDOM_COLUMN: [u8; 14] = [
+    0xA3, 0x00, 0x61, 0x00, 0xC0, 0xFF, 0xF0, 0x55, 0xD2, 0x11, 0x71, 0x01, 0x12, 0x04
+];
+
+fn run(mut emulator: Emulator) -> String {
+    emulator.load_rom(RANDOM_COLUMN.to_vec());
+    for _ in 0 .. 20 {
+        emulator.tick_frame().unwrap();
+    }
+    emulator.display_out()
+}
+
+#[test]
+fn same_seed_same_run() {
+    assert_eq!(run(Emulator::with_seed(42)), run(Emulator::with_seed(42)));
+    assert_ne!(run(Emulator::with_seed(42)), run(Emulator::with_seed(43)));
+}
+
+#[test]
+fn vip_algorithm_is_deterministic() {
+    let vip = |seed| {
+        let mut emulator = Emulator::new();
+        emulator.set_rng(RngKind::Vip, seed);
+        run(emulator)
+    };
+
+    assert_eq!(vip(7), vip(7));
+    assert_ne!(vip(7), run(Emulator::with_seed(7)));
+}
+
+#[test]
+fn every_byte_value_is_produced() {
+    let mut rng = Rng::new(RngKind::Xorshift, 1);
+    let mut seen = [false; 256];
+    for _ in 0 .. 10_000 {
+        seen[rng.next_byte(&[]) as usize] = true;
+    }
+
+    assert!(seen.iter().all(|&s| s));
+}
+
+#[test]
+fn rng_is_part_of_save_state() {
+    let mut emulator = Emulator::with_seed(9);
+    emulator.load_rom(RANDOM_COLUMN.to_vec());
+    emulator.tick_frame().unwrap();
+    let state = emulator.save_state();
+
+    for _ in 0 .. 10 {
+        emulator.tick_frame().unwrap();
+    }
+    let expected = emulator.display_out();
+
+    let mut restored = Emulator::with_seed(1234);
+    restored.load_state(&state).unwrap();
+    for _ in 0 .. 10 {
+        restored.tick_frame().unwrap();
+    }
+
+    assert_eq!(restored.seed(), 9);
+    assert_eq!(restored.display_out(), expected);
+}
@@ -0,0 +1,89 @@
+use skylark::emu::Emulator;
+
+mod common;
+use common::emulator_with;
+
+fn lit(emulator: &Emulator) -> Vec<(usize, usize)> {
+    emulator.display_out().lines().enumerate()
+        .flat_map(|(y, line)| {
+            line.chars().enumerate().filter(|&(_, c)| c == '◼').map(move |(x, _)| (x, y)).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn hires_changes_dimensions() {
+    // hires, loop, then lores on the second pass
+    let mut emulator = emulator_with(&[0x00, 0xFF, 0x12, 0x02]);
+    assert_eq!((emulator.width(), emulator.height()), (64, 32));
+
+    emulator.tick_frame().unwrap();
+    assert_eq!((emulator.width(), emulator.height()), (128, 64));
+    assert_eq!(emulator.display_out().lines().count(), 64);
+    assert_eq!(emulator.display_out().lines().next().unwrap().chars().count(), 128);
+
+    let mut emulator = emulator_with(&[0x00, 0xFF, 0x00, 0xFE, 0x12, 0x04]);
+    emulator.tick_frame().unwrap();
+    assert_eq!((emulator.width(), emulator.height()), (64, 32));
+}
+
+#[test]
+fn big_sprite_draws_16x16() {
+    // I = 0x208, DXY0 at (0, 0), loop; sprite is a single pixel in each corner
+    let mut rom = vec![0xA2, 0x08, 0xD0, 0x00, 0x12, 0x04, 0x00, 0x00];
+    let mut sprite = vec![0u8; 32];
+    sprite[0] = 0x80;
+    sprite[31] = 0x01;
+    rom.extend(sprite);
+
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(lit(&emulator), vec![(0, 0), (15, 15)]);
+}
+
+#[test]
+fn scrolls_move_pixels() {
+    // I = 0x210 (single pixel), draw at (0, 0), scroll down 3, right 4, left 4, right 4, loop
+    let rom = [0xA2, 0x10, 0xD0, 0x01, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFB, 0x12, 0x0C, 0x00, 0x00, 0x80];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(lit(&emulator), vec![(4, 3)]);
+}
+
+#[test]
+fn big_font_glyph() {
+    // V0 = 1, I = big 1, draw 10 rows at (0, 0), loop
+    let mut emulator = emulator_with(&[0x60, 0x01, 0xF0, 0x30, 0x61, 0x00, 0xD1, 0x1A, 0x12, 0x08]);
+    emulator.tick_frame().unwrap();
+
+    let first = emulator.display_out().lines().next().unwrap().chars().take(8).collect::<String>();
+    let last = emulator.display_out().lines().nth(9).unwrap().chars().take(8).collect::<String>();
+    assert_eq!(first, "◻◻◻◼◼◻◻◻");
+    assert_eq!(last, "◼◼◼◼◼◼◼◼");
+}
+
+#[test]
+fn exit_halts_cpu() {
+    // exit, then an invalid opcode that must never run
+    let mut emulator = emulator_with(&[0x00, 0xFD, 0xFF, 0xFF]);
+    emulator.tick_frame().unwrap();
+    emulator.tick_frame().unwrap();
+
+    assert!(emulator.is_halted());
+}
+
+#[test]
+fn rpl_flags_round_trip() {
+    // V0 = 1, V1 = 2, save V0..V1, clear them, restore, draw glyph V1 at (V0, V0), loop
+    let rom = [
+        0x60, 0x01, 0x61, 0x02, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        0xF1, 0x29, 0xD0, 0x01, 0x12, 0x10
+    ];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    // top row of glyph 2 is 0xf0
+    assert_eq!(lit(&emulator), vec![(1, 1), (2, 1), (3, 1), (4, 1)]);
+}
@@ -0,0 +1,109 @@
+use skylark::emu::snapshot::{ self, Snapshot };
+use skylark::emu::headless::{ StopReason, Until };
+use skylark::emu::{ asm, octo, Emulator, Headless };
+use std::fs;
+
+// .ch8 roms as they are, assembler and Octo sources built first
+fn rom(path: &str) -> Vec<u8> {
+    if path.ends_with(".8o") {
+        octo::compile(path, &fs::read_to_string(path).unwrap()).unwrap().bytes
+    } else if path.ends_with(".src") {
+        asm::assemble_file(path).unwrap().bytes
+    } else {
+        fs::read(path).unwrap()
+    }
+}
+
+// every rom under roms/ that draws something and isn't another's source, the frame to
+// capture and the golden file under tests/golden. SKYLARK_BLESS=1 cargo test --test
+// snapshot rewrites them.
+const ROMS: [(&str, u32, &str); 10] = [
+    ("BLINKY.ch8", 400, "blinky.txt"),
+    ("MISSILE.ch8", 120, "missile.txt"),
+    ("PONG.ch8", 120, "pong.txt"),
+    ("TANK.ch8", 120, "tank.txt"),
+    ("TETRIS.ch8", 120, "tetris.txt"),
+    ("font_test.ch8", 60, "font_test.txt"),
+    ("scrolling_logo.ch8", 90, "scrolling_logo.pbm"),
+    // the source expects VF = 0 after a subtraction without borrow, so it stops at its
+    // first check before drawing anything
+    ("vf_test.ch8.src", 10, "vf_test.txt"),
+    ("octo/digits.8o", 10, "digits.txt"),
+    ("octo/features.8o", 10, "features.txt")
+];
+
+#[test]
+fn roms_match_golden_snapshots() {
+    let failures: Vec<String> = ROMS.iter()
+        .filter_map(|&(name, frames, golden)| {
+            let outcome = Headless::new(rom(&format!("roms/{}", name))).run(frames, &[]);
+            snapshot::check(format!("tests/golden/{}", golden), &outcome.display).err()
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// keyboard_test clears the screen again as soon as it has drawn the key, so stop on
+// the jump back to the top instead of at the end of a frame
+#[test]
+fn keyboard_test_draws_the_key() {
+    let mut headless = Headless::new(rom("roms/keyboard_test.ch8.src"));
+    headless.key(5, 0xa, true);
+    let outcome = headless.run(30, &[Until::Pc(0x20c)]);
+
+    assert_eq!(outcome.reason, StopReason::Until(Until::Pc(0x20c)));
+    snapshot::check("tests/golden/keyboard_test.txt", &outcome.display).unwrap();
+}
+
+// rand_test draws nothing either way, so also check it reached the success loop, under
+// a fixed seed so two equal draws can't fail it
+#[test]
+fn rand_test_passes() {
+    let mut headless = Headless::with_emulator(Emulator::with_seed(1), rom("roms/rand_test.ch8.src"));
+    let outcome = headless.run(10, &[Until::Pc(0x208)]);
+
+    assert_eq!(outcome.reason, StopReason::Until(Until::Pc(0x208)));
+    snapshot::check("tests/golden/rand_test.txt", &outcome.display).unwrap();
+}
+
+#[test]
+fn text_matches_display_out() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(rom("roms/font_test.ch8"));
+    emulator.tick_frame().unwrap();
+
+    let snapshot = Snapshot::of(emulator.display());
+    assert_eq!(snapshot.to_text(), emulator.display_out());
+    assert_eq!(Snapshot::from_text(&emulator.display_out()), Ok(snapshot.clone()));
+    assert_eq!(Snapshot::from_pbm(snapshot.to_pbm().as_bytes()), Ok(snapshot));
+}
+
+#[test]
+fn pbm_formats() {
+    let plain = Snapshot::from_pbm(b"P1\n# a comment\n3 2\n1 0 1\n0 1 0\n").unwrap();
+    assert_eq!(plain, Snapshot::from_text("#.#\n.#.").unwrap());
+
+    // rows are padded to whole bytes
+    let raw = Snapshot::from_pbm(b"P4\n3 2\n\xa0\x40").unwrap();
+    assert_eq!(raw, plain);
+
+    assert!(Snapshot::from_pbm(b"P4\n3 2\n\xa0").is_err());
+    assert!(Snapshot::from_pbm(b"P2\n3 2\n").is_err());
+}
+
+#[test]
+fn diff_shows_the_differing_rows() {
+    let golden = Snapshot::from_text("....\n.##.\n....").unwrap();
+    assert_eq!(golden.diff(&golden), None);
+
+    let actual = Snapshot::from_text("....\n.#.#\n....").unwrap();
+    assert_eq!(golden.diff(&actual).unwrap(), "\
+2 pixels differ, the first at (2, 1)
++ lit only in the actual display, - lit only in the golden one
+  1 .#-+
+");
+
+    let hires = Snapshot::from_text("..").unwrap();
+    assert_eq!(golden.diff(&hires).unwrap(), "expected a 4x3 display, got 2x1");
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use skylark::emu::{ state, Emulator, Quirks, StateError };
+
+mod common;
+
+fn emulator_with_rom(path: &str) -> Emulator {
+    common::emulator_with(&fs::read(path).unwrap())
+}
+
+#[test]
+fn restore_resumes_identically() {
+    let mut emulator = emulator_with_rom("roms/scrolling_logo.ch8");
+    for _ in 0 .. 30 {
+        emulator.tick_frame().unwrap();
+    }
+
+    let state = emulator.save_state();
+    for _ in 0 .. 30 {
+        emulator.tick_frame().unwrap();
+    }
+    let expected = emulator.display_out();
+
+    let mut restored = Emulator::new();
+    restored.load_state(&state).unwrap();
+    for _ in 0 .. 30 {
+        restored.tick_frame().unwrap();
+    }
+
+    assert_eq!(restored.display_out(), expected);
+    assert_eq!(restored.save_state(), emulator.save_state());
+}
+
+#[test]
+fn save_load_save_is_stable() {
+    let mut emulator = emulator_with_rom("roms/font_test.ch8");
+    emulator.tick_frame().unwrap();
+    emulator.key_change(5, true);
+
+    let state = emulator.save_state();
+    let mut restored = Emulator::new();
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn quirks_survive_a_round_trip() {
+    let mut emulator = common::load(Emulator::with_quirks(&Quirks::chip48()), &fs::read("roms/font_test.ch8").unwrap());
+    emulator.tick_frame().unwrap();
+    let state = emulator.save_state();
+
+    let mut restored = Emulator::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.quirks(), Quirks::chip48());
+    assert!(restored.quirks().load_store_inc_i_by_x);
+}
+
+#[test]
+fn corrupted_state_is_rejected() {
+    let emulator = emulator_with_rom("roms/font_test.ch8");
+    let mut state = emulator.save_state();
+    state[100] ^= 0xff;
+
+    let mut target = Emulator::new();
+    assert_eq!(target.load_state(&state), Err(StateError::ChecksumMismatch));
+    assert_eq!(target.load_state(b"nope"), Err(StateError::BadMagic));
+    assert_eq!(target.load_state(b"SKY8"), Err(StateError::Truncated));
+}
+
+#[test]
+fn failed_load_leaves_emulator_untouched() {
+    let mut emulator = emulator_with_rom("roms/font_test.ch8");
+    emulator.tick_frame().unwrap();
+    let before = emulator.save_state();
+
+    let mut bad = before.clone();
+    bad[4] = 0xff;
+    assert!(emulator.load_state(&bad).is_err());
+
+    assert_eq!(emulator.save_state(), before);
+}
+
+#[test]
+fn state_with_bad_ram_size_is_rejected() {
+    let emulator = emulator_with_rom("roms/font_test.ch8");
+    let state = emulator.save_state();
+
+    // magic, version and memory policy, then the length prefixed ram
+    let ram_end = 4 + 2 + 1 + 4 + 0x1000;
+    let with_ram = |size: usize| {
+        let mut crafted = state[.. 7].to_vec();
+        crafted.extend_from_slice(&(size as u32).to_le_bytes());
+        crafted.extend(vec![0; size]);
+        crafted.extend_from_slice(&state[ram_end .. state.len() - 4]);
+        let crc = state::crc32(&crafted);
+        crafted.extend_from_slice(&crc.to_le_bytes());
+        crafted
+    };
+
+    let mut target = Emulator::new();
+    assert_eq!(target.load_state(&with_ram(16)), Err(StateError::Invalid));
+    assert_eq!(target.load_state(&with_ram(0x800)), Err(StateError::Invalid));
+    assert_eq!(target.load_state(&with_ram(0x1000)), Ok(()));
+}
+
+#[test]
+fn other_versions_are_rejected() {
+    let state = emulator_with_rom("roms/font_test.ch8").save_state();
+    assert_eq!(state[4 .. 6], state::STATE_VERSION.to_le_bytes());
+
+    let mut target = Emulator::new();
+    for version in [0u16, 2, 3] {
+        let mut other = state[.. state.len() - 4].to_vec();
+        other[4 .. 6].copy_from_slice(&version.to_le_bytes());
+        let crc = state::crc32(&other);
+        other.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(target.load_state(&other), Err(StateError::UnsupportedVersion));
+    }
+}
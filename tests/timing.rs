@@ -0,0 +1,71 @@
+use skylark::emu::{ timing, Emulator, Instruction, Quirks, Timing };
+
+mod common;
+
+fn emulator_with(source: &str, timing: Timing) -> Emulator {
+    let mut emulator = Emulator::with_quirks(&Quirks::vip());
+    emulator.set_timing(timing);
+    common::load(emulator, &common::assemble(source))
+}
+
+#[test]
+fn instructions_cost_vip_machine_cycles() {
+    let v = [0; 16];
+    assert_eq!(timing::vip_cycles(&Instruction::LoadImm(0, 1), &v), 46);
+    assert_eq!(timing::vip_cycles(&Instruction::Add(0, 1), &v), 84);
+    assert_eq!(timing::vip_cycles(&Instruction::Store(0xf), &v), 40 + 14 + 14 * 16);
+
+    // BCD subtracts once per unit of each digit, unaligned sprites are shifted into place
+    let mut v = [0; 16];
+    v[2] = 255;
+    assert_eq!(timing::vip_cycles(&Instruction::Bcd(2), &v), 40 + 84 + 16 * 12);
+    assert!(timing::vip_cycles(&Instruction::Draw(2, 0, 5), &v) > timing::vip_cycles(&Instruction::Draw(0, 0, 5), &v));
+
+    assert_eq!(timing::VIP_CYCLES_PER_FRAME, 3668);
+}
+
+#[test]
+fn frames_run_against_a_cycle_budget() {
+    let counter = "start: ADD V0, 1\nJP start";
+
+    let mut emulator = emulator_with(counter, Timing::Fixed);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 5);
+
+    // 50 cycles to add and 52 to jump, the 26th add takes the frame past its budget
+    let mut emulator = emulator_with(counter, Timing::Vip);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 26);
+
+    // expensive instructions get fewer per frame, and what one overruns comes off the next
+    let mut emulator = emulator_with("start: ADD V0, 1\nLD I, 0x300\nLD [I], VF\nJP start", Timing::Vip);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 7);
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.registers()[0], 13);
+}
+
+#[test]
+fn skips_taken_cost_more() {
+    // both loops are an add, a skip and a jump, 156 cycles when the skip is taken and 152
+    // when it isn't
+    let mut taken = emulator_with("start: ADD V1, 1\nSE V0, 0\nJP start\nJP start", Timing::Vip);
+    let mut not_taken = emulator_with("start: ADD V1, 1\nSE V0, 1\nJP start\nJP start", Timing::Vip);
+    taken.tick_frame().unwrap();
+    not_taken.tick_frame().unwrap();
+    assert_eq!((taken.registers()[1], not_taken.registers()[1]), (17, 18));
+}
+
+#[test]
+fn draws_still_wait_for_vblank() {
+    let mut emulator = emulator_with("start: DRW V0, V0, 5\nADD V1, 1\nJP start", Timing::Vip);
+    for _ in 0 .. 3 {
+        emulator.tick_frame().unwrap();
+    }
+    assert_eq!(emulator.registers()[1], 2);
+    assert_eq!(emulator.timing(), Timing::Vip);
+
+    // reset keeps the timing
+    emulator.reset();
+    assert_eq!(emulator.timing(), Timing::Vip);
+}
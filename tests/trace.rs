@@ -0,0 +1,109 @@
+use skylark::emu::OpcodeClass;
+use skylark::emu::trace::{ Change, Register };
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+mod common;
+use common::emulator_with_source;
+
+const LOOP: &str = "
+    start:
+        LD V0, 7
+        LD I, 0x300
+        LD DT, V0
+        ADD V1, V0
+        JP start
+";
+
+#[test]
+fn entries_show_registers_changed() {
+    let mut emulator = emulator_with_source(LOOP);
+    emulator.trace_to_buffer(100);
+    for _ in 0 .. 4 {
+        emulator.step().unwrap();
+    }
+
+    let entries = emulator.trace_entries();
+    assert_eq!(entries.len(), 4);
+    assert_eq!((entries[0].pc, entries[0].opcode, entries[0].text.as_str()), (0x200, 0x6007, "LD V0, 0x07"));
+    assert_eq!(entries[2].changes, [Change { register: Register::DT, before: 0, after: 7 }]);
+    assert_eq!(emulator.trace_log().lines().collect::<Vec<_>>(), [
+        "0x200  6007  LD V0, 0x07              V0 00->07",
+        "0x202  A300  LD I, 0x300              I 000->300",
+        "0x204  F015  LD DT, V0                DT 00->07",
+        "0x206  8104  ADD V1, V0               V1 00->07"
+    ]);
+}
+
+#[test]
+fn buffer_keeps_the_latest_entries() {
+    let mut emulator = emulator_with_source(LOOP);
+    emulator.trace_to_buffer(3);
+    emulator.tick_frame().unwrap();
+
+    // 10 instructions a frame is twice round the loop
+    let pcs: Vec<u16> = emulator.trace_entries().iter().map(|e| e.pc).collect();
+    assert_eq!(pcs, [0x204, 0x206, 0x208]);
+
+    emulator.clear_trace();
+    assert!(emulator.trace_log().is_empty());
+
+    emulator.stop_trace();
+    emulator.tick_frame().unwrap();
+    assert!(!emulator.is_tracing());
+    assert!(emulator.trace_entries().is_empty());
+}
+
+#[test]
+fn filters_by_range_and_class() {
+    let mut emulator = emulator_with_source(LOOP);
+    emulator.trace_to_buffer(100);
+    emulator.set_trace_range(0x202, 0x206);
+    emulator.tick_frame().unwrap();
+
+    let pcs: Vec<u16> = emulator.trace_entries().iter().map(|e| e.pc).collect();
+    assert_eq!(pcs, [0x202, 0x204, 0x202, 0x204]);
+
+    emulator.clear_trace();
+    emulator.clear_trace_filter();
+    emulator.set_trace_classes(1 << OpcodeClass::Flow as u32 | 1 << OpcodeClass::Timer as u32);
+    emulator.tick_frame().unwrap();
+
+    let classes: Vec<OpcodeClass> = emulator.trace_entries().iter().map(|e| e.class).collect();
+    assert_eq!(classes, [OpcodeClass::Timer, OpcodeClass::Flow, OpcodeClass::Timer, OpcodeClass::Flow]);
+}
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_gets_a_line_per_instruction() {
+    let out = Shared::default();
+    let mut emulator = emulator_with_source(LOOP);
+    emulator.trace_to_writer(Box::new(out.clone()));
+    emulator.tick_frame().unwrap();
+
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    assert_eq!(text.lines().count(), 10);
+    assert!(text.starts_with("0x200  6007  LD V0, 0x07"));
+}
+
+#[test]
+fn console_trace_runs_outside_the_browser() {
+    let mut emulator = emulator_with_source(LOOP);
+    emulator.trace_to_console();
+    emulator.tick_frame().unwrap();
+    assert_eq!(emulator.pc(), 0x200);
+}
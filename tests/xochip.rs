@@ -0,0 +1,84 @@
+use skylark::emu::{ Emulator, ErrorKind };
+
+mod common;
+
+fn emulator_with(rom: &[u8]) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.set_extended_memory(true);
+    common::load(emulator, rom)
+}
+
+#[test]
+fn long_load_reaches_past_4k() {
+    // I = 0x1000, draw one row at (0, 0), loop; the sprite byte lives at 0x1000
+    let mut rom = vec![0xF0, 0x00, 0x10, 0x00, 0xD0, 0x01, 0x12, 0x06];
+    rom.resize(0x1000 - 0x200, 0);
+    rom.push(0xC0);
+
+    let mut emulator = emulator_with(&rom);
+    assert_eq!(emulator.ram_size(), 0x10000);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!((emulator.pixel(0, 0), emulator.pixel(1, 0), emulator.pixel(2, 0)), (1, 1, 0));
+}
+
+#[test]
+fn faults_past_64k_report_the_full_address() {
+    // I = 0xFFFE, dump V0..V3
+    let mut emulator = emulator_with(&[0xF0, 0x00, 0xFF, 0xFE, 0xF3, 0x55]);
+    let err = emulator.tick_frame().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::MemoryOutOfBounds);
+    assert_eq!(err.addr(), 0x10000);
+}
+
+#[test]
+fn skip_steps_over_long_load() {
+    // V0 == 0 so skip the long load, draw glyph 0 (I still 0), loop
+    let rom = [0x30, 0x00, 0xF0, 0x00, 0x00, 0x50, 0xD0, 0x01, 0x12, 0x08];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    // glyph 0 is 0xf0 on the first row
+    assert_eq!(emulator.pixel(3, 0), 1);
+    assert_eq!(emulator.pixel(4, 0), 0);
+}
+
+#[test]
+fn register_range_save_and_load() {
+    // V1 = 0x80, V2 = 0xC0, I = 0x300, save V1..V2, clear, load V2..V1 reversed into V2, V1
+    // then draw one row from V1 and V2 using I = 0x300 / 0x301
+    let rom = [
+        0x61, 0x80, 0x62, 0xC0, 0xA3, 0x00, 0x51, 0x22, 0x61, 0x00, 0x62, 0x00,
+        0x52, 0x13, // V2 = [0x300] = 0x80, V1 = [0x301] = 0xC0
+        0x63, 0x00, 0xF2, 0x55, // [0x300..] = V0, V1, V2
+        0xA3, 0x01, 0xD3, 0x31, // draw V1 (0xC0) at (0, 0)
+        0x12, 0x16
+    ];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+    emulator.tick_frame().unwrap();
+
+    assert_eq!((emulator.pixel(0, 0), emulator.pixel(1, 0), emulator.pixel(2, 0)), (1, 1, 0));
+}
+
+#[test]
+fn planes_give_palette_indices() {
+    // select both planes, I = sprite data (plane 0: 0xC0, plane 1: 0x60), draw at (0, 0), loop
+    let rom = [0xF3, 0x01, 0xA2, 0x0A, 0x60, 0x00, 0xD0, 0x01, 0x12, 0x08, 0xC0, 0x60];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    let row: Vec<u8> = (0 .. 4).map(|x| emulator.pixel(x, 0)).collect();
+    assert_eq!(row, vec![1, 3, 2, 0]);
+}
+
+#[test]
+fn clear_only_affects_selected_plane() {
+    // draw 0x80 on both planes, select plane 2 and clear, loop
+    let rom = [0xF3, 0x01, 0xA2, 0x0E, 0x60, 0x00, 0xD0, 0x01, 0xF2, 0x01, 0x00, 0xE0, 0x12, 0x0C, 0x80, 0x80];
+    let mut emulator = emulator_with(&rom);
+    emulator.tick_frame().unwrap();
+
+    assert_eq!(emulator.pixel(0, 0), 1);
+}
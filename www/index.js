@@ -1,14 +1,15 @@
-import { Emulator } from "skylark-wasm";
+import { Emulator, Quirks } from "skylark-wasm";
 import { memory } from "skylark-wasm/skylark_bg";
 
 const PIXEL_SIZE = 5; // px
-const WHITE = "#FFFFFF";
-const BLACK = "#000000";
+// palette index -> color, index 0 is the background (XO-CHIP has 2 bitplanes)
+const PALETTE = ["#000000", "#FFFFFF", "#AAAAAA", "#555555"];
 
 // Construct the universe, and get its width and height.
-const emulator = Emulator.new();
-const width = emulator.width();
-const height = emulator.height();
+const emulator = Emulator.with_seed(Date.now() % 0x100000000);
+// SUPER-CHIP roms can switch resolution, so these are refreshed every frame
+let width = emulator.width();
+let height = emulator.height();
 
 var romFile = null;
 var running = false;
@@ -16,16 +17,30 @@ var running = false;
 // Give the canvas room for all of our cells and a 1px border
 // around each of them.
 const canvas = document.getElementById("skylark-canvas");
-canvas.height = (PIXEL_SIZE ) * height;
-canvas.width = (PIXEL_SIZE) * width;
-
 const ctx = canvas.getContext('2d');
 
+const resizeCanvas = () => {
+    width = emulator.width();
+    height = emulator.height();
+
+    // keep the canvas the same size on screen in both resolutions
+    const pixelSize = PIXEL_SIZE * 64 / width;
+    canvas.height = pixelSize * height;
+    canvas.width = pixelSize * width;
+};
+
+resizeCanvas();
+
 const getIndex = (x, y) => {
     return x + y * width;
 };
 
 const drawPixels = () => {
+    if (width !== emulator.width() || height !== emulator.height()) {
+        resizeCanvas();
+    }
+
+    const pixelSize = canvas.width / width;
     const pixelsPtr = emulator.pixels();
     const pixels = new Uint8Array(memory.buffer, pixelsPtr, width * height);
 
@@ -35,15 +50,13 @@ const drawPixels = () => {
         for (let y = 0; y < height; y++) {
             const idx = getIndex(x, y);
 
-            ctx.fillStyle = pixels[idx]
-                ? WHITE
-                : BLACK;
+            ctx.fillStyle = PALETTE[pixels[idx]];
 
             ctx.fillRect(
-                x * (PIXEL_SIZE),
-                y * (PIXEL_SIZE),
-                PIXEL_SIZE,
-                PIXEL_SIZE
+                x * pixelSize,
+                y * pixelSize,
+                pixelSize,
+                pixelSize
             );
         }
     }
@@ -62,6 +75,99 @@ romInput.onchange = e => {
     fr.readAsArrayBuffer(e.target.files[0]);
 }
 
+// Register quirk toggles
+const QUIRK_NAMES = ["shift_vy", "load_store_inc_i", "load_store_inc_i_by_x", "jump_vx", "vf_reset", "clip_sprites", "display_wait"];
+const quirksPanel = document.getElementById("quirks");
+
+const renderQuirks = () => {
+    const quirks = emulator.quirks();
+    quirksPanel.innerHTML = "";
+
+    for (const name of QUIRK_NAMES) {
+        const label = document.createElement("label");
+        const checkbox = document.createElement("input");
+        checkbox.type = "checkbox";
+        checkbox.checked = quirks[name];
+        checkbox.onchange = () => {
+            const current = emulator.quirks();
+            current[name] = checkbox.checked;
+            emulator.set_quirks(current);
+        };
+
+        label.appendChild(checkbox);
+        label.appendChild(document.createTextNode(name));
+        quirksPanel.appendChild(label);
+    }
+};
+
+document.getElementById("quirks-preset").onchange = e => {
+    const presets = {
+        default: Quirks.new,
+        vip: Quirks.vip,
+        chip48: Quirks.chip48,
+        schip: Quirks.schip,
+        xochip: Quirks.xochip
+    };
+
+    emulator.set_quirks(presets[e.target.value]());
+    renderQuirks();
+};
+
+renderQuirks();
+
+// Register speed control
+const ipfInput = document.getElementById("ipf");
+const initialConfig = emulator.config();
+ipfInput.value = initialConfig.instructions_per_frame;
+initialConfig.free();
+ipfInput.onchange = () => {
+    const config = emulator.config();
+    config.instructions_per_frame = Number(ipfInput.value);
+    emulator.set_config(config);
+    config.free();
+};
+
+// Register quick save / quick load
+let quickSave = null;
+document.getElementById("save-state").onclick = () => {
+    quickSave = emulator.save_state();
+};
+document.getElementById("load-state").onclick = () => {
+    if (quickSave) {
+        emulator.load_state(quickSave);
+    }
+};
+
+// Register movie recording / playback
+const recordButton = document.getElementById("record-movie");
+recordButton.onclick = () => {
+    if (!emulator.is_recording()) {
+        emulator.start_recording();
+        recordButton.textContent = "Stop recording";
+        return;
+    }
+
+    const movie = emulator.stop_recording();
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(new Blob([movie]));
+    link.download = "recording.skym";
+    link.click();
+    recordButton.textContent = "Record movie";
+};
+
+document.getElementById("movie-input").onchange = e => {
+    const fr = new FileReader();
+    fr.onload = () => {
+        try {
+            emulator.play_movie(new Uint8Array(fr.result));
+        } catch (err) {
+            console.error("Could not play movie:", err);
+        }
+    };
+
+    fr.readAsArrayBuffer(e.target.files[0]);
+};
+
 const keyMapping = {};
 keyMapping[KeyboardEvent.DOM_VK_1] = 0x1;
 keyMapping[KeyboardEvent.DOM_VK_2] = 0x2;
@@ -84,6 +190,11 @@ keyMapping[KeyboardEvent.DOM_VK_V] = 0xF;
 document.addEventListener('keydown', e => onKeyChange(e, true));
 document.addEventListener('keyup', e => onKeyChange(e, false));
 
+// Hold backspace to rewind
+let rewinding = false;
+document.addEventListener('keydown', e => { if (e.key === "Backspace") rewinding = true; });
+document.addEventListener('keyup', e => { if (e.key === "Backspace") rewinding = false; });
+
 function onKeyChange(e, pressed) {
     let mappedKey = keyMapping[e.keyCode];
     if (mappedKey) {
@@ -91,10 +202,44 @@ function onKeyChange(e, pressed) {
     }
 }
 
-const renderLoop = () => {
+// Queue each frame's samples right after the previous frame's
+let audioCtx = null;
+let audioTime = 0;
+
+const playAudio = () => {
+    if (!audioCtx) {
+        audioCtx = new AudioContext();
+    }
+
+    const samples = emulator.audio_samples(audioCtx.sampleRate);
+    if (!emulator.is_beeping() && samples.every(s => s === 0)) {
+        return;
+    }
+
+    const buffer = audioCtx.createBuffer(1, samples.length, audioCtx.sampleRate);
+    buffer.copyToChannel(samples, 0);
+
+    const source = audioCtx.createBufferSource();
+    source.buffer = buffer;
+    source.connect(audioCtx.destination);
+
+    audioTime = Math.max(audioTime, audioCtx.currentTime);
+    source.start(audioTime);
+    audioTime += buffer.duration;
+};
+
+// time of the last animation frame, run_for is given the time since then
+let lastTime = null;
+
+const renderLoop = now => {
+    const elapsed = lastTime === null ? 0 : now - lastTime;
+    lastTime = now;
+
     if (!running){
         if (romFile){
             emulator.load_rom(romFile);
+            // a snapshot every 2 frames, 20 seconds worth
+            emulator.enable_rewind(2, 600);
             running = true;
         }
         else{
@@ -103,8 +248,28 @@ const renderLoop = () => {
         }
     }
 
-    emulator.tick_frame();
+    if (rewinding) {
+        emulator.rewind(2);
+        drawPixels();
+        requestAnimationFrame(renderLoop);
+        return;
+    }
+
+    // the display's refresh rate needn't be 60Hz, so run for the time that has passed
+    let frames;
+    try {
+        frames = emulator.run_for(elapsed);
+    } catch (e) {
+        // halt on execution faults rather than spinning on the bad instruction
+        drawPixels();
+        console.error(e.message());
+        return;
+    }
+
     drawPixels();
+    for (let i = 0; i < frames; i++) {
+        playAudio();
+    }
     requestAnimationFrame(renderLoop);
 };
 